@@ -1,7 +1,8 @@
 // Authentication module for Internet Computer canisters
-use candid::Principal;
+use candid::{CandidType, Principal};
 use ic_cdk;
-use std::cell::RefCell;
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
 
 // ═══════════════════════════════════════════════════════════════
@@ -18,6 +19,10 @@ pub enum AuthError {
     StorageError(String),
     #[error("Serialization error: {0}")]
     SerializationError(String),
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("Invalid delegation: {0}")]
+    InvalidDelegation(String),
 }
 
 pub type AuthResult<T> = Result<T, AuthError>;
@@ -26,6 +31,15 @@ pub type AuthResult<T> = Result<T, AuthError>;
 //  Storage Implementation
 // ═══════════════════════════════════════════════════════════════
 
+/// Backend that persists the set of authorized principals
+///
+/// `Auth` writes through to its backend on every change, so a stable-memory
+/// backend keeps the allowlist across upgrades without extra hooks.
+pub trait PrincipalStorage {
+    fn save_principals(&self, principals: &HashSet<Principal>) -> AuthResult<()>;
+    fn load_principals(&self) -> AuthResult<HashSet<Principal>>;
+}
+
 /// Simple in-memory storage for authorized principals
 pub struct AuthStorage {
     principals: RefCell<HashSet<Principal>>,
@@ -62,21 +76,96 @@ impl Default for AuthStorage {
     }
 }
 
+impl PrincipalStorage for AuthStorage {
+    fn save_principals(&self, principals: &HashSet<Principal>) -> AuthResult<()> {
+        AuthStorage::save_principals(self, principals)
+    }
+
+    fn load_principals(&self) -> AuthResult<HashSet<Principal>> {
+        AuthStorage::load_principals(self)
+    }
+}
+
+/// Stable-memory storage for authorized principals
+///
+/// Stores the allowlist as a single Candid blob in any `StorageRegistry`,
+/// typically a dedicated `StableBTreeMap`, so it survives upgrades.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::init]
+/// fn init() {
+///     let map = StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))));
+///     auth::init_with_storage(StableAuthStorage::new(map));
+///     auth::ensure_authorized(ic_cdk::api::msg_caller()).unwrap();
+/// }
+///
+/// #[ic_cdk::post_upgrade]
+/// fn post_upgrade() {
+///     let map = StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))));
+///     auth::init_with_storage(StableAuthStorage::new(map));
+/// }
+/// ```
+#[cfg(feature = "storage")]
+pub struct StableAuthStorage<R: crate::storage::StorageRegistry> {
+    registry: RefCell<R>,
+    key: String,
+}
+
+#[cfg(feature = "storage")]
+impl<R: crate::storage::StorageRegistry> StableAuthStorage<R> {
+    /// Default registry key the allowlist is stored under
+    pub const DEFAULT_KEY: &'static str = "auth_principals";
+
+    pub fn new(registry: R) -> Self {
+        Self::with_key(registry, Self::DEFAULT_KEY)
+    }
+
+    pub fn with_key(registry: R, key: impl Into<String>) -> Self {
+        Self {
+            registry: RefCell::new(registry),
+            key: key.into(),
+        }
+    }
+}
+
+#[cfg(feature = "storage")]
+impl<R: crate::storage::StorageRegistry> PrincipalStorage for StableAuthStorage<R> {
+    fn save_principals(&self, principals: &HashSet<Principal>) -> AuthResult<()> {
+        let mut list: Vec<Principal> = principals.iter().cloned().collect();
+        list.sort();
+        crate::storage::save_candid(&self.registry, &self.key, &list)
+            .map_err(AuthError::StorageError)
+    }
+
+    /// A missing key is an empty allowlist; an undecodable one is an error
+    fn load_principals(&self) -> AuthResult<HashSet<Principal>> {
+        let Some(bytes) = crate::storage::load_bytes(&self.registry, &self.key) else {
+            return Ok(HashSet::new());
+        };
+        let list = candid::Decode!(&bytes, Vec<Principal>)
+            .map_err(|e| AuthError::StorageError(format!("Failed to decode principals: {}", e)))?;
+        Ok(list.into_iter().collect())
+    }
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Auth Manager
 // ═══════════════════════════════════════════════════════════════
 
 /// Main authentication manager for IC canisters
 pub struct Auth {
-    storage: AuthStorage,
+    storage: Box<dyn PrincipalStorage>,
     cache: RefCell<HashSet<Principal>>,
+    prefixes: RefCell<Vec<Vec<u8>>>,
 }
 
 impl Auth {
-    pub fn new(storage: AuthStorage) -> Self {
+    pub fn new(storage: impl PrincipalStorage + 'static) -> Self {
         let auth = Self {
-            storage,
+            storage: Box::new(storage),
             cache: RefCell::new(HashSet::new()),
+            prefixes: RefCell::new(Vec::new()),
         };
 
         // Load from storage into cache
@@ -88,14 +177,21 @@ impl Auth {
     }
 
     /// Check if a principal is authorized
+    ///
+    /// A principal is authorized if it is in the allowlist or its bytes start
+    /// with a registered prefix.
     pub fn is_authorized(&self, principal: &Principal) -> AuthResult<bool> {
-        Ok(self.cache.borrow().contains(principal))
+        if self.cache.borrow().contains(principal) {
+            return Ok(true);
+        }
+        let bytes = principal.as_slice();
+        Ok(self.prefixes.borrow().iter().any(|p| bytes.starts_with(p)))
     }
 
     /// Get the current caller principal
     pub fn get_current_principal(&self) -> AuthResult<Principal> {
-        let caller = ic_cdk::api::msg_caller();
-        if caller == ic_cdk::api::canister_self() {
+        let caller = crate::env::caller();
+        if caller == crate::env::canister_self() {
             return Err(AuthError::Unauthorized);
         }
         Ok(caller)
@@ -103,9 +199,14 @@ impl Auth {
 
     /// Check if current caller is authorized
     pub fn check_authorized(&self) -> AuthResult<()> {
+        self.authorized_caller().map(|_| ())
+    }
+
+    /// Get the current caller principal if it is authorized
+    pub fn authorized_caller(&self) -> AuthResult<Principal> {
         let current = self.get_current_principal()?;
         if self.is_authorized(&current)? {
-            Ok(())
+            Ok(current)
         } else {
             Err(AuthError::Unauthorized)
         }
@@ -114,18 +215,75 @@ impl Auth {
     /// Add an authorized principal
     pub fn add_principal(&self, principal: Principal) -> AuthResult<()> {
         self.cache.borrow_mut().insert(principal);
-        Ok(())
+        self.save_to_storage()
     }
 
     /// Remove an authorized principal
     pub fn remove_principal(&self, principal: &Principal) -> AuthResult<()> {
         self.cache.borrow_mut().remove(principal);
-        Ok(())
+        self.save_to_storage()
     }
 
-    /// List all authorized principals
+    /// List all authorized principals, sorted by their bytes
     pub fn list_principals(&self) -> AuthResult<Vec<Principal>> {
-        Ok(self.cache.borrow().iter().cloned().collect())
+        let mut principals: Vec<Principal> = self.cache.borrow().iter().cloned().collect();
+        principals.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        Ok(principals)
+    }
+
+    /// List up to `limit` principals starting at `offset`, in `list_principals` order
+    pub fn list_principals_paged(&self, offset: usize, limit: usize) -> AuthResult<Vec<Principal>> {
+        Ok(self
+            .list_principals()?
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .collect())
+    }
+
+    /// Number of authorized principals (prefixes not included)
+    pub fn count(&self) -> AuthResult<usize> {
+        Ok(self.cache.borrow().len())
+    }
+
+    /// Compare the allowlist with `expected` (prefixes not included)
+    pub fn diff_against(&self, expected: &[Principal]) -> AuthDiff {
+        let cache = self.cache.borrow();
+        let expected: HashSet<Principal> = expected.iter().cloned().collect();
+
+        let mut missing: Vec<Principal> = expected.difference(&cache).cloned().collect();
+        let mut extra: Vec<Principal> = cache.difference(&expected).cloned().collect();
+        missing.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        extra.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+
+        AuthDiff { missing, extra }
+    }
+
+    /// Authorize every principal whose bytes start with `prefix`
+    ///
+    /// Empty prefixes are rejected since they would match every caller.
+    pub fn add_principal_prefix(&self, prefix: Vec<u8>) -> AuthResult<()> {
+        if prefix.is_empty() {
+            return Err(AuthError::InvalidPrincipal);
+        }
+        let mut prefixes = self.prefixes.borrow_mut();
+        if !prefixes.contains(&prefix) {
+            prefixes.push(prefix);
+        }
+        Ok(())
+    }
+
+    /// Remove a registered principal prefix
+    pub fn remove_principal_prefix(&self, prefix: &[u8]) -> AuthResult<()> {
+        self.prefixes
+            .borrow_mut()
+            .retain(|p| p.as_slice() != prefix);
+        Ok(())
+    }
+
+    /// List registered principal prefixes
+    pub fn list_principal_prefixes(&self) -> AuthResult<Vec<Vec<u8>>> {
+        Ok(self.prefixes.borrow().clone())
     }
 
     /// Ensure a principal is authorized (add if not present)
@@ -153,6 +311,17 @@ impl Auth {
 
 thread_local! {
     static AUTH: RefCell<Option<Auth>> = RefCell::new(None);
+    static REJECT_ANONYMOUS: Cell<bool> = Cell::new(true);
+}
+
+/// Drop the anonymous principal in `try_init_with_principals` (default: true)
+pub fn set_reject_anonymous(reject: bool) {
+    REJECT_ANONYMOUS.with(|r| r.set(reject));
+}
+
+/// Whether `try_init_with_principals` drops the anonymous principal
+pub fn reject_anonymous() -> bool {
+    REJECT_ANONYMOUS.with(|r| r.get())
 }
 
 /// Initialize the auth system with simple in-memory storage
@@ -164,45 +333,126 @@ pub fn init() {
 
 /// Initialize auth system with the deployer as initial authorized principal
 pub fn init_with_caller() {
-    let caller = ic_cdk::api::msg_caller();
+    let caller = crate::env::caller();
     let storage = AuthStorage::with_initial_principal(caller);
     let auth = Auth::new(storage);
     AUTH.with(|a| *a.borrow_mut() = Some(auth));
 }
 
 /// Initialize the auth system with specific principals
+///
+/// See `try_init_with_principals` for how the input is filtered.
 pub fn init_with_principals(principals: Vec<Principal>) {
-    let mut initial_set = HashSet::new();
-    for principal in principals {
-        initial_set.insert(principal);
-    }
+    let _ = try_init_with_principals(principals);
+}
+
+/// Initialize the auth system with specific principals, reporting what was kept
+///
+/// Duplicates are collapsed and, while `reject_anonymous()` is set, the
+/// anonymous principal is dropped. Returns the number of distinct principals
+/// stored, so callers can detect a misconfigured allowlist.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::init]
+/// fn init(admins: Vec<Principal>) {
+///     let count = auth::try_init_with_principals(admins).expect("auth init");
+///     assert!(count > 0, "no usable admin principals");
+/// }
+/// ```
+pub fn try_init_with_principals(principals: Vec<Principal>) -> AuthResult<usize> {
+    let reject_anonymous = reject_anonymous();
+    let initial_set: HashSet<Principal> = principals
+        .into_iter()
+        .filter(|p| !(reject_anonymous && *p == Principal::anonymous()))
+        .collect();
+    let count = initial_set.len();
 
     let storage = AuthStorage {
         principals: RefCell::new(initial_set),
     };
     let auth = Auth::new(storage);
     AUTH.with(|a| *a.borrow_mut() = Some(auth));
+    Ok(count)
+}
+
+/// Initialize the auth system with a custom storage backend
+pub fn init_with_storage(storage: impl PrincipalStorage + 'static) {
+    let auth = Auth::new(storage);
+    AUTH.with(|a| *a.borrow_mut() = Some(auth));
+}
+
+/// Initialize with a storage backend, migrating principals saved with `save_to_bytes`
+///
+/// If the backend is empty, it is seeded from `saved_bytes`. Use this once in
+/// `post_upgrade` when moving from in-memory storage to `StableAuthStorage`.
+pub fn migrate_to_storage(
+    storage: impl PrincipalStorage + 'static,
+    saved_bytes: Option<Vec<u8>>,
+) -> AuthResult<()> {
+    let saved = saved_bytes
+        .map(|bytes| decode_saved(&bytes))
+        .transpose()
+        .map_err(|e| AuthError::SerializationError(format!("{:?}", e)))?;
+    let (principals, prefixes) = saved.unwrap_or_default();
+
+    if !principals.is_empty() && storage.load_principals()?.is_empty() {
+        storage.save_principals(&principals.into_iter().collect())?;
+    }
+
+    init_with_storage(storage);
+    with_auth(|auth| *auth.prefixes.borrow_mut() = prefixes);
+    Ok(())
 }
 
 /// Initialize auth system from saved bytes (for post-upgrade)
+///
+/// **Data-loss risk:** if `saved_bytes` is missing or fails to decode, the
+/// saved allowlist is discarded and only the caller (the upgrader) is
+/// authorized. After a bad upgrade this silently locks out every other
+/// principal. Prefer `init_from_saved_strict` and trap on error so the
+/// upgrade rolls back with the allowlist intact.
 pub fn init_from_saved(saved_bytes: Option<Vec<u8>>) {
-    let principals = if let Some(bytes) = saved_bytes {
-        match candid::decode_args::<(Vec<Principal>,)>(&bytes) {
-            Ok((principals,)) => {
+    let (principals, prefixes) = if let Some(bytes) = saved_bytes {
+        match decode_saved(&bytes) {
+            Ok((principals, prefixes)) => {
                 ic_cdk::println!("Restored {} principals from saved data", principals.len());
-                principals
+                (principals, prefixes)
             }
             Err(e) => {
                 ic_cdk::println!("Failed to decode saved principals: {:?}, starting fresh", e);
-                vec![ic_cdk::api::msg_caller()]
+                (vec![crate::env::caller()], Vec::new())
             }
         }
     } else {
         ic_cdk::println!("No saved principals found, starting fresh");
-        vec![ic_cdk::api::msg_caller()]
+        (vec![crate::env::caller()], Vec::new())
     };
 
     init_with_principals(principals);
+    with_auth(|auth| *auth.prefixes.borrow_mut() = prefixes);
+}
+
+/// Initialize auth system from saved bytes, failing instead of falling back
+///
+/// Unlike `init_from_saved`, nothing is initialized if the bytes don't decode.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::post_upgrade]
+/// fn post_upgrade() {
+///     let bytes = read_saved_auth_bytes();
+///     // Trapping rolls the upgrade back, keeping the old allowlist
+///     auth::init_from_saved_strict(&bytes).expect("failed to restore auth");
+/// }
+/// ```
+pub fn init_from_saved_strict(bytes: &[u8]) -> AuthResult<()> {
+    let (principals, prefixes) =
+        decode_saved(bytes).map_err(|e| AuthError::SerializationError(format!("{:?}", e)))?;
+
+    init_with_principals(principals);
+    with_auth(|auth| *auth.prefixes.borrow_mut() = prefixes);
+    Ok(())
 }
 
 /// Helper function to work with the auth instance
@@ -223,6 +473,11 @@ where
 //  Public API
 // ═══════════════════════════════════════════════════════════════
 
+/// Whether `auth::init*` has been called (usable as a health check)
+pub fn is_initialized() -> bool {
+    AUTH.with(|a| a.borrow().is_some())
+}
+
 /// Guard function for IC CDK queries/updates
 pub fn is_authorized() -> Result<(), String> {
     with_auth(|auth| {
@@ -236,6 +491,27 @@ pub fn check() -> Result<(), String> {
     is_authorized()
 }
 
+/// Check authorization and return the caller principal
+///
+/// Use in method bodies instead of a guard plus a separate `msg_caller()`, so
+/// the authorization check and the body always agree on who the caller is.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::update]
+/// fn set_profile(profile: Profile) -> Result<(), String> {
+///     let caller = auth::authorized_caller()?;
+///     PROFILES.with(|p| p.borrow_mut().insert(caller, profile));
+///     Ok(())
+/// }
+/// ```
+pub fn authorized_caller() -> Result<Principal, String> {
+    with_auth(|auth| {
+        auth.authorized_caller()
+            .map_err(|e| format!("Authorization failed: {}", e))
+    })
+}
+
 /// Add an authorized principal
 pub fn add_principal(principal: Principal) -> Result<(), String> {
     with_auth(|auth| {
@@ -261,7 +537,7 @@ pub fn is_principal_authorized(principal: Principal) -> Result<bool, String> {
     })
 }
 
-/// List all authorized principals
+/// List all authorized principals, sorted by their bytes
 pub fn list_principals() -> Result<Vec<Principal>, String> {
     with_auth(|auth| {
         auth.list_principals()
@@ -269,6 +545,63 @@ pub fn list_principals() -> Result<Vec<Principal>, String> {
     })
 }
 
+/// Difference between the allowlist and an expected set of principals
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AuthDiff {
+    /// Expected but not authorized
+    pub missing: Vec<Principal>,
+    /// Authorized but not expected
+    pub extra: Vec<Principal>,
+}
+
+impl AuthDiff {
+    /// True when the allowlist matches exactly
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// Compare the current allowlist with `expected`, e.g. after an upgrade
+///
+/// Both lists are sorted like `list_principals`; prefixes are ignored.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::post_upgrade]
+/// fn post_upgrade() {
+///     auth::init_from_saved(restore_auth_bytes());
+///     let diff = auth::diff_against(&EXPECTED_ADMINS);
+///     assert!(diff.is_empty(), "allowlist drift: {:?}", diff);
+/// }
+/// ```
+pub fn diff_against(expected: &[Principal]) -> AuthDiff {
+    with_auth(|auth| auth.diff_against(expected))
+}
+
+/// List a page of authorized principals in stable (sorted) order
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::query(guard = "auth::is_authorized")]
+/// fn admins(page: usize) -> Vec<Principal> {
+///     auth::list_principals_paged(page * 20, 20).unwrap_or_default()
+/// }
+/// ```
+pub fn list_principals_paged(offset: usize, limit: usize) -> Result<Vec<Principal>, String> {
+    with_auth(|auth| {
+        auth.list_principals_paged(offset, limit)
+            .map_err(|e| format!("Failed to list principals: {}", e))
+    })
+}
+
+/// Number of authorized principals
+pub fn principal_count() -> Result<usize, String> {
+    with_auth(|auth| {
+        auth.count()
+            .map_err(|e| format!("Failed to count principals: {}", e))
+    })
+}
+
 /// Ensure a principal is authorized
 pub fn ensure_authorized(principal: Principal) -> Result<(), String> {
     with_auth(|auth| {
@@ -277,28 +610,69 @@ pub fn ensure_authorized(principal: Principal) -> Result<(), String> {
     })
 }
 
+/// Authorize every caller whose principal bytes start with `prefix_bytes`
+///
+/// Intended for delegated identity schemes where a provider derives
+/// principals under a shared prefix. A prefix authorizes principals nobody
+/// has enumerated yet, so keep it as long as the scheme allows: a short
+/// prefix can match unrelated principals (canisters, self-authenticating
+/// users) that happen to share those leading bytes. Empty prefixes are
+/// rejected.
+///
+/// Prefixes are kept in memory and persisted by `save_to_bytes`, not by the
+/// `PrincipalStorage` backend.
+///
+/// # Example
+/// ```rust,ignore
+/// let provider = Principal::from_text("rdmx6-jaaaa-aaaaa-aaadq-cai").unwrap();
+/// auth::add_principal_prefix(provider.as_slice().to_vec())?;
+/// ```
+pub fn add_principal_prefix(prefix_bytes: Vec<u8>) -> Result<(), String> {
+    with_auth(|auth| {
+        auth.add_principal_prefix(prefix_bytes)
+            .map_err(|e| format!("Failed to add principal prefix: {}", e))
+    })
+}
+
+/// Remove a registered principal prefix
+pub fn remove_principal_prefix(prefix_bytes: &[u8]) -> Result<(), String> {
+    with_auth(|auth| {
+        auth.remove_principal_prefix(prefix_bytes)
+            .map_err(|e| format!("Failed to remove principal prefix: {}", e))
+    })
+}
+
+/// List registered principal prefixes
+pub fn list_principal_prefixes() -> Result<Vec<Vec<u8>>, String> {
+    with_auth(|auth| {
+        auth.list_principal_prefixes()
+            .map_err(|e| format!("Failed to list principal prefixes: {}", e))
+    })
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Serialization Utilities (for upgrade persistence)
 // ═══════════════════════════════════════════════════════════════
 
-/// Save auth principals to bytes for stable storage
+/// Save auth principals and principal prefixes to bytes for stable storage
 pub fn save_to_bytes() -> Vec<u8> {
     with_auth(|auth| {
         let principals = auth.list_principals().unwrap_or_default();
-        candid::encode_args((&principals,)).unwrap_or_default()
+        let prefixes = auth.list_principal_prefixes().unwrap_or_default();
+        candid::encode_args((&principals, &prefixes)).unwrap_or_default()
     })
 }
 
 /// Load auth principals from bytes (for post-upgrade)
 pub fn load_from_bytes(bytes: &[u8]) -> Result<(), String> {
-    let decoded: Result<(Vec<Principal>,), _> = candid::decode_args(bytes);
-    match decoded {
-        Ok((principals,)) => {
+    match decode_saved(bytes) {
+        Ok((principals, prefixes)) => {
             with_auth(|auth| {
                 auth.cache.borrow_mut().clear();
                 for principal in principals {
                     let _ = auth.add_principal(principal);
                 }
+                *auth.prefixes.borrow_mut() = prefixes;
             });
             Ok(())
         }
@@ -306,11 +680,360 @@ pub fn load_from_bytes(bytes: &[u8]) -> Result<(), String> {
     }
 }
 
+/// Decode `save_to_bytes` output, accepting bytes saved before prefixes existed
+fn decode_saved(bytes: &[u8]) -> Result<(Vec<Principal>, Vec<Vec<u8>>), candid::Error> {
+    candid::decode_args::<(Vec<Principal>, Vec<Vec<u8>>)>(bytes).or_else(|_| {
+        candid::decode_args::<(Vec<Principal>,)>(bytes)
+            .map(|(principals,)| (principals, Vec::new()))
+    })
+}
+
 /// Validate a principal text string
 pub fn validate_principal_text(text: &str) -> Result<Principal, AuthError> {
     Principal::from_text(text).map_err(|_| AuthError::InvalidPrincipal)
 }
 
+/// Type byte of a self-authenticating principal (derived from a public key)
+const SELF_AUTHENTICATING_TYPE: u8 = 0x02;
+
+/// Validate a principal text string that must identify a human user
+///
+/// IC principals encode their class in the last byte:
+/// - `0x01` opaque IDs (canister IDs)
+/// - `0x02` self-authenticating IDs (users, derived from a public key)
+/// - `0x03` derived IDs
+/// - `0x04` the anonymous principal
+///
+/// The management canister is the empty principal. Only self-authenticating
+/// principals are accepted; everything else is rejected as `InvalidPrincipal`.
+pub fn validate_user_principal(text: &str) -> AuthResult<Principal> {
+    let principal = validate_principal_text(text)?;
+
+    if principal == Principal::anonymous() {
+        return Err(AuthError::InvalidPrincipal);
+    }
+
+    match principal.as_slice().last() {
+        Some(&SELF_AUTHENTICATING_TYPE) => Ok(principal),
+        _ => Err(AuthError::InvalidPrincipal),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Request Signatures
+// ═══════════════════════════════════════════════════════════════
+
+/// Verify an Ed25519 signature over `message`
+///
+/// Uses strict verification (rejects malleable signatures and weak keys).
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::update]
+/// fn http_request_update(req: HttpRequest) -> HttpResponse {
+///     let signature = http::get_header(&req.headers, "X-Signature")
+///         .and_then(|s| hex::decode(s).ok())
+///         .unwrap_or_default();
+///     let message = auth::canonical_request_message(&req);
+///     if auth::verify_signature(&REGISTERED_KEY, &message, &signature).is_err() {
+///         return HttpError::unauthorized("Bad signature").to_response();
+///     }
+///     // ...
+/// }
+/// ```
+#[cfg(feature = "signatures")]
+pub fn verify_signature(public_key: &[u8], message: &[u8], signature: &[u8]) -> AuthResult<()> {
+    use ed25519_dalek::{Signature, VerifyingKey};
+
+    let public_key: &[u8; 32] = public_key
+        .try_into()
+        .map_err(|_| AuthError::InvalidSignature("public key must be 32 bytes".to_string()))?;
+    let verifying_key = VerifyingKey::from_bytes(public_key)
+        .map_err(|e| AuthError::InvalidSignature(e.to_string()))?;
+    let signature =
+        Signature::from_slice(signature).map_err(|e| AuthError::InvalidSignature(e.to_string()))?;
+
+    verifying_key
+        .verify_strict(message, &signature)
+        .map_err(|e| AuthError::InvalidSignature(e.to_string()))
+}
+
+/// Build the canonical message a client signs for an HTTP request
+///
+/// The message is `METHOD\npath\nbody`, with the method uppercased and the
+/// query string removed from the path.
+pub fn canonical_request_message(request: &crate::http::HttpRequest) -> Vec<u8> {
+    let mut message = format!(
+        "{}\n{}\n",
+        request.method.to_uppercase(),
+        crate::http::extract_path(&request.url)
+    )
+    .into_bytes();
+    message.extend_from_slice(&request.body);
+    message
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Internet Identity Delegations (requires "hashing" feature)
+// ═══════════════════════════════════════════════════════════════
+
+/// Internet Identity delegation chains for the HTTP-gateway path
+///
+/// HTTP requests don't carry an authenticated caller, so an II-authenticated
+/// frontend sends its delegation chain (the JSON produced by agent-js
+/// `DelegationChain.toJSON()`) and signs the request with its session key.
+///
+/// `verify_delegation` checks expiry, targets and each delegation's signature
+/// and returns the user principal. II root keys are canister signatures,
+/// which need certificate and BLS verification this crate doesn't ship, so
+/// signatures are checked by a verifier registered with
+/// `set_signature_verifier`. Without one every chain is rejected.
+///
+/// The chain only proves the session key speaks for the user: also verify the
+/// request itself was signed by `DelegationChain::session_key`.
+#[cfg(feature = "hashing")]
+pub mod ii {
+    use super::{AuthError, AuthResult};
+    use candid::Principal;
+    use serde::Deserialize;
+    use sha2::{Digest, Sha256};
+    use std::cell::Cell;
+
+    /// Domain separator prepended to the signed delegation hash
+    pub const DELEGATION_DOMAIN_SEPARATOR: &[u8] = b"\x1Aic-request-auth-delegation";
+
+    /// Longest chain the IC accepts
+    pub const MAX_DELEGATIONS: usize = 20;
+
+    /// Verify `signature` by the DER-encoded `public_key` over `message`
+    pub type SignatureVerifierFn =
+        fn(public_key: &[u8], message: &[u8], signature: &[u8]) -> AuthResult<()>;
+
+    thread_local! {
+        static SIGNATURE_VERIFIER: Cell<Option<SignatureVerifierFn>> = Cell::new(None);
+    }
+
+    /// Set the verifier used for delegation signatures
+    pub fn set_signature_verifier(verifier: SignatureVerifierFn) {
+        SIGNATURE_VERIFIER.with(|v| v.set(Some(verifier)));
+    }
+
+    /// A single delegation to `pubkey`, valid until `expiration` (nanoseconds)
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Delegation {
+        pub pubkey: Vec<u8>,
+        pub expiration: u64,
+        /// Canisters the delegation is restricted to (`None` = any)
+        pub targets: Option<Vec<Principal>>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SignedDelegation {
+        pub delegation: Delegation,
+        pub signature: Vec<u8>,
+    }
+
+    /// Delegation chain from the user's root key to a session key
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct DelegationChain {
+        /// DER-encoded root public key the user principal is derived from
+        pub public_key: Vec<u8>,
+        pub delegations: Vec<SignedDelegation>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ChainJson {
+        public_key: String,
+        delegations: Vec<SignedDelegationJson>,
+    }
+
+    #[derive(Deserialize)]
+    struct SignedDelegationJson {
+        delegation: DelegationJson,
+        signature: String,
+    }
+
+    #[derive(Deserialize)]
+    struct DelegationJson {
+        pubkey: String,
+        expiration: String,
+        targets: Option<Vec<String>>,
+    }
+
+    impl DelegationChain {
+        /// Parse the JSON form of a chain (hex-encoded keys, signatures and
+        /// expirations, as serialized by agent-js)
+        pub fn from_json(bytes: &[u8]) -> AuthResult<Self> {
+            let json: ChainJson = serde_json::from_slice(bytes)
+                .map_err(|e| AuthError::InvalidDelegation(e.to_string()))?;
+
+            let delegations = json
+                .delegations
+                .into_iter()
+                .map(|signed| {
+                    let d = signed.delegation;
+                    let expiration = u64::from_str_radix(&d.expiration, 16)
+                        .map_err(|_| invalid("expiration is not a hex u64"))?;
+                    let targets = d
+                        .targets
+                        .map(|targets| {
+                            targets
+                                .iter()
+                                .map(|t| {
+                                    Principal::try_from_slice(&from_hex(t)?)
+                                        .map_err(|e| invalid(&format!("bad target: {}", e)))
+                                })
+                                .collect::<AuthResult<Vec<_>>>()
+                        })
+                        .transpose()?;
+
+                    Ok(SignedDelegation {
+                        delegation: Delegation {
+                            pubkey: from_hex(&d.pubkey)?,
+                            expiration,
+                            targets,
+                        },
+                        signature: from_hex(&signed.signature)?,
+                    })
+                })
+                .collect::<AuthResult<Vec<_>>>()?;
+
+            Ok(Self {
+                public_key: from_hex(&json.public_key)?,
+                delegations,
+            })
+        }
+
+        /// Public key the last delegation hands authority to
+        pub fn session_key(&self) -> &[u8] {
+            self.delegations
+                .last()
+                .map_or(&self.public_key, |d| &d.delegation.pubkey)
+        }
+
+        /// Self-authenticating principal of the root key
+        pub fn user_principal(&self) -> Principal {
+            Principal::self_authenticating(&self.public_key)
+        }
+    }
+
+    /// Verify a delegation chain for `expected_canister` at `now_ns`
+    ///
+    /// Rejects empty or overlong chains, expired delegations, delegations
+    /// whose targets exclude `expected_canister`, and bad signatures. Returns
+    /// the user principal derived from the root key.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let chain = http::get_header(&req.headers, "X-Delegation-Chain").unwrap_or_default();
+    /// let user = auth::ii::verify_delegation(
+    ///     chain.as_bytes(),
+    ///     ic_cdk::api::canister_self(),
+    ///     ic_cdk::api::time(),
+    /// )
+    /// .map_err(|e| HttpError::unauthorized(e.to_string()))?;
+    /// ```
+    pub fn verify_delegation(
+        chain_bytes: &[u8],
+        expected_canister: Principal,
+        now_ns: u64,
+    ) -> AuthResult<Principal> {
+        let chain = DelegationChain::from_json(chain_bytes)?;
+
+        if chain.delegations.is_empty() {
+            return Err(invalid("chain has no delegations"));
+        }
+        if chain.delegations.len() > MAX_DELEGATIONS {
+            return Err(invalid("chain has too many delegations"));
+        }
+
+        let verifier = SIGNATURE_VERIFIER
+            .with(|v| v.get())
+            .ok_or_else(|| invalid("no signature verifier configured"))?;
+
+        let mut signer = chain.public_key.as_slice();
+        for signed in &chain.delegations {
+            let delegation = &signed.delegation;
+
+            if delegation.expiration < now_ns {
+                return Err(invalid("delegation expired"));
+            }
+            if let Some(targets) = &delegation.targets {
+                if !targets.contains(&expected_canister) {
+                    return Err(invalid("canister is not a delegation target"));
+                }
+            }
+
+            verifier(signer, &signed_message(delegation), &signed.signature)?;
+            signer = &delegation.pubkey;
+        }
+
+        Ok(chain.user_principal())
+    }
+
+    /// Bytes a delegation's signature covers: the domain separator followed by
+    /// the representation-independent hash of the delegation
+    pub fn signed_message(delegation: &Delegation) -> Vec<u8> {
+        let mut fields = vec![
+            field_hash("pubkey", &sha256(&delegation.pubkey)),
+            field_hash("expiration", &sha256(&leb128(delegation.expiration))),
+        ];
+        if let Some(targets) = &delegation.targets {
+            let mut concat = Vec::new();
+            for target in targets {
+                concat.extend_from_slice(&sha256(target.as_slice()));
+            }
+            fields.push(field_hash("targets", &sha256(&concat)));
+        }
+        fields.sort();
+
+        let mut message = DELEGATION_DOMAIN_SEPARATOR.to_vec();
+        message.extend_from_slice(&sha256(&fields.concat()));
+        message
+    }
+
+    fn field_hash(key: &str, value_hash: &[u8; 32]) -> Vec<u8> {
+        let mut bytes = sha256(key.as_bytes()).to_vec();
+        bytes.extend_from_slice(value_hash);
+        bytes
+    }
+
+    fn sha256(bytes: &[u8]) -> [u8; 32] {
+        Sha256::digest(bytes).into()
+    }
+
+    fn leb128(mut n: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                out.push(byte);
+                return out;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn from_hex(s: &str) -> AuthResult<Vec<u8>> {
+        let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+
+        s.as_bytes()
+            .chunks(2)
+            .map(|pair| match pair {
+                [hi, lo] => Some((digit(*hi)? << 4) | digit(*lo)?),
+                _ => None,
+            })
+            .map(|byte| byte.ok_or_else(|| invalid("invalid hex")))
+            .collect()
+    }
+
+    fn invalid(msg: &str) -> AuthError {
+        AuthError::InvalidDelegation(msg.to_string())
+    }
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  IC CDK Exported Functions (Optional - for standalone use)
 // ═══════════════════════════════════════════════════════════════
@@ -342,7 +1065,7 @@ pub fn check_principal_authorized(principal: Principal) -> bool {
 /// Query to get count of authorized principals (guarded)
 #[ic_cdk::query(guard = "is_authorized")]
 pub fn get_authorized_count() -> usize {
-    list_principals().map(|list| list.len()).unwrap_or(0)
+    principal_count().unwrap_or(0)
 }
 
 #[cfg(test)]
@@ -382,6 +1105,177 @@ mod tests {
         assert!(!auth.is_authorized(&test_principal).unwrap());
     }
 
+    #[test]
+    fn test_principal_prefix() {
+        let auth = Auth::new(AuthStorage::new());
+        let provider = Principal::from_slice(&[9, 9, 9]);
+        let derived = Principal::from_slice(&[9, 9, 9, 1, 2]);
+        let other = Principal::from_slice(&[9, 8, 9, 1, 2]);
+
+        assert!(auth.add_principal_prefix(vec![]).is_err());
+        auth.add_principal_prefix(provider.as_slice().to_vec())
+            .unwrap();
+
+        assert!(auth.is_authorized(&provider).unwrap());
+        assert!(auth.is_authorized(&derived).unwrap());
+        assert!(!auth.is_authorized(&other).unwrap());
+        // Prefixes are not listed as principals
+        assert!(auth.list_principals().unwrap().is_empty());
+
+        auth.remove_principal_prefix(provider.as_slice()).unwrap();
+        assert!(!auth.is_authorized(&derived).unwrap());
+    }
+
+    #[test]
+    fn test_try_init_with_principals() {
+        let user = Principal::self_authenticating([1u8; 32]);
+        let principals = vec![user, Principal::anonymous(), user];
+
+        assert_eq!(try_init_with_principals(principals.clone()).unwrap(), 1);
+        assert_eq!(list_principals().unwrap(), vec![user]);
+
+        set_reject_anonymous(false);
+        assert_eq!(try_init_with_principals(principals).unwrap(), 2);
+        assert!(is_principal_authorized(Principal::anonymous()).unwrap());
+    }
+
+    #[test]
+    fn test_init_from_saved_strict() {
+        assert!(matches!(
+            init_from_saved_strict(b"not candid"),
+            Err(AuthError::SerializationError(_))
+        ));
+        assert!(AUTH.with(|a| a.borrow().is_none()));
+
+        let user = Principal::self_authenticating([2u8; 32]);
+        let bytes = candid::encode_args((vec![user], vec![vec![7u8]])).unwrap();
+        init_from_saved_strict(&bytes).unwrap();
+
+        assert_eq!(list_principals().unwrap(), vec![user]);
+        assert_eq!(list_principal_prefixes().unwrap(), vec![vec![7u8]]);
+    }
+
+    #[test]
+    fn test_guard_with_mocked_caller() {
+        use crate::testing;
+
+        let admin = Principal::self_authenticating([3u8; 32]);
+        let stranger = Principal::self_authenticating([4u8; 32]);
+        testing::set_canister_id(Principal::from_slice(&[9]));
+        init_with_principals(vec![admin]);
+
+        testing::set_caller(admin);
+        assert!(is_authorized().is_ok());
+        assert_eq!(authorized_caller().unwrap(), admin);
+
+        testing::set_caller(stranger);
+        assert!(is_authorized().is_err());
+        testing::reset();
+    }
+
+    #[test]
+    fn test_is_initialized() {
+        assert!(!is_initialized());
+        init();
+        assert!(is_initialized());
+    }
+
+    #[test]
+    fn test_diff_against() {
+        let a = Principal::self_authenticating([5u8; 32]);
+        let b = Principal::self_authenticating([6u8; 32]);
+        let c = Principal::self_authenticating([7u8; 32]);
+        init_with_principals(vec![a, b]);
+
+        assert!(diff_against(&[b, a]).is_empty());
+
+        let diff = diff_against(&[a, c]);
+        assert_eq!(diff.missing, vec![c]);
+        assert_eq!(diff.extra, vec![b]);
+    }
+
+    #[test]
+    fn test_list_principals_sorted_and_paged() {
+        let auth = Auth::new(AuthStorage::new());
+        for bytes in [[3u8], [1u8], [2u8]] {
+            auth.add_principal(Principal::from_slice(&bytes)).unwrap();
+        }
+        // Longer principals sort by bytes, not by length
+        auth.add_principal(Principal::from_slice(&[1, 9])).unwrap();
+
+        let slices: Vec<Vec<u8>> = auth
+            .list_principals()
+            .unwrap()
+            .iter()
+            .map(|p| p.as_slice().to_vec())
+            .collect();
+        assert_eq!(slices, vec![vec![1], vec![1, 9], vec![2], vec![3]]);
+
+        let page = auth.list_principals_paged(1, 2).unwrap();
+        assert_eq!(page, vec![Principal::from_slice(&[1, 9]), Principal::from_slice(&[2])]);
+        assert!(auth.list_principals_paged(10, 2).unwrap().is_empty());
+        assert_eq!(auth.count().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_decode_saved() {
+        let principals = vec![Principal::anonymous()];
+        let prefixes = vec![vec![1u8, 2]];
+
+        let bytes = candid::encode_args((&principals, &prefixes)).unwrap();
+        assert_eq!(
+            decode_saved(&bytes).unwrap(),
+            (principals.clone(), prefixes)
+        );
+
+        // Bytes saved before prefixes were persisted
+        let bytes = candid::encode_args((&principals,)).unwrap();
+        assert_eq!(decode_saved(&bytes).unwrap(), (principals, vec![]));
+    }
+
+    #[test]
+    fn test_auth_writes_through_to_storage() {
+        struct SharedStorage(std::rc::Rc<AuthStorage>);
+
+        impl PrincipalStorage for SharedStorage {
+            fn save_principals(&self, principals: &HashSet<Principal>) -> AuthResult<()> {
+                self.0.save_principals(principals)
+            }
+
+            fn load_principals(&self) -> AuthResult<HashSet<Principal>> {
+                self.0.load_principals()
+            }
+        }
+
+        let backing = std::rc::Rc::new(AuthStorage::new());
+        let auth = Auth::new(SharedStorage(backing.clone()));
+
+        auth.add_principal(Principal::anonymous()).unwrap();
+        assert!(backing.load_principals().unwrap().contains(&Principal::anonymous()));
+
+        auth.remove_principal(&Principal::anonymous()).unwrap();
+        assert!(backing.load_principals().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "storage")]
+    #[test]
+    fn test_stable_auth_storage() {
+        use crate::testing::TestRegistry;
+
+        let storage = StableAuthStorage::new(TestRegistry::default());
+        assert!(storage.load_principals().unwrap().is_empty());
+
+        let mut principals = HashSet::new();
+        principals.insert(Principal::anonymous());
+        storage.save_principals(&principals).unwrap();
+
+        assert_eq!(storage.load_principals().unwrap(), principals);
+
+        // A corrupt blob is an error, not an empty allowlist
+        crate::storage::save_bytes(&storage.registry, &storage.key, vec![0xff, 0x00]);
+        assert!(matches!(storage.load_principals(), Err(AuthError::StorageError(_))));
+    }
+
     #[test]
     fn test_principal_validation() {
         let result = validate_principal_text("2vxsx-fae");
@@ -390,4 +1284,138 @@ mod tests {
         let result = validate_principal_text("invalid");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_validate_user_principal() {
+        let user = Principal::self_authenticating([1u8; 32]);
+        assert_eq!(validate_user_principal(&user.to_text()).unwrap(), user);
+
+        // Anonymous
+        assert!(validate_user_principal("2vxsx-fae").is_err());
+        // Canister ID (opaque)
+        assert!(validate_user_principal("ryjl3-tyaaa-aaaaa-aaaba-cai").is_err());
+        // Management canister (empty)
+        assert!(validate_user_principal("aaaaa-aa").is_err());
+        // Unparseable
+        assert!(validate_user_principal("invalid").is_err());
+    }
+
+    #[test]
+    fn test_canonical_request_message() {
+        let request = crate::http::HttpRequest {
+            method: "post".to_string(),
+            url: "/api/items?debug=1".to_string(),
+            headers: vec![],
+            body: b"{}".to_vec(),
+        };
+        assert_eq!(canonical_request_message(&request), b"POST\n/api/items\n{}".to_vec());
+    }
+
+    #[cfg(feature = "signatures")]
+    #[test]
+    fn test_verify_signature() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = signing_key.verifying_key().to_bytes();
+        let signature = signing_key.sign(b"hello").to_bytes();
+
+        assert!(verify_signature(&public_key, b"hello", &signature).is_ok());
+        assert!(verify_signature(&public_key, b"hellO", &signature).is_err());
+        assert!(verify_signature(&public_key[..31], b"hello", &signature).is_err());
+        assert!(verify_signature(&public_key, b"hello", &signature[..63]).is_err());
+    }
+
+    #[cfg(feature = "hashing")]
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[cfg(feature = "hashing")]
+    #[test]
+    fn test_ii_signed_message() {
+        let mut delegation = ii::Delegation {
+            pubkey: vec![1, 2, 3],
+            expiration: 1_000_000_000,
+            targets: None,
+        };
+
+        let message = ii::signed_message(&delegation);
+        assert!(message.starts_with(ii::DELEGATION_DOMAIN_SEPARATOR));
+        assert_eq!(
+            hex(&message[ii::DELEGATION_DOMAIN_SEPARATOR.len()..]),
+            "2b5fcd5efbbaa1b30b5d938c95ab069c391f6c62ac1a2653d185ce2c56034f62"
+        );
+
+        delegation.targets = Some(vec![Principal::from_slice(&[0, 0, 0, 0, 0, 0, 4, 210])]);
+        assert_eq!(
+            hex(&ii::signed_message(&delegation)[ii::DELEGATION_DOMAIN_SEPARATOR.len()..]),
+            "2c4ccc404e47b37af4df6f5ff4b8be33f0d4c9141070b81d40dc0998ec5499dd"
+        );
+    }
+
+    #[cfg(feature = "hashing")]
+    #[test]
+    fn test_ii_verify_delegation() {
+        use sha2::{Digest, Sha256};
+
+        // Stand-in signature scheme: sha256(public_key || message)
+        fn sign(public_key: &[u8], message: &[u8]) -> Vec<u8> {
+            Sha256::digest([public_key, message].concat()).to_vec()
+        }
+        fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> AuthResult<()> {
+            if sign(public_key, message) == signature {
+                Ok(())
+            } else {
+                Err(AuthError::InvalidSignature("mismatch".to_string()))
+            }
+        }
+
+        let canister = Principal::from_slice(&[0, 0, 0, 0, 0, 0, 4, 210]);
+        let root = vec![1u8, 2, 3];
+        let delegation = ii::Delegation {
+            pubkey: vec![4, 5, 6],
+            expiration: 1_000_000_000,
+            targets: Some(vec![canister]),
+        };
+        let chain = |signature: &[u8]| {
+            format!(
+                concat!(
+                    r#"{{"delegations":[{{"delegation":{{"pubkey":"040506","#,
+                    r#""expiration":"3b9aca00","targets":["{}"]}},"#,
+                    r#""signature":"{}"}}],"publicKey":"{}"}}"#
+                ),
+                hex(canister.as_slice()),
+                hex(signature),
+                hex(&root)
+            )
+        };
+        let good = chain(&sign(&root, &ii::signed_message(&delegation)));
+
+        // Fails closed until a verifier is configured
+        assert!(ii::verify_delegation(good.as_bytes(), canister, 0).is_err());
+        ii::set_signature_verifier(verify);
+
+        let parsed = ii::DelegationChain::from_json(good.as_bytes()).unwrap();
+        assert_eq!(parsed.delegations[0].delegation, delegation);
+        assert_eq!(parsed.session_key(), &[4, 5, 6]);
+
+        assert_eq!(
+            ii::verify_delegation(good.as_bytes(), canister, 999).unwrap(),
+            Principal::self_authenticating(&root)
+        );
+        // Expired, wrong target, bad signature, malformed
+        assert!(ii::verify_delegation(good.as_bytes(), canister, 1_000_000_001).is_err());
+        assert!(ii::verify_delegation(good.as_bytes(), Principal::anonymous(), 0).is_err());
+        assert!(ii::verify_delegation(chain(&[0; 32]).as_bytes(), canister, 0).is_err());
+        let empty = br#"{"delegations":[],"publicKey":"01"}"#;
+        assert!(ii::verify_delegation(empty, canister, 0).is_err());
+
+        // Targets longer than a principal (29 bytes) are rejected, not a panic
+        let overlong = good.replace(&hex(canister.as_slice()), &hex(&[7; 30]));
+        assert!(matches!(
+            ii::DelegationChain::from_json(overlong.as_bytes()),
+            Err(AuthError::InvalidDelegation(_))
+        ));
+    }
 }
\ No newline at end of file
@@ -11,8 +11,13 @@
 #![cfg(feature = "candle")]
 
 use candid::CandidType;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+use crate::storage::{self, StorageRegistry};
 
 // ═══════════════════════════════════════════════════════════════
 //  Generic Model Traits (for ALL model types)
@@ -41,15 +46,139 @@ pub trait CandleModel: Sized {
 }
 
 /// Model metadata
-#[derive(CandidType, Deserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct ModelMetadata {
     pub name: String,
     pub version: String,
+    /// Free-form architecture name (see `arch()` for the parsed form)
     pub architecture: String,
     pub parameters: u64,
     pub context_length: Option<usize>,
 }
 
+impl ModelMetadata {
+    /// Parse `architecture` into an `Architecture`
+    pub fn arch(&self) -> Architecture {
+        self.architecture.parse().unwrap_or_else(|never| match never {})
+    }
+}
+
+/// Known model architectures
+///
+/// Parsing is case-insensitive and never fails: unrecognized names become
+/// `Other` with the original string.
+///
+/// # Example
+/// ```rust,ignore
+/// match model.metadata().arch() {
+///     Architecture::Llama | Architecture::Mistral => load_llama(weights),
+///     Architecture::Other(name) => Err(format!("Unsupported architecture: {}", name)),
+///     _ => Err("Not implemented yet".to_string()),
+/// }
+/// ```
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Architecture {
+    Llama,
+    Qwen2,
+    /// Phi, Phi-2 and Phi-3 (`phi`, `phi2`, `phi3`)
+    Phi,
+    Mistral,
+    Gpt2,
+    Other(String),
+}
+
+impl FromStr for Architecture {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "llama" => Architecture::Llama,
+            "qwen2" => Architecture::Qwen2,
+            "phi" | "phi2" | "phi3" => Architecture::Phi,
+            "mistral" => Architecture::Mistral,
+            "gpt2" => Architecture::Gpt2,
+            _ => Architecture::Other(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Architecture::Llama => write!(f, "llama"),
+            Architecture::Qwen2 => write!(f, "qwen2"),
+            Architecture::Phi => write!(f, "phi"),
+            Architecture::Mistral => write!(f, "mistral"),
+            Architecture::Gpt2 => write!(f, "gpt2"),
+            Architecture::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Memory Usage (best-effort estimates)
+// ═══════════════════════════════════════════════════════════════
+
+/// Current wasm heap size in bytes
+///
+/// This is the size of linear memory (pages * 64 KiB), not the number of
+/// live allocations, so it only ever grows. Returns 0 off-chain.
+pub fn heap_usage() -> usize {
+    #[cfg(target_arch = "wasm32")]
+    {
+        core::arch::wasm32::memory_size(0) * 65536
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        0
+    }
+}
+
+/// Current stable memory size in bytes
+pub fn stable_memory_usage() -> u64 {
+    ic_cdk::stable::stable_size() * 65536
+}
+
+/// Approximate bytes per parameter, guessed from the metadata strings
+///
+/// Looks for common quantization tags (e.g. `q4_k`, `q8_0`, `f16`) in the
+/// name, version and architecture. Defaults to 4 bytes (f32).
+pub fn bytes_per_parameter(metadata: &ModelMetadata) -> f64 {
+    let tags = format!("{} {} {}", metadata.name, metadata.version, metadata.architecture)
+        .to_lowercase();
+
+    if tags.contains("q2") {
+        0.3125
+    } else if tags.contains("q3") {
+        0.4375
+    } else if tags.contains("q4") {
+        0.5625
+    } else if tags.contains("q5") {
+        0.6875
+    } else if tags.contains("q6") {
+        0.8125
+    } else if tags.contains("q8") {
+        1.0625
+    } else if tags.contains("f16") || tags.contains("bf16") {
+        2.0
+    } else {
+        4.0
+    }
+}
+
+/// Approximate memory footprint of a model's weights in bytes
+///
+/// This is an estimate derived from the parameter count and guessed
+/// quantization - it ignores KV caches and activations. Returns `None`
+/// if the parameter count is unknown (zero).
+pub fn estimate_footprint(metadata: &ModelMetadata) -> Option<usize> {
+    if metadata.parameters == 0 {
+        return None;
+    }
+    Some((metadata.parameters as f64 * bytes_per_parameter(metadata)) as usize)
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Model Manager (for managing multiple models)
 // ═══════════════════════════════════════════════════════════════
@@ -137,6 +266,39 @@ impl<T> ModelManager<T> {
     pub fn active_name(&self) -> Option<&str> {
         self.active_model.as_deref()
     }
+
+    /// Save the registered model names and active selection to storage
+    ///
+    /// Only the index is persisted - model weights are expected to live in
+    /// stable storage already and must be reloaded by the caller.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// #[ic_cdk::pre_upgrade]
+    /// fn pre_upgrade() {
+    ///     MANAGER.with(|m| REGISTRY.with(|r| m.borrow().save_index(r, "model_index")))
+    ///         .expect("Failed to save model index");
+    /// }
+    /// ```
+    pub fn save_index<R: StorageRegistry>(
+        &self,
+        registry: &RefCell<R>,
+        key: &str,
+    ) -> Result<(), String> {
+        let mut names = self.list();
+        names.sort();
+        storage::save_candid(registry, key, &(names, self.active_model.clone()))
+    }
+
+    /// Load a previously saved index of model names and active selection
+    ///
+    /// Use this in `post_upgrade` to rebuild the registration list.
+    pub fn load_index<R: StorageRegistry>(
+        registry: &RefCell<R>,
+        key: &str,
+    ) -> Option<(Vec<String>, Option<String>)> {
+        storage::load_candid(registry, key)
+    }
 }
 
 impl<T> Default for ModelManager<T> {
@@ -150,6 +312,7 @@ impl<T> Default for ModelManager<T> {
 // ═══════════════════════════════════════════════════════════════
 
 pub mod gguf {
+    use super::ModelMetadata;
     use candle_core::Device;
     use candle_core::quantized::gguf_file;
     use std::io::Cursor;
@@ -172,11 +335,71 @@ pub mod gguf {
     pub fn cpu_device() -> Device {
         Device::Cpu
     }
+
+    /// Read common GGUF metadata keys into a `ModelMetadata`
+    ///
+    /// Maps `general.name`, `general.version`, `general.architecture`,
+    /// `general.parameter_count` and `<architecture>.context_length`.
+    /// Missing keys fall back to the architecture (for the name), "unknown",
+    /// the summed tensor element count, and `None` respectively. The raw
+    /// architecture string is kept as-is; `ModelMetadata::arch()` parses it.
+    pub fn read_metadata(content: &gguf_file::Content) -> ModelMetadata {
+        let get_string = |key: &str| {
+            content.metadata.get(key)
+                .and_then(|v| v.to_string().ok())
+                .cloned()
+        };
+
+        let architecture = get_string("general.architecture")
+            .unwrap_or_else(|| "unknown".to_string());
+
+        let parameters = content.metadata.get("general.parameter_count")
+            .and_then(|v| v.to_u64().ok())
+            .unwrap_or_else(|| {
+                content.tensor_infos.values()
+                    .map(|info| info.shape.elem_count() as u64)
+                    .sum()
+            });
+
+        let context_length = content.metadata
+            .get(&format!("{}.context_length", architecture))
+            .and_then(|v| v.to_u64().ok())
+            .map(|n| n as usize);
+
+        ModelMetadata {
+            name: get_string("general.name").unwrap_or_else(|| architecture.clone()),
+            version: get_string("general.version").unwrap_or_else(|| "unknown".to_string()),
+            architecture,
+            parameters,
+            context_length,
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Safetensors Helpers (for all model types)
+// ═══════════════════════════════════════════════════════════════
+
+pub mod safetensors {
+    use candle_core::Tensor;
+    use std::collections::HashMap;
+
+    pub use super::gguf::cpu_device;
+
+    /// Load all tensors from safetensors bytes
+    ///
+    /// Safetensors is the default weight format for many Hugging Face models.
+    /// Tensors are loaded onto the CPU device, matching the GGUF helpers.
+    pub fn load_tensors(weights: Vec<u8>) -> Result<HashMap<String, Tensor>, String> {
+        candle_core::safetensors::load_buffer(&weights, &cpu_device())
+            .map_err(|e| format!("Failed to read safetensors: {}", e))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::TestRegistry;
 
     #[test]
     fn test_model_manager() {
@@ -196,4 +419,106 @@ mod tests {
         manager.remove("model1");
         assert_eq!(manager.list().len(), 1);
     }
+
+    #[test]
+    fn test_architecture_parse() {
+        assert_eq!("Qwen2".parse::<Architecture>(), Ok(Architecture::Qwen2));
+        assert_eq!("phi3".parse::<Architecture>(), Ok(Architecture::Phi));
+        assert_eq!(
+            "mamba".parse::<Architecture>(),
+            Ok(Architecture::Other("mamba".to_string()))
+        );
+        assert_eq!(Architecture::Gpt2.to_string(), "gpt2");
+        assert_eq!(Architecture::Other("mamba".to_string()).to_string(), "mamba");
+    }
+
+    #[test]
+    fn test_estimate_footprint() {
+        let mut metadata = ModelMetadata {
+            name: "tiny".to_string(),
+            version: "1".to_string(),
+            architecture: "llama".to_string(),
+            parameters: 1_000,
+            context_length: None,
+        };
+        assert_eq!(estimate_footprint(&metadata), Some(4_000));
+
+        metadata.version = "Q8_0".to_string();
+        assert_eq!(estimate_footprint(&metadata), Some(1_062));
+
+        metadata.parameters = 0;
+        assert_eq!(estimate_footprint(&metadata), None);
+    }
+
+    #[test]
+    fn test_model_manager_index() {
+        let registry = RefCell::new(TestRegistry::default());
+
+        let mut manager: ModelManager<String> = ModelManager::new();
+        manager.register("model2".to_string(), "data2".to_string());
+        manager.register("model1".to_string(), "data1".to_string());
+        manager.set_active("model1").unwrap();
+        manager.save_index(&registry, "index").unwrap();
+
+        let (names, active) = ModelManager::<String>::load_index(&registry, "index").unwrap();
+        assert_eq!(names, vec!["model1".to_string(), "model2".to_string()]);
+        assert_eq!(active, Some("model1".to_string()));
+
+        assert!(ModelManager::<String>::load_index(&registry, "missing").is_none());
+    }
+
+    #[test]
+    fn test_gguf_read_metadata() {
+        use candle_core::quantized::gguf_file::{self, Value};
+        use std::io::Cursor;
+
+        let arch = Value::String("llama".to_string());
+        let name = Value::String("tiny-llama".to_string());
+        let ctx = Value::U32(2048);
+        let params = Value::U64(1_100_000_000);
+
+        let mut cursor = Cursor::new(Vec::new());
+        gguf_file::write(
+            &mut cursor,
+            &[
+                ("general.architecture", &arch),
+                ("general.name", &name),
+                ("llama.context_length", &ctx),
+                ("general.parameter_count", &params),
+            ],
+            &[],
+        )
+        .unwrap();
+
+        let (content, _) = gguf::load_content(cursor.into_inner()).unwrap();
+        let metadata = gguf::read_metadata(&content);
+
+        assert_eq!(metadata.name, "tiny-llama");
+        assert_eq!(metadata.architecture, "llama");
+        assert_eq!(metadata.arch(), Architecture::Llama);
+        assert_eq!(metadata.version, "unknown");
+        assert_eq!(metadata.parameters, 1_100_000_000);
+        assert_eq!(metadata.context_length, Some(2048));
+    }
+
+    #[test]
+    fn test_safetensors_round_trip() {
+        use candle_core::Tensor;
+
+        let device = safetensors::cpu_device();
+        let mut tensors = HashMap::new();
+        tensors.insert(
+            "weight".to_string(),
+            Tensor::new(&[1.0f32, 2.0, 3.0], &device).unwrap(),
+        );
+
+        let path = std::env::temp_dir().join("ic_dev_kit_safetensors_test.safetensors");
+        candle_core::safetensors::save(&tensors, &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let loaded = safetensors::load_tensors(bytes).unwrap();
+        let weight = loaded.get("weight").unwrap();
+        assert_eq!(weight.to_vec1::<f32>().unwrap(), vec![1.0, 2.0, 3.0]);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,79 @@
+// Canister environment accessors
+//
+// Thin wrappers over `ic_cdk::api` used throughout the crate. With the
+// `testing` feature enabled (and in this crate's own unit tests) they return
+// the values set through `crate::testing` first, so guards and time-based
+// logic can be exercised in plain `#[test]`s.
+
+use candid::Principal;
+
+/// Caller of the current message
+pub(crate) fn caller() -> Principal {
+    #[cfg(any(test, feature = "testing"))]
+    if let Some(caller) = crate::testing::mock_caller() {
+        return caller;
+    }
+    ic_cdk::api::msg_caller()
+}
+
+/// Current IC time in nanoseconds
+pub(crate) fn time() -> u64 {
+    #[cfg(any(test, feature = "testing"))]
+    if let Some(now) = crate::testing::mock_time() {
+        return now;
+    }
+    ic_cdk::api::time()
+}
+
+/// Principal of this canister
+pub(crate) fn canister_self() -> Principal {
+    #[cfg(any(test, feature = "testing"))]
+    if let Some(id) = crate::testing::mock_canister_id() {
+        return id;
+    }
+    ic_cdk::api::canister_self()
+}
+
+/// Cycle balance of this canister
+pub(crate) fn cycle_balance() -> u128 {
+    #[cfg(any(test, feature = "testing"))]
+    if let Some(balance) = crate::testing::mock_cycle_balance() {
+        return balance;
+    }
+    ic_cdk::api::canister_cycle_balance()
+}
+
+/// Instructions executed so far in the current message
+pub(crate) fn performance_counter() -> u64 {
+    #[cfg(any(test, feature = "testing"))]
+    if let Some(count) = crate::testing::mock_performance_counter() {
+        return count;
+    }
+    ic_cdk::api::performance_counter(0)
+}
+
+/// Whether `principal` controls this canister
+#[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+pub(crate) fn is_controller(principal: &Principal) -> bool {
+    #[cfg(any(test, feature = "testing"))]
+    if let Some(controllers) = crate::testing::mock_controllers() {
+        return controllers.contains(principal);
+    }
+    ic_cdk::api::is_controller(principal)
+}
+
+/// Write a line to the canister log
+///
+/// Goes to the canistergeek logger with `telemetry` and to the debug output
+/// otherwise. With `testing` the line is captured instead (see
+/// `testing::take_logs`), since both sinks trap outside a canister.
+pub(crate) fn log(message: String) {
+    #[cfg(any(test, feature = "testing"))]
+    crate::testing::capture_log(message);
+
+    #[cfg(all(not(any(test, feature = "testing")), feature = "telemetry"))]
+    canistergeek_ic_rust::logger::log_message(message);
+
+    #[cfg(all(not(any(test, feature = "testing")), not(feature = "telemetry")))]
+    ic_cdk::println!("{}", message);
+}
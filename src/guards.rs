@@ -0,0 +1,102 @@
+// Guard composition for Internet Computer canisters
+//
+// IC CDK guards are plain `fn() -> Result<(), String>` functions referenced by
+// name, so combining them requires generating a new named function. Use the
+// `define_guard!` macro for that, or `any_of` / `all_of` directly.
+
+/// Signature of an IC CDK guard function
+pub type GuardFn = fn() -> Result<(), String>;
+
+/// Pass if any guard passes
+///
+/// On failure, the error lists every guard's error message.
+pub fn any_of(guards: &[GuardFn]) -> Result<(), String> {
+    let mut errors = Vec::new();
+
+    for guard in guards {
+        match guard() {
+            Ok(()) => return Ok(()),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    Err(errors.join("; "))
+}
+
+/// Pass only if all guards pass
+///
+/// Returns the first error encountered.
+pub fn all_of(guards: &[GuardFn]) -> Result<(), String> {
+    for guard in guards {
+        guard()?;
+    }
+    Ok(())
+}
+
+/// Macro to generate a named guard combining other guards
+///
+/// # Example
+/// ```rust,ignore
+/// ic_dev_kit_rs::define_guard!(
+///     combined_admin_or_monitor,
+///     any_of[
+///         ic_dev_kit_rs::auth::is_authorized,
+///         ic_dev_kit_rs::telemetry::is_monitoring_authorized,
+///     ]
+/// );
+///
+/// #[ic_cdk::query(guard = "combined_admin_or_monitor")]
+/// fn get_status() -> String { ... }
+/// ```
+#[macro_export]
+macro_rules! define_guard {
+    ($name:ident, any_of [$($guard:path),+ $(,)?]) => {
+        pub fn $name() -> Result<(), String> {
+            $crate::guards::any_of(&[$($guard),+])
+        }
+    };
+
+    ($name:ident, all_of [$($guard:path),+ $(,)?]) => {
+        pub fn $name() -> Result<(), String> {
+            $crate::guards::all_of(&[$($guard),+])
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allow() -> Result<(), String> {
+        Ok(())
+    }
+
+    fn deny_a() -> Result<(), String> {
+        Err("denied a".to_string())
+    }
+
+    fn deny_b() -> Result<(), String> {
+        Err("denied b".to_string())
+    }
+
+    #[test]
+    fn test_any_of() {
+        assert!(any_of(&[deny_a, allow]).is_ok());
+        assert_eq!(any_of(&[deny_a, deny_b]), Err("denied a; denied b".to_string()));
+    }
+
+    #[test]
+    fn test_all_of() {
+        assert!(all_of(&[allow, allow]).is_ok());
+        assert_eq!(all_of(&[allow, deny_b, deny_a]), Err("denied b".to_string()));
+    }
+
+    #[test]
+    fn test_define_guard() {
+        crate::define_guard!(admin_or_monitor, any_of[deny_a, allow]);
+        crate::define_guard!(admin_and_monitor, all_of[allow, deny_a]);
+
+        assert!(admin_or_monitor().is_ok());
+        assert!(admin_and_monitor().is_err());
+    }
+}
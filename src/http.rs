@@ -1,7 +1,11 @@
 // HTTP handling module for Internet Computer canisters
+use candid::CandidType;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+
+use crate::rate_limit::{RateLimitKey, RateLimiter};
 
 // ═══════════════════════════════════════════════════════════════
 //  Error Types
@@ -29,8 +33,14 @@ pub enum HttpError {
     UnprocessableEntity(String),
     #[error("Forbidden: {0}")]
     Forbidden(String),
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+    #[error("Rate limit exceeded, retry after {retry_after_secs}s")]
+    TooManyRequests { retry_after_secs: u64 },
     #[error("HTTP {status}: {message}")]
     Status { status: u16, message: String },
+    #[error("Validation failed")]
+    ValidationFailed(Vec<FieldError>),
 }
 
 impl HttpError {
@@ -47,13 +57,36 @@ impl HttpError {
             HttpError::Conflict(_) => 409,
             HttpError::UnprocessableEntity(_) => 422,
             HttpError::Forbidden(_) => 403,
+            HttpError::PreconditionFailed(_) => 412,
+            HttpError::TooManyRequests { .. } => 429,
             HttpError::Status { status, .. } => *status,
+            HttpError::ValidationFailed(_) => 422,
         }
     }
 
     /// Convert to HTTP response automatically
     pub fn to_response(&self) -> HttpResponse {
-        error_response(self.status_code(), &self.to_string())
+        match self {
+            HttpError::ValidationFailed(fields) => {
+                let envelope = error_envelope()(self.status_code(), &self.to_string());
+                let body = match serde_json::from_str::<serde_json::Value>(&envelope) {
+                    Ok(serde_json::Value::Object(mut object)) => {
+                        object.insert("fields".to_string(), serde_json::json!(fields));
+                        serde_json::Value::Object(object).to_string()
+                    }
+                    _ => envelope,
+                };
+                json_response(self.status_code(), body)
+            }
+            HttpError::TooManyRequests { retry_after_secs } => {
+                let mut response = error_response(self.status_code(), &self.to_string());
+                response
+                    .headers
+                    .push(("Retry-After".to_string(), retry_after_secs.to_string()));
+                response
+            }
+            _ => error_response(self.status_code(), &self.to_string()),
+        }
     }
 
     // Convenience constructors
@@ -84,6 +117,10 @@ impl HttpError {
         HttpError::Forbidden(msg.into())
     }
 
+    pub fn precondition_failed(msg: impl Into<String>) -> Self {
+        HttpError::PreconditionFailed(msg.into())
+    }
+
     pub fn internal_error(msg: impl Into<String>) -> Self {
         HttpError::InternalError(msg.into())
     }
@@ -98,6 +135,39 @@ impl HttpError {
 
 pub type HttpResult<T> = Result<T, HttpError>;
 
+/// A validation failure for a single field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Semantic validation for parsed request bodies
+///
+/// # Example
+/// ```rust,ignore
+/// impl Validate for CreateUser {
+///     fn validate(&self) -> Result<(), Vec<FieldError>> {
+///         if self.name.is_empty() {
+///             return Err(vec![FieldError::new("name", "must not be empty")]);
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait Validate {
+    fn validate(&self) -> Result<(), Vec<FieldError>>;
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  HTTP Types
 // ═══════════════════════════════════════════════════════════════
@@ -122,7 +192,7 @@ pub struct HttpResponse {
 }
 
 /// HTTP method enumeration
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum HttpMethod {
     GET,
     POST,
@@ -160,6 +230,93 @@ impl HttpMethod {
     }
 }
 
+impl From<HttpMethod> for &'static str {
+    fn from(method: HttpMethod) -> Self {
+        method.as_str()
+    }
+}
+
+#[cfg(feature = "http-interop")]
+impl TryFrom<&::http::Method> for HttpMethod {
+    type Error = HttpError;
+
+    fn try_from(method: &::http::Method) -> Result<Self, Self::Error> {
+        HttpMethod::from_str(method.as_str()).ok_or(HttpError::MethodNotAllowed)
+    }
+}
+
+#[cfg(feature = "http-interop")]
+impl From<HttpMethod> for ::http::Method {
+    fn from(method: HttpMethod) -> Self {
+        match method {
+            HttpMethod::GET => ::http::Method::GET,
+            HttpMethod::POST => ::http::Method::POST,
+            HttpMethod::PUT => ::http::Method::PUT,
+            HttpMethod::DELETE => ::http::Method::DELETE,
+            HttpMethod::PATCH => ::http::Method::PATCH,
+            HttpMethod::OPTIONS => ::http::Method::OPTIONS,
+            HttpMethod::HEAD => ::http::Method::HEAD,
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  ic-cdk Type Conversions
+// ═══════════════════════════════════════════════════════════════
+
+/// HTTP request as the HTTP gateway passes it to `http_request`
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct GatewayRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub certificate_version: Option<u16>,
+}
+
+/// HTTP response in the shape the HTTP gateway expects back
+///
+/// `upgrade: Some(true)` from a query asks the gateway to retry the request
+/// as an `http_request_update` call.
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct GatewayResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub upgrade: Option<bool>,
+}
+
+/// Build an `HttpRequest` from a gateway request
+///
+/// Any method is passed through as-is, so the result can go straight to
+/// `Router::handle`.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::query]
+/// fn http_request(req: GatewayRequest) -> GatewayResponse {
+///     ROUTER.with(|r| http::to_ic_response(r.handle(http::from_ic_request(&req))))
+/// }
+/// ```
+pub fn from_ic_request(request: &GatewayRequest) -> HttpRequest {
+    HttpRequest {
+        method: request.method.clone(),
+        url: request.url.clone(),
+        headers: request.headers.clone(),
+        body: request.body.clone(),
+    }
+}
+
+/// Convert an `HttpResponse` into a gateway response, keeping `upgrade`
+pub fn to_ic_response(response: HttpResponse) -> GatewayResponse {
+    GatewayResponse {
+        status_code: response.status_code,
+        headers: response.headers,
+        body: response.body,
+        upgrade: response.upgrade,
+    }
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Response Builders
 // ═══════════════════════════════════════════════════════════════
@@ -176,11 +333,78 @@ pub fn json_response(status_code: u16, body: String) -> HttpResponse {
     }
 }
 
-pub fn error_response(status_code: u16, error: &str) -> HttpResponse {
-    json_response(
+/// Build an HTML response (`text/html; charset=utf-8`)
+pub fn html_response(status_code: u16, html: String) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![
+            ("Content-Type".to_string(), "text/html; charset=utf-8".to_string()),
+            ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
+        ],
+        body: html.into_bytes(),
+        upgrade: None,
+    }
+}
+
+/// Build a plain text response (`text/plain; charset=utf-8`)
+pub fn text_response(status_code: u16, body: String) -> HttpResponse {
+    HttpResponse {
         status_code,
-        format!(r#"{{"error":"{}"}}"#, escape_json(error)),
-    )
+        headers: vec![
+            ("Content-Type".to_string(), "text/plain; charset=utf-8".to_string()),
+            ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
+        ],
+        body: body.into_bytes(),
+        upgrade: None,
+    }
+}
+
+/// Build a JSON error response
+///
+/// The body is formatted by the error envelope hook, `{"error": "..."}` by
+/// default (see `set_error_envelope`).
+pub fn error_response(status_code: u16, error: &str) -> HttpResponse {
+    json_response(status_code, error_envelope()(status_code, error))
+}
+
+/// Formats an error body from a status code and message
+pub type ErrorEnvelopeFn = fn(u16, &str) -> String;
+
+thread_local! {
+    static ERROR_ENVELOPE: Cell<ErrorEnvelopeFn> = Cell::new(default_error_envelope);
+}
+
+/// Default error body: `{"error": "..."}`
+///
+/// The message is serialized with `serde_json`, so any string (control
+/// characters, quotes, non-ASCII) produces valid JSON.
+pub fn default_error_envelope(_status_code: u16, error: &str) -> String {
+    serde_json::json!({ "error": error }).to_string()
+}
+
+/// Set how `error_response` and `HttpError::to_response` format error bodies
+///
+/// For `HttpError::ValidationFailed`, the field list is added as `fields`
+/// when the envelope is a JSON object.
+///
+/// # Example
+/// ```rust,ignore
+/// fn envelope(status: u16, message: &str) -> String {
+///     serde_json::json!({ "success": false, "message": message, "code": status }).to_string()
+/// }
+///
+/// #[ic_cdk::init]
+/// fn init() {
+///     http::set_error_envelope(envelope);
+/// }
+/// ```
+pub fn set_error_envelope(envelope: ErrorEnvelopeFn) {
+    ERROR_ENVELOPE.with(|e| e.set(envelope));
+}
+
+/// Get the current error envelope hook
+pub fn error_envelope() -> ErrorEnvelopeFn {
+    ERROR_ENVELOPE.with(|e| e.get())
 }
 
 pub fn success_response<T: Serialize>(data: &T) -> HttpResult<HttpResponse> {
@@ -189,6 +413,38 @@ pub fn success_response<T: Serialize>(data: &T) -> HttpResult<HttpResponse> {
     Ok(json_response(200, json))
 }
 
+/// Build a 200 JSON array response, serializing items one at a time
+///
+/// Items are written straight into the response body, so a large list never
+/// exists as both the source collection and a separate JSON `String`.
+///
+/// # Example
+/// ```rust,ignore
+/// fn list_tasks(_req: HttpRequest) -> HttpResult<HttpResponse> {
+///     TASKS.with(|t| json_array_response(t.borrow().values()))
+/// }
+/// ```
+pub fn json_array_response<T, I>(items: I) -> HttpResult<HttpResponse>
+where
+    T: Serialize,
+    I: Iterator<Item = T>,
+{
+    let mut body = vec![b'['];
+
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            body.push(b',');
+        }
+        serde_json::to_writer(&mut body, &item)
+            .map_err(|e| HttpError::SerializationError(format!("JSON serialization error: {}", e)))?;
+    }
+    body.push(b']');
+
+    let mut response = json_response(200, String::new());
+    response.body = body;
+    Ok(response)
+}
+
 pub fn upgrade_response() -> HttpResponse {
     HttpResponse {
         status_code: 204,
@@ -199,13 +455,21 @@ pub fn upgrade_response() -> HttpResponse {
 }
 
 pub fn cors_preflight_response() -> HttpResponse {
+    cors_preflight_response_for("GET, POST, PUT, DELETE, PATCH, OPTIONS")
+}
+
+/// CORS preflight response allowing only `methods` (e.g. `"GET, HEAD, OPTIONS"`)
+///
+/// Sets both `Allow` and `Access-Control-Allow-Methods`.
+pub fn cors_preflight_response_for(methods: &str) -> HttpResponse {
     HttpResponse {
         status_code: 204,
         headers: vec![
+            ("Allow".to_string(), methods.to_string()),
             ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
             (
                 "Access-Control-Allow-Methods".to_string(),
-                "GET, POST, PUT, DELETE, PATCH, OPTIONS".to_string(),
+                methods.to_string(),
             ),
             (
                 "Access-Control-Allow-Headers".to_string(),
@@ -217,23 +481,22 @@ pub fn cors_preflight_response() -> HttpResponse {
     }
 }
 
-// Helper to escape JSON strings
-fn escape_json(s: &str) -> String {
-    s.replace('\\', "\\\\")
-        .replace('"', "\\\"")
-        .replace('\n', "\\n")
-        .replace('\r', "\\r")
-        .replace('\t', "\\t")
-}
-
 // ═══════════════════════════════════════════════════════════════
 //  JSON Utilities
 // ═══════════════════════════════════════════════════════════════
 
+/// Parse a JSON request body
+///
+/// An empty (or whitespace-only) body is a `BadRequest("empty request body")`
+/// rather than a serde EOF error.
 pub fn parse_json<T>(body: &[u8]) -> HttpResult<T>
 where
     T: for<'de> Deserialize<'de>,
 {
+    if is_empty_body(body) {
+        return Err(HttpError::BadRequest("empty request body".to_string()));
+    }
+
     let body_str = String::from_utf8(body.to_vec())
         .map_err(|_| HttpError::InvalidRequest("Invalid UTF-8 in request body".to_string()))?;
 
@@ -241,6 +504,40 @@ where
         .map_err(|e| HttpError::InvalidRequest(format!("JSON parse error: {}", e)))
 }
 
+/// Parse a JSON request body, using `T::default()` when the body is empty
+///
+/// # Example
+/// ```rust,ignore
+/// // POST /search with no body searches with default options
+/// let options: SearchOptions = http::parse_json_or_default(&req.body)?;
+/// ```
+pub fn parse_json_or_default<T>(body: &[u8]) -> HttpResult<T>
+where
+    T: Default + for<'de> Deserialize<'de>,
+{
+    if is_empty_body(body) {
+        return Ok(T::default());
+    }
+    parse_json(body)
+}
+
+fn is_empty_body(body: &[u8]) -> bool {
+    body.iter().all(|b| b.is_ascii_whitespace())
+}
+
+/// Parse a JSON body and run its `Validate` implementation
+///
+/// Malformed JSON is a 400 (as with `parse_json`); validation failures are a
+/// 422 whose body lists the field errors.
+pub fn parse_and_validate<T>(body: &[u8]) -> HttpResult<T>
+where
+    T: for<'de> Deserialize<'de> + Validate,
+{
+    let data: T = parse_json(body)?;
+    data.validate().map_err(HttpError::ValidationFailed)?;
+    Ok(data)
+}
+
 pub fn to_json<T>(data: &T) -> HttpResult<String>
 where
     T: Serialize,
@@ -266,6 +563,14 @@ pub fn extract_path(url: &str) -> &str {
     url.split('?').next().unwrap_or(url)
 }
 
+/// Remove a single trailing slash from a path (the root path `/` is kept)
+pub fn trim_trailing_slash(path: &str) -> &str {
+    match path.strip_suffix('/') {
+        Some("") | None => path,
+        Some(trimmed) => trimmed,
+    }
+}
+
 /// Extract query parameters from a URL
 pub fn extract_query_params(url: &str) -> HashMap<String, String> {
     let mut params = HashMap::new();
@@ -281,8 +586,42 @@ pub fn extract_query_params(url: &str) -> HashMap<String, String> {
     params
 }
 
+/// Decode a percent-encoded query value (`+` is a space)
+///
+/// Invalid escapes are kept as-is; invalid UTF-8 is replaced.
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => out.push(b' '),
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
 /// Check if a path matches a pattern (with wildcard support)
 pub fn matches_pattern(path: &str, pattern: &str) -> bool {
+    matches_pattern_with_case(path, pattern, true)
+}
+
+/// Check if a path matches a pattern, optionally ignoring ASCII case on literal segments
+pub fn matches_pattern_with_case(path: &str, pattern: &str, case_sensitive: bool) -> bool {
     let path_parts: Vec<&str> = path.split('/').collect();
     let pattern_parts: Vec<&str> = pattern.split('/').collect();
 
@@ -297,7 +636,12 @@ pub fn matches_pattern(path: &str, pattern: &str) -> bool {
         if pattern_part.starts_with(':') {
             continue;
         }
-        if path_part != pattern_part {
+        let matched = if case_sensitive {
+            path_part == pattern_part
+        } else {
+            path_part.eq_ignore_ascii_case(pattern_part)
+        };
+        if !matched {
             return false;
         }
     }
@@ -307,6 +651,17 @@ pub fn matches_pattern(path: &str, pattern: &str) -> bool {
 
 /// Extract path parameters from a pattern match
 pub fn extract_params(path: &str, pattern: &str) -> HashMap<String, String> {
+    extract_params_with_case(path, pattern, true)
+}
+
+/// Extract path parameters, optionally ignoring ASCII case on literal segments
+///
+/// Parameter values are always captured in their original case.
+pub fn extract_params_with_case(
+    path: &str,
+    pattern: &str,
+    case_sensitive: bool,
+) -> HashMap<String, String> {
     let mut params = HashMap::new();
     let path_parts: Vec<&str> = path.split('/').collect();
     let pattern_parts: Vec<&str> = pattern.split('/').collect();
@@ -319,7 +674,15 @@ pub fn extract_params(path: &str, pattern: &str) -> HashMap<String, String> {
         if pattern_part.starts_with(':') {
             let param_name = &pattern_part[1..];
             params.insert(param_name.to_string(), path_part.to_string());
-        } else if path_part != pattern_part {
+            continue;
+        }
+
+        let matched = if case_sensitive {
+            path_part == pattern_part
+        } else {
+            path_part.eq_ignore_ascii_case(pattern_part)
+        };
+        if !matched {
             return HashMap::new();
         }
     }
@@ -350,6 +713,60 @@ pub fn extract_bearer_token(headers: &[(String, String)]) -> Option<String> {
     })
 }
 
+// ═══════════════════════════════════════════════════════════════
+//  Content Types
+// ═══════════════════════════════════════════════════════════════
+
+/// Guess a `Content-Type` for stored bytes
+///
+/// Uses the extension of `filename` first, then sniffs magic bytes for a few
+/// formats, falling back to `application/octet-stream`.
+///
+/// # Example
+/// ```rust,ignore
+/// let content_type = http::guess_content_type("weights.bin", &data);
+/// ```
+pub fn guess_content_type(filename: &str, data: &[u8]) -> &'static str {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let by_extension = match extension.as_str() {
+        "json" => Some("application/json"),
+        "html" | "htm" => Some("text/html; charset=utf-8"),
+        "txt" => Some("text/plain; charset=utf-8"),
+        "css" => Some("text/css"),
+        "js" | "mjs" => Some("text/javascript"),
+        "csv" => Some("text/csv"),
+        "svg" => Some("image/svg+xml"),
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "ico" => Some("image/x-icon"),
+        "pdf" => Some("application/pdf"),
+        "wasm" => Some("application/wasm"),
+        "zip" => Some("application/zip"),
+        "gz" => Some("application/gzip"),
+        _ => None,
+    };
+
+    if let Some(content_type) = by_extension {
+        return content_type;
+    }
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if data.starts_with(b"%PDF-") {
+        "application/pdf"
+    } else {
+        "application/octet-stream"
+    }
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  HTTP Status Codes
 // ═══════════════════════════════════════════════════════════════
@@ -359,154 +776,1670 @@ pub mod status {
     pub const CREATED: u16 = 201;
     pub const ACCEPTED: u16 = 202;
     pub const NO_CONTENT: u16 = 204;
+    pub const PARTIAL_CONTENT: u16 = 206;
+    pub const NOT_MODIFIED: u16 = 304;
     pub const BAD_REQUEST: u16 = 400;
     pub const UNAUTHORIZED: u16 = 401;
     pub const FORBIDDEN: u16 = 403;
     pub const NOT_FOUND: u16 = 404;
     pub const METHOD_NOT_ALLOWED: u16 = 405;
     pub const CONFLICT: u16 = 409;
+    pub const PRECONDITION_FAILED: u16 = 412;
+    pub const RANGE_NOT_SATISFIABLE: u16 = 416;
     pub const UNPROCESSABLE_ENTITY: u16 = 422;
+    pub const TOO_MANY_REQUESTS: u16 = 429;
     pub const INTERNAL_SERVER_ERROR: u16 = 500;
     pub const BAD_GATEWAY: u16 = 502;
     pub const SERVICE_UNAVAILABLE: u16 = 503;
 }
 
 // ═══════════════════════════════════════════════════════════════
-//  Result Extension Trait
+//  Request IDs
 // ═══════════════════════════════════════════════════════════════
 
-/// Extension trait to convert results to HTTP responses
-pub trait IntoHttpResponse {
-    fn into_http_response(self) -> HttpResult<HttpResponse>;
+/// Header carrying the request ID
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+thread_local! {
+    static REQUEST_COUNTER: Cell<u64> = Cell::new(0);
 }
 
-impl<T: Serialize> IntoHttpResponse for Result<T, HttpError> {
-    fn into_http_response(self) -> HttpResult<HttpResponse> {
-        match self {
-            Ok(data) => success_response(&data),
-            Err(e) => Ok(e.to_response()),
-        }
+/// Get the request ID from `X-Request-Id`, or generate one
+///
+/// Generated IDs are `{time}-{counter}` in hex. Counter increments made during
+/// query calls are discarded, so prefer client-supplied IDs for queries.
+pub fn request_id(request: &HttpRequest) -> String {
+    match get_header(&request.headers, REQUEST_ID_HEADER) {
+        Some(id) if !id.is_empty() => id.to_string(),
+        _ => generate_request_id(crate::env::time()),
     }
 }
 
+/// Set the `X-Request-Id` header on a response (replacing any existing one)
+pub fn set_request_id(response: &mut HttpResponse, id: &str) {
+    response
+        .headers
+        .retain(|(k, _)| !k.eq_ignore_ascii_case(REQUEST_ID_HEADER));
+    response
+        .headers
+        .push((REQUEST_ID_HEADER.to_string(), id.to_string()));
+}
+
+fn generate_request_id(now: u64) -> String {
+    let count = REQUEST_COUNTER.with(|c| {
+        let count = c.get();
+        c.set(count.wrapping_add(1));
+        count
+    });
+    format!("{:x}-{:x}", now, count)
+}
+
+fn log_request(id: &str, method: &str, path: &str) {
+    #[cfg(feature = "telemetry")]
+    crate::telemetry::log_structured(
+        crate::telemetry::LogLevel::Info,
+        "http request",
+        &[("request_id", id), ("method", method), ("path", path)],
+    );
+
+    #[cfg(not(feature = "telemetry"))]
+    crate::env::log(format!("[INFO] http request {} {} request_id={}", method, path, id));
+}
+
 // ═══════════════════════════════════════════════════════════════
-//  Simple Router Implementation
+//  ETags and Conditional Requests
 // ═══════════════════════════════════════════════════════════════
 
-pub type HandlerFn = fn(HttpRequest) -> HttpResult<HttpResponse>;
+/// Compute a quoted ETag for a body (64-bit FNV-1a, hex encoded)
+///
+/// The hash is stable across builds and upgrades, so clients can keep
+/// revalidating cached responses after a canister upgrade.
+pub fn etag(body: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in body {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("\"{:016x}\"", hash)
+}
 
-pub struct Router {
-    routes: HashMap<(HttpMethod, String), HandlerFn>,
+/// Set the `ETag` header on a response (replacing any existing one)
+pub fn set_etag(response: &mut HttpResponse, etag: &str) {
+    response.headers.retain(|(k, _)| !k.eq_ignore_ascii_case("ETag"));
+    response.headers.push(("ETag".to_string(), etag.to_string()));
 }
 
-impl Router {
-    pub fn new() -> Self {
-        Self {
-            routes: HashMap::new(),
-        }
-    }
+/// Check whether `If-None-Match` matches `etag` (weak comparison)
+///
+/// Returns true when the handler can answer with `not_modified_response`.
+pub fn check_not_modified(headers: &[(String, String)], etag: &str) -> bool {
+    let Some(if_none_match) = get_header(headers, "If-None-Match") else {
+        return false;
+    };
 
-    pub fn add_route(&mut self, method: HttpMethod, path: impl Into<String>, handler: HandlerFn) {
-        self.routes.insert((method, path.into()), handler);
-    }
+    let strip_weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = strip_weak(etag);
 
-    pub fn get(&mut self, path: impl Into<String>, handler: HandlerFn) {
-        self.add_route(HttpMethod::GET, path, handler);
-    }
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || strip_weak(tag) == etag)
+}
 
-    pub fn post(&mut self, path: impl Into<String>, handler: HandlerFn) {
-        self.add_route(HttpMethod::POST, path, handler);
+/// Require `If-Match` (if present) to match `current_etag`
+///
+/// Uses strong comparison, so weak (`W/`) tags never match; `*` matches any
+/// current representation. Returns `Ok(())` when the header is absent, so
+/// clients that don't send it keep last-write-wins semantics.
+///
+/// # Example
+/// ```rust,ignore
+/// let current = http::etag(&stored_bytes);
+/// http::require_if_match(&req.headers, &current)?;
+/// storage::save_bytes(&REGISTRY, key, req.body.clone())?;
+/// ```
+pub fn require_if_match(headers: &[(String, String)], current_etag: &str) -> HttpResult<()> {
+    let Some(if_match) = get_header(headers, "If-Match") else {
+        return Ok(());
+    };
+
+    let current = current_etag.trim();
+    let matches = !current.starts_with("W/")
+        && if_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag == current);
+
+    if matches {
+        Ok(())
+    } else {
+        Err(HttpError::precondition_failed("ETag does not match If-Match"))
     }
+}
 
-    pub fn put(&mut self, path: impl Into<String>, handler: HandlerFn) {
-        self.add_route(HttpMethod::PUT, path, handler);
+/// Build a 304 response with an empty body
+pub fn not_modified_response(etag: &str) -> HttpResponse {
+    HttpResponse {
+        status_code: status::NOT_MODIFIED,
+        headers: vec![("ETag".to_string(), etag.to_string())],
+        body: vec![],
+        upgrade: None,
     }
+}
 
-    pub fn delete(&mut self, path: impl Into<String>, handler: HandlerFn) {
-        self.add_route(HttpMethod::DELETE, path, handler);
+// ═══════════════════════════════════════════════════════════════
+//  Range Requests
+// ═══════════════════════════════════════════════════════════════
+
+/// Parse a single `Range: bytes=...` header into an inclusive byte range
+///
+/// Supports `bytes=start-end`, open-ended `bytes=start-` and suffix
+/// `bytes=-len` forms; `end` is clamped to the last byte. Returns `None` if the
+/// header is absent, malformed, has multiple ranges, or is not satisfiable.
+pub fn parse_range(headers: &[(String, String)], total_len: u64) -> Option<(u64, u64)> {
+    let spec = get_header(headers, "Range")?.trim().strip_prefix("bytes=")?;
+    if total_len == 0 || spec.contains(',') {
+        return None;
     }
 
-    pub fn handle(&self, request: HttpRequest) -> HttpResponse {
-        // Handle CORS preflight
-        if request.method.to_uppercase() == "OPTIONS" {
-            return cors_preflight_response();
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return None;
+            }
+            (total_len.saturating_sub(suffix), total_len - 1)
+        }
+        (start, "") => (start.parse().ok()?, total_len - 1),
+        (start, end) => {
+            let end: u64 = end.parse().ok()?;
+            (start.parse().ok()?, end.min(total_len - 1))
         }
+    };
 
-        let method = match HttpMethod::from_str(&request.method) {
-            Some(m) => m,
-            None => return HttpError::MethodNotAllowed.to_response(),
-        };
+    if start > end || start >= total_len {
+        return None;
+    }
 
-        let path = extract_path(&request.url);
+    Some((start, end))
+}
 
-        // Try exact match first
-        if let Some(handler) = self.routes.get(&(method.clone(), path.to_string())) {
-            return handler(request).unwrap_or_else(|e| e.to_response());
-        }
+/// Build a 206 response for the inclusive range `start..=end` of `full`
+///
+/// Returns 416 with `Content-Range: bytes */len` if the range is invalid.
+pub fn range_response(full: &[u8], start: u64, end: u64) -> HttpResponse {
+    let total_len = full.len() as u64;
 
-        // Try pattern matching
-        for ((route_method, route_path), handler) in &self.routes {
-            if route_method == &method && matches_pattern(path, route_path) {
-                return handler(request).unwrap_or_else(|e| e.to_response());
-            }
-        }
+    if start > end || end >= total_len {
+        return range_not_satisfiable_response(total_len);
+    }
 
-        HttpError::NotFound.to_response()
+    HttpResponse {
+        status_code: status::PARTIAL_CONTENT,
+        headers: vec![
+            ("Content-Type".to_string(), "application/octet-stream".to_string()),
+            (
+                "Content-Range".to_string(),
+                format!("bytes {}-{}/{}", start, end, total_len),
+            ),
+            ("Accept-Ranges".to_string(), "bytes".to_string()),
+        ],
+        body: full[start as usize..=end as usize].to_vec(),
+        upgrade: None,
     }
 }
 
-impl Default for Router {
-    fn default() -> Self {
-        Self::new()
+fn range_not_satisfiable_response(total_len: u64) -> HttpResponse {
+    HttpResponse {
+        status_code: status::RANGE_NOT_SATISFIABLE,
+        headers: vec![
+            ("Content-Range".to_string(), format!("bytes */{}", total_len)),
+            ("Accept-Ranges".to_string(), "bytes".to_string()),
+        ],
+        body: vec![],
+        upgrade: None,
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_http_error_status_codes() {
-        assert_eq!(HttpError::BadRequest("test".to_string()).status_code(), 400);
-        assert_eq!(HttpError::Conflict("test".to_string()).status_code(), 409);
-        assert_eq!(
-            HttpError::UnprocessableEntity("test".to_string()).status_code(),
-            422
-        );
-        assert_eq!(
-            HttpError::custom_status(418, "I'm a teapot").status_code(),
-            418
-        );
+/// Serve `full` honouring an optional `Range` header
+///
+/// No (or a multi-range) `Range` header gives a 200 with the whole body, a
+/// satisfiable range gives a 206, and an unsatisfiable one gives a 416.
+///
+/// # Example
+/// ```rust,ignore
+/// fn download_file(req: HttpRequest) -> HttpResult<HttpResponse> {
+///     let data = REGISTRY.with(|r| storage::load_bytes(r, "model.gguf"))
+///         .ok_or_else(|| HttpError::not_found("File not found"))?;
+///     Ok(http::serve_range(&req.headers, &data))
+/// }
+/// ```
+pub fn serve_range(headers: &[(String, String)], full: &[u8]) -> HttpResponse {
+    let total_len = full.len() as u64;
+
+    match get_header(headers, "Range") {
+        Some(range) if !range.contains(',') => match parse_range(headers, total_len) {
+            Some((start, end)) => range_response(full, start, end),
+            None => range_not_satisfiable_response(total_len),
+        },
+        _ => HttpResponse {
+            status_code: status::OK,
+            headers: vec![
+                ("Content-Type".to_string(), "application/octet-stream".to_string()),
+                ("Accept-Ranges".to_string(), "bytes".to_string()),
+            ],
+            body: full.to_vec(),
+            upgrade: None,
+        },
     }
+}
 
-    #[test]
-    fn test_http_method_from_str() {
-        assert_eq!(HttpMethod::from_str("GET"), Some(HttpMethod::GET));
-        assert_eq!(HttpMethod::from_str("post"), Some(HttpMethod::POST));
-        assert_eq!(HttpMethod::from_str("INVALID"), None);
-    }
+// ═══════════════════════════════════════════════════════════════
+//  Server-Sent Events
+// ═══════════════════════════════════════════════════════════════
 
-    #[test]
-    fn test_extract_path() {
-        assert_eq!(extract_path("/api/test"), "/api/test");
-        assert_eq!(extract_path("/api/test?param=value"), "/api/test");
-        assert_eq!(
-            extract_path("/api/test?param1=value1&param2=value2"),
-            "/api/test"
-        );
+pub mod sse {
+    use super::HttpResponse;
+
+    /// Format a single SSE frame
+    ///
+    /// Multi-line data is split into one `data:` line per line, as required by
+    /// the SSE format.
+    pub fn sse_frame(event: Option<&str>, data: &str) -> String {
+        let mut frame = String::new();
+        if let Some(event) = event {
+            frame.push_str(&format!("event: {}\n", event));
+        }
+        for line in data.lines() {
+            frame.push_str(&format!("data: {}\n", line));
+        }
+        if data.is_empty() {
+            frame.push_str("data: \n");
+        }
+        frame.push('\n');
+        frame
     }
 
-    #[test]
-    fn test_extract_query_params() {
-        let params = extract_query_params("/api/test?foo=bar&baz=qux");
-        assert_eq!(params.get("foo"), Some(&"bar".to_string()));
-        assert_eq!(params.get("baz"), Some(&"qux".to_string()));
+    /// Builder accumulating SSE frames into a `text/event-stream` response
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let mut sse = SseBuilder::new();
+    /// for token in tokens {
+    ///     sse.data(&token);
+    /// }
+    /// sse.event("done", "");
+    /// sse.build()
+    /// ```
+    #[derive(Debug, Clone, Default)]
+    pub struct SseBuilder {
+        body: String,
     }
 
-    #[test]
-    fn test_path_matching() {
-        assert!(matches_pattern("/api/test", "/api/test"));
-        assert!(matches_pattern("/api/test", "/api/*"));
+    impl SseBuilder {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Append an unnamed frame
+        pub fn data(&mut self, data: &str) -> &mut Self {
+            self.body.push_str(&sse_frame(None, data));
+            self
+        }
+
+        /// Append a named event frame
+        pub fn event(&mut self, event: &str, data: &str) -> &mut Self {
+            self.body.push_str(&sse_frame(Some(event), data));
+            self
+        }
+
+        /// Build the response
+        pub fn build(&self) -> HttpResponse {
+            HttpResponse {
+                status_code: 200,
+                headers: vec![
+                    ("Content-Type".to_string(), "text/event-stream".to_string()),
+                    ("Cache-Control".to_string(), "no-cache".to_string()),
+                    ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
+                ],
+                body: self.body.clone().into_bytes(),
+                upgrade: None,
+            }
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Health Checks
+// ═══════════════════════════════════════════════════════════════
+
+/// A named readiness check; returns true when healthy
+pub type HealthCheckFn = fn() -> bool;
+
+thread_local! {
+    static HEALTH_CHECKS: RefCell<Vec<(String, HealthCheckFn)>> = RefCell::new(Vec::new());
+}
+
+/// Set the checks run by `health_handler`
+///
+/// Non-capturing closures work as checks. `auth::is_initialized` and the
+/// `is_model_loaded` endpoint generated by `generate_model_endpoints!` are
+/// ready-made ones.
+///
+/// # Example
+/// ```rust,ignore
+/// http::set_health_checks(vec![
+///     ("auth", auth::is_initialized),
+///     ("model", is_model_loaded),
+///     ("storage", || REGISTRY.with(|r| storage::exists(r, "config"))),
+/// ]);
+///
+/// router.get("/health", http::health_handler);
+/// router.get("/ready", http::health_handler);
+/// ```
+pub fn set_health_checks(checks: Vec<(&str, HealthCheckFn)>) {
+    let checks = checks
+        .into_iter()
+        .map(|(name, check)| (name.to_string(), check))
+        .collect();
+    HEALTH_CHECKS.with(|h| *h.borrow_mut() = checks);
+}
+
+/// Run `checks` and report the result
+///
+/// Returns 200 with `{"status":"ok","checks":{"name":true,...}}` when all pass,
+/// otherwise 503 with `"status":"unavailable"` and the failing names under
+/// `"failing"`. No checks counts as healthy.
+pub fn health_response(checks: &[(String, HealthCheckFn)]) -> HttpResponse {
+    let mut results = serde_json::Map::new();
+    let mut failing = Vec::new();
+
+    for (name, check) in checks {
+        let passed = check();
+        if !passed {
+            failing.push(name.clone());
+        }
+        results.insert(name.clone(), serde_json::Value::Bool(passed));
+    }
+
+    let body = if failing.is_empty() {
+        serde_json::json!({ "status": "ok", "checks": results })
+    } else {
+        serde_json::json!({ "status": "unavailable", "checks": results, "failing": failing })
+    };
+    let status_code = if failing.is_empty() {
+        status::OK
+    } else {
+        status::SERVICE_UNAVAILABLE
+    };
+
+    json_response(status_code, body.to_string())
+}
+
+/// Handler running the checks set with `set_health_checks`
+pub fn health_handler(_request: HttpRequest) -> HttpResult<HttpResponse> {
+    Ok(HEALTH_CHECKS.with(|h| health_response(&h.borrow())))
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Result Extension Trait
+// ═══════════════════════════════════════════════════════════════
+
+/// Extension trait to convert results to HTTP responses
+pub trait IntoHttpResponse {
+    fn into_http_response(self) -> HttpResult<HttpResponse>;
+}
+
+impl<T: Serialize> IntoHttpResponse for Result<T, HttpError> {
+    fn into_http_response(self) -> HttpResult<HttpResponse> {
+        match self {
+            Ok(data) => success_response(&data),
+            Err(e) => Ok(e.to_response()),
+        }
+    }
+}
+
+/// Plain `String` errors become a JSON error with the status set by
+/// `set_string_error_status` (default 500)
+///
+/// # Example
+/// ```rust,ignore
+/// fn get_profile(req: HttpRequest) -> HttpResult<HttpResponse> {
+///     profiles::load(&req.url).into_http_response()
+/// }
+/// ```
+impl<T: Serialize> IntoHttpResponse for Result<T, String> {
+    fn into_http_response(self) -> HttpResult<HttpResponse> {
+        match self {
+            Ok(data) => success_response(&data),
+            Err(e) => Ok(error_response(string_error_status(), &e)),
+        }
+    }
+}
+
+thread_local! {
+    static STRING_ERROR_STATUS: Cell<u16> = Cell::new(status::INTERNAL_SERVER_ERROR);
+}
+
+/// Set the status used for `Err(String)` in `IntoHttpResponse` (default 500)
+pub fn set_string_error_status(status_code: u16) {
+    STRING_ERROR_STATUS.with(|s| s.set(status_code));
+}
+
+/// Get the status used for `Err(String)` in `IntoHttpResponse`
+pub fn string_error_status() -> u16 {
+    STRING_ERROR_STATUS.with(|s| s.get())
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Simple Router Implementation
+// ═══════════════════════════════════════════════════════════════
+
+pub type HandlerFn = fn(HttpRequest) -> HttpResult<HttpResponse>;
+
+/// Hook called with a handler's error; `Some` replaces the default response
+pub type ErrorHookFn = fn(&HttpError) -> Option<HttpResponse>;
+
+/// Header `Router::expose_timing` reports handler instructions in
+pub const INSTRUCTIONS_USED_HEADER: &str = "X-Instructions-Used";
+
+pub struct Router {
+    routes: HashMap<(HttpMethod, String), HandlerFn>,
+    upgrade_routes: HashSet<(HttpMethod, String)>,
+    strict_trailing_slash: bool,
+    case_insensitive_paths: bool,
+    request_ids: bool,
+    expose_timing: bool,
+    on_error: Option<ErrorHookFn>,
+    limits: HashMap<(HttpMethod, String), (RateLimiter, RateLimitKey)>,
+    #[cfg(feature = "telemetry")]
+    body_size_telemetry: bool,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self {
+            routes: HashMap::new(),
+            upgrade_routes: HashSet::new(),
+            strict_trailing_slash: true,
+            case_insensitive_paths: false,
+            request_ids: false,
+            expose_timing: false,
+            on_error: None,
+            limits: HashMap::new(),
+            #[cfg(feature = "telemetry")]
+            body_size_telemetry: false,
+        }
+    }
+
+    /// Treat `/users` and `/users/` as distinct paths (default: true)
+    ///
+    /// When disabled, a trailing slash is ignored on both the request path and
+    /// registered routes, so one registration serves both forms.
+    pub fn strict_trailing_slash(&mut self, strict: bool) {
+        self.strict_trailing_slash = strict;
+    }
+
+    /// Compare literal path segments ignoring ASCII case (default: false)
+    ///
+    /// `:params` and `*` wildcards match as before; handlers still see the
+    /// request path in its original case.
+    pub fn case_insensitive_paths(&mut self, case_insensitive: bool) {
+        self.case_insensitive_paths = case_insensitive;
+    }
+
+    /// Attach a request ID to every request and response (default: false)
+    ///
+    /// The ID comes from `X-Request-Id` or is generated, is logged (through
+    /// telemetry when enabled), added to the request headers so handlers can
+    /// read it with `request_id`, and echoed on the response.
+    pub fn request_ids(&mut self, enabled: bool) {
+        self.request_ids = enabled;
+    }
+
+    /// Report the instructions each handler used (default: false)
+    ///
+    /// Adds an `X-Instructions-Used` header measured with
+    /// `performance_counter(0)` around the handler, on both successful and
+    /// error responses. Useful for spotting slow routes while debugging.
+    pub fn expose_timing(&mut self, enabled: bool) {
+        self.expose_timing = enabled;
+    }
+
+    /// Log request/response body sizes for routed requests (default: false)
+    ///
+    /// Logs the matched route, status and both body lengths with
+    /// `telemetry::log_info` and adds them to the `bytes_in` / `bytes_out`
+    /// counters. Counter updates made in query calls are discarded, so the
+    /// counters only reflect `handle_update` traffic.
+    #[cfg(feature = "telemetry")]
+    pub fn body_size_telemetry(&mut self, enabled: bool) {
+        self.body_size_telemetry = enabled;
+    }
+
+    /// Call `hook` whenever a handler returns `Err`
+    ///
+    /// Use it to log failures or rewrite responses; returning `None` falls
+    /// back to `HttpError::to_response`. Router-generated errors (404, 405)
+    /// don't go through the hook.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// fn hide_internal_errors(error: &HttpError) -> Option<HttpResponse> {
+    ///     telemetry::log_error(&error.to_string());
+    ///     match error.status_code() {
+    ///         500.. => Some(error_response(500, "Internal server error")),
+    ///         _ => None,
+    ///     }
+    /// }
+    ///
+    /// router.on_error(hide_internal_errors);
+    /// ```
+    pub fn on_error(&mut self, hook: ErrorHookFn) {
+        self.on_error = Some(hook);
+    }
+
+    pub fn add_route(&mut self, method: HttpMethod, path: impl Into<String>, handler: HandlerFn) {
+        self.routes.insert((method, path.into()), handler);
+    }
+
+    /// Register one handler for several methods
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// router.route(&[HttpMethod::POST, HttpMethod::PUT], "/api/items/:id", save_item);
+    /// ```
+    pub fn route(&mut self, methods: &[HttpMethod], path: impl Into<String>, handler: HandlerFn) {
+        let path = path.into();
+        for method in methods {
+            self.add_route(method.clone(), path.clone(), handler);
+        }
+    }
+
+    pub fn get(&mut self, path: impl Into<String>, handler: HandlerFn) {
+        self.add_route(HttpMethod::GET, path, handler);
+    }
+
+    pub fn post(&mut self, path: impl Into<String>, handler: HandlerFn) {
+        self.add_route(HttpMethod::POST, path, handler);
+    }
+
+    pub fn put(&mut self, path: impl Into<String>, handler: HandlerFn) {
+        self.add_route(HttpMethod::PUT, path, handler);
+    }
+
+    pub fn delete(&mut self, path: impl Into<String>, handler: HandlerFn) {
+        self.add_route(HttpMethod::DELETE, path, handler);
+    }
+
+    /// Register a route whose calls are counted against `limiter`
+    ///
+    /// The limit is checked before the handler runs; once exceeded the router
+    /// answers `429 Too Many Requests` with a `Retry-After` header. Limiter
+    /// state only persists in update calls, so mark limited routes with
+    /// `upgrade_for` (query-call increments are discarded).
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// router.add_limited_route(
+    ///     HttpMethod::POST,
+    ///     "/generate",
+    ///     generate,
+    ///     RateLimiter::new(5, 60_000_000_000),
+    ///     RateLimitKey::header("X-Api-Key"),
+    /// );
+    /// router.upgrade_for(HttpMethod::POST, "/generate");
+    /// ```
+    pub fn add_limited_route(
+        &mut self,
+        method: HttpMethod,
+        path: impl Into<String>,
+        handler: HandlerFn,
+        limiter: RateLimiter,
+        key: RateLimitKey,
+    ) {
+        let path = path.into();
+        self.limits.insert((method.clone(), path.clone()), (limiter, key));
+        self.add_route(method, path, handler);
+    }
+
+    /// Register a rate-limited GET route (see `add_limited_route`)
+    pub fn get_limited(
+        &mut self,
+        path: impl Into<String>,
+        handler: HandlerFn,
+        limiter: RateLimiter,
+        key: RateLimitKey,
+    ) {
+        self.add_limited_route(HttpMethod::GET, path, handler, limiter, key);
+    }
+
+    /// Register a rate-limited POST route (see `add_limited_route`)
+    pub fn post_limited(
+        &mut self,
+        path: impl Into<String>,
+        handler: HandlerFn,
+        limiter: RateLimiter,
+        key: RateLimitKey,
+    ) {
+        self.add_limited_route(HttpMethod::POST, path, handler, limiter, key);
+    }
+
+    /// Add all routes of `sub` under `prefix`
+    ///
+    /// `sub.get("/users/:id", ..)` mounted at `/api` serves `/api/users/:id`;
+    /// params and wildcards match as usual. Upgrade markings are kept, while
+    /// the sub-router's own options (trailing slash, timing, ...) are ignored
+    /// in favour of this router's.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let mut v1 = Router::new();
+    /// v1.get("/users/:id", get_user);
+    ///
+    /// let mut router = Router::new();
+    /// router.mount("/api/v1", v1);
+    /// ```
+    pub fn mount(&mut self, prefix: &str, sub: Router) {
+        let prefix = prefix.trim_end_matches('/');
+
+        for ((method, path), handler) in sub.routes {
+            self.routes.insert((method, join_paths(prefix, &path)), handler);
+        }
+        for (method, path) in sub.upgrade_routes {
+            self.upgrade_routes.insert((method, join_paths(prefix, &path)));
+        }
+        for ((method, path), limit) in sub.limits {
+            self.limits.insert((method, join_paths(prefix, &path)), limit);
+        }
+    }
+
+    /// Mark a route as always upgraded to an update call
+    ///
+    /// `handle` answers these routes with `upgrade_response()` without running
+    /// the handler; the gateway then retries via `http_request_update`, where
+    /// `handle_update` runs the handler.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// router.post("/api/items", create_item);
+    /// router.upgrade_for(HttpMethod::POST, "/api/items");
+    ///
+    /// #[ic_cdk::query]
+    /// fn http_request(req: HttpRequest) -> HttpResponse {
+    ///     ROUTER.with(|r| r.handle(req))
+    /// }
+    ///
+    /// #[ic_cdk::update]
+    /// fn http_request_update(req: HttpRequest) -> HttpResponse {
+    ///     ROUTER.with(|r| r.handle_update(req))
+    /// }
+    /// ```
+    pub fn upgrade_for(&mut self, method: HttpMethod, path: impl Into<String>) {
+        self.upgrade_routes.insert((method, path.into()));
+    }
+
+    /// Check whether a request matches a route marked with `upgrade_for`
+    pub fn should_upgrade(&self, request: &HttpRequest) -> bool {
+        let Some(method) = HttpMethod::from_str(&request.method) else {
+            return false;
+        };
+
+        self.find_route(&method, extract_path(&request.url))
+            .map(|(key, _)| self.upgrade_routes.contains(key))
+            .unwrap_or(false)
+    }
+
+    /// All registered `(method, path)` pairs, sorted by path then method
+    pub fn routes(&self) -> Vec<(HttpMethod, String)> {
+        let mut routes: Vec<(HttpMethod, String)> = self.routes.keys().cloned().collect();
+        routes.sort_by(|(m1, p1), (m2, p2)| p1.cmp(p2).then(m1.cmp(m2)));
+        routes
+    }
+
+    /// Human-readable table of registered routes, one per line
+    ///
+    /// Routes marked with `upgrade_for` or rate limited are annotated.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// // Handlers can't capture the router, so read it from the thread-local
+    /// router.get("/_routes", |_| Ok(text_response(200, ROUTER.with(|r| r.borrow().describe()))));
+    /// ```
+    pub fn describe(&self) -> String {
+        let routes = self.routes();
+        let width = routes.iter().map(|(m, _)| m.as_str().len()).max().unwrap_or(0);
+
+        let mut out = String::new();
+        for (method, path) in routes {
+            let key = (method, path);
+            let mut line = format!("{:<width$}  {}", key.0.as_str(), key.1, width = width);
+            if self.upgrade_routes.contains(&key) {
+                line.push_str("  [upgrade]");
+            }
+            if self.limits.contains_key(&key) {
+                line.push_str("  [rate limited]");
+            }
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Handle a request in a query call (`http_request`)
+    pub fn handle(&self, request: HttpRequest) -> HttpResponse {
+        self.dispatch(request, true)
+    }
+
+    /// Handle a request in an update call (`http_request_update`)
+    ///
+    /// Routes marked with `upgrade_for` run their handler here.
+    pub fn handle_update(&self, request: HttpRequest) -> HttpResponse {
+        self.dispatch(request, false)
+    }
+
+    fn dispatch(&self, mut request: HttpRequest, allow_upgrade: bool) -> HttpResponse {
+        if !self.request_ids {
+            return self.route_request(request, allow_upgrade);
+        }
+
+        let id = request_id(&request);
+        log_request(&id, &request.method, extract_path(&request.url));
+
+        if get_header(&request.headers, REQUEST_ID_HEADER) != Some(id.as_str()) {
+            request
+                .headers
+                .retain(|(k, _)| !k.eq_ignore_ascii_case(REQUEST_ID_HEADER));
+            request
+                .headers
+                .push((REQUEST_ID_HEADER.to_string(), id.clone()));
+        }
+
+        let mut response = self.route_request(request, allow_upgrade);
+        set_request_id(&mut response, &id);
+        response
+    }
+
+    fn route_request(&self, request: HttpRequest, allow_upgrade: bool) -> HttpResponse {
+        let method = match HttpMethod::from_str(&request.method) {
+            Some(m) => m,
+            None => return HttpError::MethodNotAllowed.to_response(),
+        };
+
+        let path = extract_path(&request.url);
+
+        // CORS preflight: advertise the methods registered for this path
+        if method == HttpMethod::OPTIONS && self.find_route(&method, path).is_none() {
+            let allowed = self.allowed_methods(path);
+            if allowed.is_empty() {
+                return cors_preflight_response();
+            }
+            return cors_preflight_response_for(&join_methods(&allowed));
+        }
+
+        // HEAD falls back to the GET handler with the body stripped
+        if method == HttpMethod::HEAD && self.find_route(&method, path).is_none() {
+            let mut head_request = request;
+            head_request.method = HttpMethod::GET.as_str().to_string();
+            let mut response = self.route_request(head_request, allow_upgrade);
+            if response.upgrade != Some(true) {
+                let content_length = response.body.len().to_string();
+                response
+                    .headers
+                    .retain(|(k, _)| !k.eq_ignore_ascii_case("Content-Length"));
+                response
+                    .headers
+                    .push(("Content-Length".to_string(), content_length));
+                response.body.clear();
+            }
+            return response;
+        }
+
+        match self.find_route(&method, path) {
+            Some((key, _)) if allow_upgrade && self.upgrade_routes.contains(key) => {
+                upgrade_response()
+            }
+            Some((key, handler)) => {
+                if let Some((limiter, limit_key)) = self.limits.get(key) {
+                    if let Err(e) = limiter.check_key(&limit_key.key_for(&request)) {
+                        return HttpError::from(e).to_response();
+                    }
+                }
+
+                #[cfg(feature = "telemetry")]
+                if self.body_size_telemetry {
+                    let bytes_in = request.body.len();
+                    let response = self.run_handler(handler, request);
+                    record_body_sizes(&method, &key.1, &response, bytes_in);
+                    return response;
+                }
+
+                self.run_handler(handler, request)
+            }
+            None => {
+                // Known path, other method: 405 with the methods that would work
+                let allowed = self.allowed_methods(path);
+                if allowed.is_empty() {
+                    return HttpError::NotFound.to_response();
+                }
+                let mut response = HttpError::MethodNotAllowed.to_response();
+                response
+                    .headers
+                    .push(("Allow".to_string(), join_methods(&allowed)));
+                response
+            }
+        }
+    }
+
+    fn run_handler(&self, handler: HandlerFn, request: HttpRequest) -> HttpResponse {
+        if !self.expose_timing {
+            return handler(request).unwrap_or_else(|e| self.error_response(&e));
+        }
+
+        let start = crate::env::performance_counter();
+        let mut response = handler(request).unwrap_or_else(|e| self.error_response(&e));
+        let used = crate::env::performance_counter().saturating_sub(start);
+
+        response
+            .headers
+            .push((INSTRUCTIONS_USED_HEADER.to_string(), used.to_string()));
+        response
+    }
+
+    fn error_response(&self, error: &HttpError) -> HttpResponse {
+        self.on_error
+            .and_then(|hook| hook(error))
+            .unwrap_or_else(|| error.to_response())
+    }
+
+    /// Methods that can be used on `path`, including implied HEAD and OPTIONS
+    fn allowed_methods(&self, path: &str) -> Vec<HttpMethod> {
+        const ORDER: [HttpMethod; 7] = [
+            HttpMethod::GET,
+            HttpMethod::HEAD,
+            HttpMethod::POST,
+            HttpMethod::PUT,
+            HttpMethod::DELETE,
+            HttpMethod::PATCH,
+            HttpMethod::OPTIONS,
+        ];
+
+        let registered: Vec<HttpMethod> = ORDER
+            .into_iter()
+            .filter(|m| self.find_route(m, path).is_some())
+            .collect();
+        if registered.is_empty() {
+            return registered;
+        }
+
+        ORDER
+            .into_iter()
+            .filter(|m| {
+                registered.contains(m)
+                    || (*m == HttpMethod::HEAD && registered.contains(&HttpMethod::GET))
+                    || *m == HttpMethod::OPTIONS
+            })
+            .collect()
+    }
+
+    /// Find the route for a method and path (exact match first, then patterns)
+    fn find_route(
+        &self,
+        method: &HttpMethod,
+        path: &str,
+    ) -> Option<(&(HttpMethod, String), HandlerFn)> {
+        if let Some((key, handler)) = self.routes.get_key_value(&(method.clone(), path.to_string())) {
+            return Some((key, *handler));
+        }
+
+        let path = self.normalize_path(path);
+
+        self.routes
+            .iter()
+            .find(|((route_method, route_path), _)| {
+                route_method == method
+                    && matches_pattern_with_case(
+                        path,
+                        self.normalize_path(route_path),
+                        !self.case_insensitive_paths,
+                    )
+            })
+            .map(|(key, handler)| (key, *handler))
+    }
+
+    fn normalize_path<'a>(&self, path: &'a str) -> &'a str {
+        if self.strict_trailing_slash {
+            return path;
+        }
+        trim_trailing_slash(path)
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Format methods for an `Allow` header, e.g. `GET, HEAD, OPTIONS`
+fn join_methods(methods: &[HttpMethod]) -> String {
+    methods.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+/// Join a mount prefix (without trailing slash) and a route path
+fn join_paths(prefix: &str, path: &str) -> String {
+    match path {
+        "" | "/" if !prefix.is_empty() => prefix.to_string(),
+        _ if path.starts_with('/') => format!("{}{}", prefix, path),
+        _ => format!("{}/{}", prefix, path),
+    }
+}
+
+/// Add a call's body sizes to the `bytes_in`/`bytes_out` counters and log them
+#[cfg(feature = "telemetry")]
+fn record_body_sizes(method: &HttpMethod, route: &str, response: &HttpResponse, bytes_in: usize) {
+    let bytes_out = response.body.len();
+    crate::telemetry::increment_counter("bytes_in", bytes_in as u64);
+    crate::telemetry::increment_counter("bytes_out", bytes_out as u64);
+    crate::telemetry::log_info(format!(
+        "http {} {} status={} bytes_in={} bytes_out={}",
+        method.as_str(),
+        route,
+        response.status_code,
+        bytes_in,
+        bytes_out
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_error_status_codes() {
+        assert_eq!(HttpError::BadRequest("test".to_string()).status_code(), 400);
+        assert_eq!(HttpError::Conflict("test".to_string()).status_code(), 409);
+        assert_eq!(HttpError::precondition_failed("test").status_code(), 412);
+        assert_eq!(
+            HttpError::UnprocessableEntity("test".to_string()).status_code(),
+            422
+        );
+        assert_eq!(
+            HttpError::custom_status(418, "I'm a teapot").status_code(),
+            418
+        );
+    }
+
+    #[test]
+    fn test_http_method_from_str() {
+        assert_eq!(HttpMethod::from_str("GET"), Some(HttpMethod::GET));
+        assert_eq!(HttpMethod::from_str("post"), Some(HttpMethod::POST));
+        assert_eq!(HttpMethod::from_str("INVALID"), None);
+    }
+
+    #[test]
+    fn test_http_method_into_str() {
+        let method: &'static str = HttpMethod::PATCH.into();
+        assert_eq!(method, "PATCH");
+    }
+
+    #[cfg(feature = "http-interop")]
+    #[test]
+    fn test_http_method_interop() {
+        assert_eq!(HttpMethod::try_from(&::http::Method::POST).unwrap(), HttpMethod::POST);
+        assert!(HttpMethod::try_from(&::http::Method::TRACE).is_err());
+        assert_eq!(::http::Method::from(HttpMethod::DELETE), ::http::Method::DELETE);
+    }
+
+    #[test]
+    fn test_ic_request_through_router() {
+        fn status(_req: HttpRequest) -> HttpResult<HttpResponse> {
+            success_response(&"ok")
+        }
+
+        let mut router = Router::new();
+        router.get("/api/status", status);
+        router.put("/api/status", status);
+        router.upgrade_for(HttpMethod::PUT, "/api/status");
+
+        let gateway_request = |method: &str| GatewayRequest {
+            method: method.to_string(),
+            url: "/api/status?verbose=1".to_string(),
+            headers: vec![("Accept".to_string(), "application/json".to_string())],
+            body: vec![],
+            certificate_version: Some(2),
+        };
+
+        let request = from_ic_request(&gateway_request("GET"));
+        assert_eq!(request.method, "GET");
+        assert_eq!(get_header(&request.headers, "accept"), Some("application/json"));
+        assert!(request.body.is_empty());
+
+        let response = to_ic_response(router.handle(request));
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, br#""ok""#.to_vec());
+        assert_eq!(response.upgrade, None);
+
+        // Methods beyond GET/POST/HEAD route too, and `upgrade` is kept
+        let response = to_ic_response(router.handle(from_ic_request(&gateway_request("PUT"))));
+        assert_eq!(response.upgrade, Some(true));
+    }
+
+    #[test]
+    fn test_router_upgrade_for() {
+        fn create(_req: HttpRequest) -> HttpResult<HttpResponse> {
+            Ok(json_response(201, "{}".to_string()))
+        }
+
+        let mut router = Router::new();
+        router.post("/api/items", create);
+        router.get("/api/items", create);
+        router.upgrade_for(HttpMethod::POST, "/api/items");
+
+        let request = |method: &str| HttpRequest {
+            method: method.to_string(),
+            url: "/api/items".to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+
+        assert!(router.should_upgrade(&request("POST")));
+        assert!(!router.should_upgrade(&request("GET")));
+
+        let response = router.handle(request("POST"));
+        assert_eq!(response.upgrade, Some(true));
+
+        let response = router.handle_update(request("POST"));
+        assert_eq!(response.status_code, 201);
+        assert_eq!(response.upgrade, None);
+
+        assert_eq!(router.handle(request("GET")).status_code, 201);
+    }
+
+    #[test]
+    fn test_router_trailing_slash() {
+        fn ok(_req: HttpRequest) -> HttpResult<HttpResponse> {
+            success_response(&"ok")
+        }
+
+        let request = |url: &str| HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+
+        let mut router = Router::new();
+        router.get("/a", ok);
+        router.get("/b/", ok);
+        router.get("/", ok);
+
+        // Strict by default
+        assert_eq!(router.handle(request("/a/")).status_code, 404);
+        assert_eq!(router.handle(request("/b")).status_code, 404);
+
+        router.strict_trailing_slash(false);
+        assert_eq!(router.handle(request("/a/")).status_code, 200);
+        assert_eq!(router.handle(request("/a")).status_code, 200);
+        assert_eq!(router.handle(request("/b")).status_code, 200);
+        assert_eq!(router.handle(request("/")).status_code, 200);
+
+        assert_eq!(trim_trailing_slash("/"), "/");
+        assert_eq!(trim_trailing_slash("/a/"), "/a");
+    }
+
+    #[test]
+    fn test_router_case_insensitive_paths() {
+        fn user(req: HttpRequest) -> HttpResult<HttpResponse> {
+            let params = extract_params_with_case(extract_path(&req.url), "/api/users/:id", false);
+            success_response(&params["id"])
+        }
+
+        let request = |url: &str| HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+
+        let mut router = Router::new();
+        router.get("/api/health", |_| success_response(&"ok"));
+        router.get("/api/users/:id", user);
+
+        assert_eq!(router.handle(request("/API/Health")).status_code, 404);
+
+        router.case_insensitive_paths(true);
+        assert_eq!(router.handle(request("/API/Health")).status_code, 200);
+
+        let response = router.handle(request("/Api/Users/AbC"));
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, br#""AbC""#.to_vec());
+    }
+
+    #[test]
+    fn test_router_request_ids() {
+        fn echo_id(req: HttpRequest) -> HttpResult<HttpResponse> {
+            success_response(&request_id(&req))
+        }
+
+        let request = |headers: Vec<(String, String)>| HttpRequest {
+            method: "GET".to_string(),
+            url: "/api/id".to_string(),
+            headers,
+            body: vec![],
+        };
+
+        crate::testing::set_time(0x1234);
+        let mut router = Router::new();
+        router.get("/api/id", echo_id);
+        router.request_ids(true);
+
+        let response = router.handle(request(vec![(
+            "x-request-id".to_string(),
+            "abc".to_string(),
+        )]));
+        assert_eq!(get_header(&response.headers, REQUEST_ID_HEADER), Some("abc"));
+        assert_eq!(response.body, br#""abc""#.to_vec());
+        assert!(crate::testing::take_logs().iter().any(|line| line.contains("abc")));
+
+        // Generated IDs reach the handler and the response, and are unique
+        let first = router.handle(request(vec![]));
+        let second = router.handle(request(vec![]));
+        let first_id = get_header(&first.headers, REQUEST_ID_HEADER).unwrap();
+        assert!(first_id.starts_with("1234-"));
+        assert_eq!(first.body, format!("\"{}\"", first_id).into_bytes());
+        assert_ne!(
+            get_header(&second.headers, REQUEST_ID_HEADER),
+            Some(first_id)
+        );
+
+        // Also stamped on router-generated errors
+        let mut missing = request(vec![]);
+        missing.url = "/nope".to_string();
+        assert!(get_header(&router.handle(missing).headers, REQUEST_ID_HEADER).is_some());
+        crate::testing::reset();
+    }
+
+    #[test]
+    fn test_router_expose_timing() {
+        let request = |url: &str| HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+
+        let mut router = Router::new();
+        router.get("/ok", |_| {
+            crate::testing::advance_performance_counter(500);
+            success_response(&"ok")
+        });
+        router.get("/fail", |_| {
+            crate::testing::advance_performance_counter(20);
+            Err(HttpError::bad_request("nope"))
+        });
+
+        crate::testing::set_performance_counter(1_000);
+        let response = router.handle(request("/ok"));
+        assert!(get_header(&response.headers, INSTRUCTIONS_USED_HEADER).is_none());
+
+        router.expose_timing(true);
+        let response = router.handle(request("/ok"));
+        assert_eq!(get_header(&response.headers, INSTRUCTIONS_USED_HEADER), Some("500"));
+        let response = router.handle(request("/fail"));
+        assert_eq!(get_header(&response.headers, INSTRUCTIONS_USED_HEADER), Some("20"));
+        crate::testing::reset();
+    }
+
+    #[cfg(all(feature = "telemetry", feature = "testing"))]
+    #[test]
+    fn test_router_body_size_telemetry() {
+        let request = HttpRequest {
+            method: "POST".to_string(),
+            url: "/echo".to_string(),
+            headers: vec![],
+            body: b"hello".to_vec(),
+        };
+
+        let mut router = Router::new();
+        router.post("/echo", |req| {
+            Ok(text_response(200, String::from_utf8(req.body.repeat(2)).unwrap()))
+        });
+        router.body_size_telemetry(true);
+        crate::testing::set_performance_counter(0);
+
+        let before_in = crate::telemetry::counter_value("bytes_in").unwrap_or(0);
+        let before_out = crate::telemetry::counter_value("bytes_out").unwrap_or(0);
+        router.handle(request);
+
+        assert_eq!(crate::telemetry::counter_value("bytes_in"), Some(before_in + 5));
+        assert_eq!(crate::telemetry::counter_value("bytes_out"), Some(before_out + 10));
+        let logs = crate::testing::take_logs();
+        assert!(logs.iter().any(|l| l.contains("/echo") && l.contains("bytes_in=5")));
+        crate::testing::reset();
+    }
+
+    #[test]
+    fn test_error_envelope() {
+        fn envelope(status: u16, message: &str) -> String {
+            serde_json::json!({ "success": false, "message": message, "code": status }).to_string()
+        }
+
+        set_error_envelope(envelope);
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&HttpError::NotFound.to_response().body).unwrap();
+        assert_eq!(body["success"], false);
+        assert_eq!(body["code"], 404);
+        assert!(body.get("error").is_none());
+
+        let fields = vec![FieldError {
+            field: "name".to_string(),
+            message: "required".to_string(),
+        }];
+        let response = HttpError::ValidationFailed(fields).to_response();
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["code"], 422);
+        assert_eq!(body["fields"][0]["field"], "name");
+
+        set_error_envelope(default_error_envelope);
+        assert_eq!(error_response(400, "bad").body, br#"{"error":"bad"}"#.to_vec());
+    }
+
+    #[test]
+    fn test_router_options_and_head() {
+        fn get_item(req: HttpRequest) -> HttpResult<HttpResponse> {
+            let params = extract_params(extract_path(&req.url), "/items/:id");
+            success_response(&params["id"])
+        }
+
+        let request = |method: &str, url: &str| HttpRequest {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+
+        let mut router = Router::new();
+        router.get("/items/:id", get_item);
+
+        let response = router.handle(request("OPTIONS", "/items/7"));
+        assert_eq!(response.status_code, 204);
+        assert_eq!(get_header(&response.headers, "Allow"), Some("GET, HEAD, OPTIONS"));
+        assert_eq!(
+            get_header(&response.headers, "Access-Control-Allow-Methods"),
+            Some("GET, HEAD, OPTIONS")
+        );
+
+        let get = router.handle(request("GET", "/items/7"));
+        let head = router.handle(request("HEAD", "/items/7"));
+        assert_eq!(head.status_code, 200);
+        assert!(head.body.is_empty());
+        assert_eq!(get_header(&head.headers, "Content-Type"), Some("application/json"));
+        assert_eq!(
+            get_header(&head.headers, "Content-Length"),
+            Some(get.body.len().to_string().as_str())
+        );
+
+        assert_eq!(router.handle(request("HEAD", "/nope")).status_code, 404);
+    }
+
+    #[test]
+    fn test_router_mount() {
+        fn user(req: HttpRequest) -> HttpResult<HttpResponse> {
+            let params = extract_params(extract_path(&req.url), "/api/users/:id");
+            success_response(&params["id"])
+        }
+
+        let request = |url: &str| HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+
+        let mut api = Router::new();
+        api.get("/users/:id", user);
+        api.get("/", |_| success_response(&"index"));
+
+        let mut router = Router::new();
+        router.mount("/api/", api);
+
+        let response = router.handle(request("/api/users/7"));
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, br#""7""#.to_vec());
+        assert_eq!(router.handle(request("/api")).status_code, 200);
+        assert_eq!(router.handle(request("/users/7")).status_code, 404);
+
+        assert_eq!(join_paths("", "/"), "/");
+        assert_eq!(join_paths("/api", "files/*"), "/api/files/*");
+    }
+
+    #[test]
+    fn test_router_routes_and_describe() {
+        let mut router = Router::new();
+        router.post("/items", |_| success_response(&"created"));
+        router.get("/items", |_| success_response(&"list"));
+        router.delete("/items/:id", |_| success_response(&"deleted"));
+        router.upgrade_for(HttpMethod::POST, "/items");
+
+        assert_eq!(
+            router.routes(),
+            vec![
+                (HttpMethod::GET, "/items".to_string()),
+                (HttpMethod::POST, "/items".to_string()),
+                (HttpMethod::DELETE, "/items/:id".to_string()),
+            ]
+        );
+        assert_eq!(
+            router.describe(),
+            "GET     /items\nPOST    /items  [upgrade]\nDELETE  /items/:id\n"
+        );
+        assert_eq!(Router::new().describe(), "");
+    }
+
+    #[test]
+    fn test_router_route_multiple_methods() {
+        fn save(req: HttpRequest) -> HttpResult<HttpResponse> {
+            success_response(&req.method)
+        }
+
+        let request = |method: &str| HttpRequest {
+            method: method.to_string(),
+            url: "/items/3".to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+
+        let mut router = Router::new();
+        router.route(&[HttpMethod::POST, HttpMethod::PUT], "/items/:id", save);
+
+        assert_eq!(router.handle(request("POST")).body, br#""POST""#.to_vec());
+        assert_eq!(router.handle(request("PUT")).body, br#""PUT""#.to_vec());
+
+        let response = router.handle(request("DELETE"));
+        assert_eq!(response.status_code, 405);
+        assert_eq!(get_header(&response.headers, "Allow"), Some("POST, PUT, OPTIONS"));
+    }
+
+    #[test]
+    fn test_router_on_error() {
+        fn hide_internal(error: &HttpError) -> Option<HttpResponse> {
+            match error.status_code() {
+                500.. => Some(error_response(500, "Internal server error")),
+                _ => None,
+            }
+        }
+
+        let request = |url: &str| HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+
+        let mut router = Router::new();
+        router.get("/boom", |_| Err(HttpError::internal_error("db password is hunter2")));
+        router.get("/bad", |_| Err(HttpError::bad_request("missing id")));
+        router.on_error(hide_internal);
+
+        let response = router.handle(request("/boom"));
+        assert_eq!(response.status_code, 500);
+        assert_eq!(response.body, br#"{"error":"Internal server error"}"#.to_vec());
+
+        // `None` falls back to the default conversion
+        let response = router.handle(request("/bad"));
+        assert_eq!(response.status_code, 400);
+        assert!(String::from_utf8(response.body).unwrap().contains("missing id"));
+    }
+
+    #[test]
+    fn test_router_per_route_limits() {
+        crate::testing::set_time(0);
+
+        let request = |url: &str, key: &str| HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![("X-Api-Key".to_string(), key.to_string())],
+            body: vec![],
+        };
+
+        let mut router = Router::new();
+        router.get_limited(
+            "/generate",
+            |_| Ok(json_response(200, "{}".to_string())),
+            RateLimiter::new(1, 10_000_000_000),
+            RateLimitKey::header("X-Api-Key"),
+        );
+        router.get("/health", |_| Ok(json_response(200, "{}".to_string())));
+
+        assert_eq!(router.handle(request("/generate", "a")).status_code, 200);
+        let response = router.handle(request("/generate", "a"));
+        assert_eq!(response.status_code, status::TOO_MANY_REQUESTS);
+        assert_eq!(get_header(&response.headers, "Retry-After"), Some("10"));
+
+        // Other keys and unlimited routes are unaffected
+        assert_eq!(router.handle(request("/generate", "b")).status_code, 200);
+        assert_eq!(router.handle(request("/health", "a")).status_code, 200);
+        assert_eq!(router.handle(request("/health", "a")).status_code, 200);
+        crate::testing::reset();
+    }
+
+    #[test]
+    fn test_guess_content_type() {
+        assert_eq!(guess_content_type("data.JSON", b""), "application/json");
+        assert_eq!(guess_content_type("canister.wasm", b""), "application/wasm");
+        assert_eq!(guess_content_type("upload", b"\x89PNG\r\n\x1a\nrest"), "image/png");
+        assert_eq!(guess_content_type("upload.bin", b"GIF89a"), "image/gif");
+        assert_eq!(guess_content_type("report", b"%PDF-1.7"), "application/pdf");
+        assert_eq!(guess_content_type("weights", b"\0\0"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_error_response_is_valid_json() {
+        let message = "bad \u{1} byte \"quoted\" \\ 🦀";
+        let response = error_response(400, message);
+
+        let parsed: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(parsed["error"], message);
+        assert!(String::from_utf8(response.body).unwrap().contains("\\u0001"));
+    }
+
+    #[test]
+    fn test_json_array_response() {
+        #[derive(Serialize)]
+        struct Item {
+            id: u32,
+        }
+
+        let response = json_array_response((1..=3).map(|id| Item { id })).unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, br#"[{"id":1},{"id":2},{"id":3}]"#.to_vec());
+
+        let response = json_array_response(std::iter::empty::<Item>()).unwrap();
+        assert_eq!(response.body, b"[]".to_vec());
+    }
+
+    #[test]
+    fn test_html_and_text_responses() {
+        let response = html_response(200, "<p>Signed in</p>".to_string());
+        assert_eq!(
+            get_header(&response.headers, "content-type"),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(response.body, b"<p>Signed in</p>".to_vec());
+
+        let response = text_response(404, "missing".to_string());
+        assert_eq!(response.status_code, 404);
+        assert_eq!(
+            get_header(&response.headers, "content-type"),
+            Some("text/plain; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn test_sse_frames() {
+        use sse::{sse_frame, SseBuilder};
+
+        assert_eq!(sse_frame(None, "hello"), "data: hello\n\n");
+        assert_eq!(
+            sse_frame(Some("token"), "line1\nline2"),
+            "event: token\ndata: line1\ndata: line2\n\n"
+        );
+        assert_eq!(sse_frame(Some("done"), ""), "event: done\ndata: \n\n");
+
+        let response = SseBuilder::new().data("a").event("done", "").build();
+        assert_eq!(get_header(&response.headers, "content-type"), Some("text/event-stream"));
+        assert_eq!(response.body, b"data: a\n\nevent: done\ndata: \n\n".to_vec());
+    }
+
+    #[test]
+    fn test_etag_conditional() {
+        let tag = etag(b"hello");
+        assert_eq!(tag, etag(b"hello"));
+        assert_ne!(tag, etag(b"hello!"));
+        assert!(tag.starts_with('"') && tag.ends_with('"'));
+
+        let mut response = json_response(200, "{}".to_string());
+        set_etag(&mut response, &tag);
+        assert_eq!(get_header(&response.headers, "etag"), Some(tag.as_str()));
+
+        let headers = vec![("If-None-Match".to_string(), format!("\"other\", W/{}", tag))];
+        assert!(check_not_modified(&headers, &tag));
+        assert!(!check_not_modified(&headers, &etag(b"changed")));
+        assert!(!check_not_modified(&[], &tag));
+
+        let response = not_modified_response(&tag);
+        assert_eq!(response.status_code, 304);
+        assert!(response.body.is_empty());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("hello+world%21"), "hello world!");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn test_health_checks() {
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            url: "/health".to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+
+        set_health_checks(vec![("auth", || true), ("model", || true)]);
+        let response = health_handler(request.clone()).unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(
+            response.body,
+            br#"{"checks":{"auth":true,"model":true},"status":"ok"}"#.to_vec()
+        );
+
+        set_health_checks(vec![("auth", || true), ("model", || false)]);
+        let response = health_handler(request).unwrap();
+        assert_eq!(response.status_code, 503);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["status"], "unavailable");
+        assert_eq!(body["failing"], serde_json::json!(["model"]));
+    }
+
+    #[test]
+    fn test_string_result_into_http_response() {
+        let ok: Result<u32, String> = Ok(7);
+        let response = ok.into_http_response().unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, b"7".to_vec());
+
+        let err: Result<u32, String> = Err("db offline".to_string());
+        let response = err.into_http_response().unwrap();
+        assert_eq!(response.status_code, 500);
+        assert_eq!(response.body, br#"{"error":"db offline"}"#.to_vec());
+
+        set_string_error_status(status::BAD_GATEWAY);
+        let err: Result<u32, String> = Err("upstream".to_string());
+        assert_eq!(err.into_http_response().unwrap().status_code, 502);
+    }
+
+    #[test]
+    fn test_require_if_match() {
+        let tag = etag(b"v1");
+        assert!(require_if_match(&[], &tag).is_ok());
+
+        let headers = vec![("If-Match".to_string(), format!("\"other\", {}", tag))];
+        assert!(require_if_match(&headers, &tag).is_ok());
+
+        let err = require_if_match(&headers, &etag(b"v2")).unwrap_err();
+        assert_eq!(err.status_code(), status::PRECONDITION_FAILED);
+
+        let weak = vec![("If-Match".to_string(), format!("W/{}", tag))];
+        assert!(require_if_match(&weak, &tag).is_err());
+
+        let any = vec![("If-Match".to_string(), "*".to_string())];
+        assert!(require_if_match(&any, &tag).is_ok());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let range = |value: &str| vec![("Range".to_string(), value.to_string())];
+
+        assert_eq!(parse_range(&range("bytes=0-99"), 1000), Some((0, 99)));
+        assert_eq!(parse_range(&range("bytes=500-"), 1000), Some((500, 999)));
+        assert_eq!(parse_range(&range("bytes=-200"), 1000), Some((800, 999)));
+        assert_eq!(parse_range(&range("bytes=-2000"), 1000), Some((0, 999)));
+        assert_eq!(parse_range(&range("bytes=900-5000"), 1000), Some((900, 999)));
+
+        assert_eq!(parse_range(&range("bytes=1000-"), 1000), None);
+        assert_eq!(parse_range(&range("bytes=50-10"), 1000), None);
+        assert_eq!(parse_range(&range("bytes=-0"), 1000), None);
+        assert_eq!(parse_range(&range("items=0-10"), 1000), None);
+        assert_eq!(parse_range(&range("bytes=0-1,5-6"), 1000), None);
+        assert_eq!(parse_range(&[], 1000), None);
+    }
+
+    #[test]
+    fn test_serve_range() {
+        let data = b"0123456789";
+        let range = |value: &str| vec![("Range".to_string(), value.to_string())];
+
+        let response = serve_range(&range("bytes=2-4"), data);
+        assert_eq!(response.status_code, 206);
+        assert_eq!(response.body, b"234".to_vec());
+        assert_eq!(get_header(&response.headers, "Content-Range"), Some("bytes 2-4/10"));
+
+        let response = serve_range(&range("bytes=20-"), data);
+        assert_eq!(response.status_code, 416);
+        assert_eq!(get_header(&response.headers, "Content-Range"), Some("bytes */10"));
+
+        let response = serve_range(&[], data);
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, data.to_vec());
+    }
+
+    #[test]
+    fn test_extract_path() {
+        assert_eq!(extract_path("/api/test"), "/api/test");
+        assert_eq!(extract_path("/api/test?param=value"), "/api/test");
+        assert_eq!(
+            extract_path("/api/test?param1=value1&param2=value2"),
+            "/api/test"
+        );
+    }
+
+    #[test]
+    fn test_extract_query_params() {
+        let params = extract_query_params("/api/test?foo=bar&baz=qux");
+        assert_eq!(params.get("foo"), Some(&"bar".to_string()));
+        assert_eq!(params.get("baz"), Some(&"qux".to_string()));
+    }
+
+    #[test]
+    fn test_path_matching() {
+        assert!(matches_pattern("/api/test", "/api/test"));
+        assert!(matches_pattern("/api/test", "/api/*"));
         assert!(matches_pattern("/api/v1/users", "*/users"));
         assert!(!matches_pattern("/api/test", "/api/other"));
     }
@@ -545,6 +2478,56 @@ mod tests {
         assert_eq!(parsed, data);
     }
 
+    #[test]
+    fn test_parse_json_empty_body() {
+        #[derive(Deserialize, Debug, Default, PartialEq)]
+        struct Options {
+            #[serde(default)]
+            limit: u32,
+        }
+
+        assert!(matches!(
+            parse_json::<Options>(b"  \n"),
+            Err(HttpError::BadRequest(msg)) if msg == "empty request body"
+        ));
+        assert_eq!(parse_json_or_default::<Options>(b"").unwrap(), Options::default());
+        assert_eq!(
+            parse_json_or_default::<Options>(b"{\"limit\":5}").unwrap(),
+            Options { limit: 5 }
+        );
+        assert!(parse_json_or_default::<Options>(b"{").is_err());
+    }
+
+    #[test]
+    fn test_parse_and_validate() {
+        #[derive(Deserialize, Debug)]
+        struct CreateUser {
+            name: String,
+        }
+
+        impl Validate for CreateUser {
+            fn validate(&self) -> Result<(), Vec<FieldError>> {
+                if self.name.is_empty() {
+                    return Err(vec![FieldError::new("name", "must not be empty")]);
+                }
+                Ok(())
+            }
+        }
+
+        let user: CreateUser = parse_and_validate(br#"{"name":"alice"}"#).unwrap();
+        assert_eq!(user.name, "alice");
+
+        let err = parse_and_validate::<CreateUser>(b"not json").unwrap_err();
+        assert_eq!(err.status_code(), 400);
+
+        let err = parse_and_validate::<CreateUser>(br#"{"name":""}"#).unwrap_err();
+        assert_eq!(err.status_code(), 422);
+
+        let body: serde_json::Value = serde_json::from_slice(&err.to_response().body).unwrap();
+        assert_eq!(body["fields"][0]["field"], "name");
+        assert_eq!(body["fields"][0]["message"], "must not be empty");
+    }
+
     #[test]
     fn test_get_header() {
         let headers = vec![
@@ -0,0 +1,133 @@
+//! Idempotency keys for update endpoints
+//!
+//! Records the response of an update under a client-supplied key so retries
+//! can be answered with the original response instead of re-applying effects.
+
+#![cfg(feature = "storage")]
+
+use candid::{CandidType, Deserialize};
+use std::cell::{Cell, RefCell};
+
+use crate::http::{self, HttpRequest};
+use crate::storage::{self, StorageRegistry};
+
+/// Header clients use to supply an idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Default time a recorded response is replayed for (24h)
+pub const DEFAULT_TTL_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+thread_local! {
+    static TTL_NANOS: Cell<u64> = Cell::new(DEFAULT_TTL_NANOS);
+}
+
+/// Result of starting an idempotent operation
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdempotencyState {
+    /// No unexpired response recorded - perform the operation
+    Fresh,
+    /// The operation already completed - return these response bytes
+    Replayed(Vec<u8>),
+}
+
+#[derive(CandidType, Deserialize)]
+struct IdempotencyRecord {
+    response: Vec<u8>,
+    expires_at: u64,
+}
+
+/// Set how long recorded responses are replayed for
+pub fn set_ttl(ttl_nanos: u64) {
+    TTL_NANOS.with(|t| t.set(ttl_nanos));
+}
+
+/// Get how long recorded responses are replayed for
+pub fn ttl() -> u64 {
+    TTL_NANOS.with(|t| t.get())
+}
+
+/// Get the idempotency key from the `Idempotency-Key` header
+pub fn key_from_request(request: &HttpRequest) -> Option<&str> {
+    http::get_header(&request.headers, IDEMPOTENCY_KEY_HEADER)
+}
+
+/// Check whether an operation has already completed for `key`
+///
+/// # Example
+/// ```rust,ignore
+/// if let Some(key) = idempotency::key_from_request(&req) {
+///     if let IdempotencyState::Replayed(bytes) = REGISTRY.with(|r| idempotency::begin(r, key)) {
+///         return HttpResponse { status_code: 200, headers: vec![], body: bytes, upgrade: None };
+///     }
+/// }
+/// let response = apply_update(&req);
+/// REGISTRY.with(|r| idempotency::finish(r, key, response.body.clone()))?;
+/// ```
+pub fn begin<R: StorageRegistry>(registry: &RefCell<R>, key: &str) -> IdempotencyState {
+    begin_at(registry, key, crate::env::time())
+}
+
+/// Record the response for `key` so later `begin` calls replay it
+pub fn finish<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    response_bytes: Vec<u8>,
+) -> Result<(), String> {
+    finish_at(registry, key, response_bytes, crate::env::time())
+}
+
+/// `begin` at an explicit time (nanoseconds)
+pub fn begin_at<R: StorageRegistry>(registry: &RefCell<R>, key: &str, now: u64) -> IdempotencyState {
+    let storage_key = storage_key(key);
+
+    match storage::load_candid::<IdempotencyRecord, R>(registry, &storage_key) {
+        Some(record) if record.expires_at > now => IdempotencyState::Replayed(record.response),
+        Some(_) => {
+            storage::delete(registry, &storage_key);
+            IdempotencyState::Fresh
+        }
+        None => IdempotencyState::Fresh,
+    }
+}
+
+/// `finish` at an explicit time (nanoseconds)
+pub fn finish_at<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    response_bytes: Vec<u8>,
+    now: u64,
+) -> Result<(), String> {
+    let record = IdempotencyRecord {
+        response: response_bytes,
+        expires_at: now.saturating_add(ttl()),
+    };
+    storage::save_candid(registry, &storage_key(key), &record)
+}
+
+fn storage_key(key: &str) -> String {
+    format!("idempotency:{}", key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRegistry;
+
+    #[test]
+    fn test_begin_finish_replay() {
+        let registry = RefCell::new(TestRegistry::default());
+
+        assert_eq!(begin_at(&registry, "req-1", 0), IdempotencyState::Fresh);
+        finish_at(&registry, "req-1", b"done".to_vec(), 0).unwrap();
+
+        assert_eq!(
+            begin_at(&registry, "req-1", 1),
+            IdempotencyState::Replayed(b"done".to_vec())
+        );
+        assert_eq!(begin_at(&registry, "req-2", 1), IdempotencyState::Fresh);
+
+        // Expired records are dropped
+        assert_eq!(begin_at(&registry, "req-1", DEFAULT_TTL_NANOS), IdempotencyState::Fresh);
+        assert!(registry.borrow().is_empty());
+    }
+}
@@ -7,6 +7,35 @@
 
 use candid::{CandidType, Principal};
 use serde::de::DeserializeOwned;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+thread_local! {
+    /// Cycles that `call_with_payment` will never spend
+    static CYCLES_RESERVE: Cell<u128> = Cell::new(0);
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Error Types
+// ═══════════════════════════════════════════════════════════════
+
+/// Failed intercanister call with its rejection code
+///
+/// Converts into `String` (the `Display` form), so `?` keeps working in
+/// functions that return `Result<_, String>`.
+#[allow(deprecated)]
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{message}: {code:?}")]
+pub struct CallError {
+    pub code: ic_cdk::api::call::RejectionCode,
+    pub message: String,
+}
+
+impl From<CallError> for String {
+    fn from(error: CallError) -> Self {
+        error.to_string()
+    }
+}
 
 // ═══════════════════════════════════════════════════════════════
 //  Core Call Functions
@@ -37,7 +66,38 @@ where
         .map_err(|e| format_call_error(canister_id, method, e))
 }
 
+/// Make an intercanister call to a query method
+///
+/// The IC has no separate inter-canister query API: the call is executed as a
+/// query only when made from a composite query
+/// (`#[ic_cdk::query(composite = true)]`), otherwise it is a regular update
+/// call. Composite queries can only call query methods on canisters in the
+/// same subnet, cannot be called from other canisters, and cannot modify state.
+/// Logging and error formatting are identical to `call`.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::query(composite = true)]
+/// async fn read_balance(ledger: Principal, account: Account) -> Result<u64, String> {
+///     intercanister::query_call(ledger, "balance_of", account).await
+/// }
+/// ```
+pub async fn query_call<T, R>(
+    canister_id: Principal,
+    method: &str,
+    args: T,
+) -> Result<R, String>
+where
+    T: CandidType,
+    R: DeserializeOwned + CandidType,
+{
+    call(canister_id, method, args).await
+}
+
 /// Make an intercanister call with payment (cycles)
+///
+/// Fails with `Err("insufficient cycles")` before making the call if `cycles`
+/// exceeds the canister balance minus the reserve set by `set_cycles_reserve`.
 #[allow(deprecated)]
 pub async fn call_with_payment<T, R>(
     canister_id: Principal,
@@ -49,6 +109,17 @@ where
     T: CandidType,
     R: DeserializeOwned + CandidType,
 {
+    let balance = crate::env::cycle_balance();
+    log_debug(&format!(
+        "Cycles balance {} (reserve {}), attaching {} to {}.{}",
+        balance,
+        cycles_reserve(),
+        cycles,
+        canister_id,
+        method
+    ));
+    check_cycles_available(balance, cycles, cycles_reserve())?;
+
     log_call_start_with_cycles(canister_id, method, cycles);
 
     let result: Result<(R,), _> =
@@ -64,31 +135,484 @@ where
         .map_err(|e| format_call_error(canister_id, method, e))
 }
 
+/// Set the cycles reserve that `call_with_payment` must leave untouched
+pub fn set_cycles_reserve(reserve: u128) {
+    CYCLES_RESERVE.with(|r| r.set(reserve));
+}
+
+/// Get the current cycles reserve
+pub fn cycles_reserve() -> u128 {
+    CYCLES_RESERVE.with(|r| r.get())
+}
+
+/// Check that `cycles` can be spent from `balance` while keeping `reserve`
+pub fn check_cycles_available(balance: u128, cycles: u128, reserve: u128) -> Result<(), String> {
+    if cycles > balance.saturating_sub(reserve) {
+        Err("insufficient cycles".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Make an intercanister call with pre-encoded Candid arguments
+///
+/// Returns the raw Candid reply bytes. Use this to forward opaque payloads or
+/// when the reply type is only decided at runtime.
+///
+/// # Example
+/// ```rust,ignore
+/// let args = candid::encode_args((request,)).map_err(|e| e.to_string())?;
+/// let reply = intercanister::call_raw(canister_id, "my_method", args).await?;
+/// let (response,): (MyResponse,) = candid::decode_args(&reply).map_err(|e| e.to_string())?;
+/// ```
+#[allow(deprecated)]
+pub async fn call_raw(
+    canister_id: Principal,
+    method: &str,
+    arg_bytes: Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    log_call_start(canister_id, method);
+
+    let result = ic_cdk::api::call::call_raw(canister_id, method, arg_bytes, 0).await;
+
+    match &result {
+        Ok(_) => log_call_success(canister_id, method),
+        Err(e) => log_call_error(canister_id, method, e),
+    }
+
+    result.map_err(|e| format_call_error(canister_id, method, e))
+}
+
 /// Make an intercanister call without waiting for response
+///
+/// Only failures to enqueue the message are reported; the callee's outcome
+/// is never observed.
 #[allow(deprecated)]
 pub fn call_one_way<T>(
     canister_id: Principal,
     method: &str,
     args: T,
-) -> Result<(), String>
+) -> Result<(), CallError>
 where
     T: CandidType,
 {
     log_call_start(canister_id, method);
 
-    let result: Result<(), _> = ic_cdk::api::call::notify(canister_id, method, (args,));
+    let result = ic_cdk::api::call::notify(canister_id, method, (args,));
+    log_notify_result(canister_id, method, result)
+}
+
+/// Send a one-way message with cycles attached
+///
+/// Useful for fire-and-forget cycles transfers. Unlike `call_with_payment`,
+/// the cycles reserve is not checked.
+///
+/// # Example
+/// ```rust,ignore
+/// intercanister::notify_with_payment(target, "deposit_cycles", (), 1_000_000_000_000)?;
+/// ```
+#[allow(deprecated)]
+pub fn notify_with_payment<T>(
+    canister_id: Principal,
+    method: &str,
+    args: T,
+    cycles: u128,
+) -> Result<(), CallError>
+where
+    T: CandidType,
+{
+    log_call_start_with_cycles(canister_id, method, cycles);
+
+    let result =
+        ic_cdk::api::call::notify_with_payment128(canister_id, method, (args,), cycles);
+    log_notify_result(canister_id, method, result)
+}
+
+#[allow(deprecated)]
+fn log_notify_result(
+    canister_id: Principal,
+    method: &str,
+    result: Result<(), ic_cdk::api::call::RejectionCode>,
+) -> Result<(), CallError> {
+    match result {
+        Ok(()) => {
+            log_call_success(canister_id, method);
+            Ok(())
+        }
+        Err(code) => {
+            let error = CallError {
+                code,
+                message: "Notify failed".to_string(),
+            };
+            log_message(&error.to_string());
+            Err(error)
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Call Builder
+// ═══════════════════════════════════════════════════════════════
+
+/// How `CallBuilder` retries failed calls
+///
+/// Only `SysTransient` rejections (e.g. a full queue) are retried; the call
+/// is re-sent immediately, since there is no timer between attempts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts including the first (at least 1)
+    pub max_attempts: u32,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self { max_attempts }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+/// Whether a rejection is worth retrying
+#[allow(deprecated)]
+pub fn is_transient(code: ic_cdk::api::call::RejectionCode) -> bool {
+    code == ic_cdk::api::call::RejectionCode::SysTransient
+}
+
+/// Fluent intercanister call combining cycles, retries and a deadline
+///
+/// Finish with `await_call` to wait for a reply, or `notify` to send a one-way
+/// message. Option combinations:
+/// - `cycles` works with both; `await_call` also enforces the cycles
+///   reserve (see `call_with_payment`), `notify` does not.
+/// - `retry` and `timeout` only apply to `await_call`; `notify` ignores them
+///   since a one-way message has no reply to retry on.
+/// - `timeout` stops further retries once `ns` nanoseconds have passed since
+///   the first attempt. A call in flight is never cancelled.
+///
+/// # Example
+/// ```rust,ignore
+/// let balance: u64 = CallBuilder::new(ledger, "balance_of")
+///     .args(account)
+///     .retry(RetryPolicy::new(3))
+///     .timeout(10_000_000_000)
+///     .await_call()
+///     .await?;
+///
+/// CallBuilder::new(target, "deposit_cycles").cycles(1_000_000_000).notify()?;
+/// ```
+pub struct CallBuilder {
+    canister_id: Principal,
+    method: String,
+    args: Vec<u8>,
+    cycles: u128,
+    retry: Option<RetryPolicy>,
+    timeout_ns: Option<u64>,
+}
+
+impl CallBuilder {
+    /// Start a call with no arguments
+    pub fn new(canister_id: Principal, method: impl Into<String>) -> Self {
+        Self {
+            canister_id,
+            method: method.into(),
+            args: candid::encode_args(()).expect("Failed to encode empty arguments"),
+            cycles: 0,
+            retry: None,
+            timeout_ns: None,
+        }
+    }
+
+    /// Set the single Candid argument
+    ///
+    /// Traps if the argument cannot be encoded, like `call`.
+    pub fn args<T: CandidType>(mut self, args: T) -> Self {
+        self.args = candid::encode_one(args).expect("Failed to encode arguments");
+        self
+    }
+
+    /// Set pre-encoded Candid arguments (see `call_raw`)
+    pub fn raw_args(mut self, arg_bytes: Vec<u8>) -> Self {
+        self.args = arg_bytes;
+        self
+    }
+
+    /// Attach cycles to the call
+    pub fn cycles(mut self, cycles: u128) -> Self {
+        self.cycles = cycles;
+        self
+    }
+
+    /// Retry transient rejections (`await_call` only)
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Stop retrying after `ns` nanoseconds (`await_call` only)
+    pub fn timeout(mut self, ns: u64) -> Self {
+        self.timeout_ns = Some(ns);
+        self
+    }
+
+    /// Make the call and decode the single reply value
+    #[allow(deprecated)]
+    pub async fn await_call<R>(self) -> Result<R, String>
+    where
+        R: DeserializeOwned + CandidType,
+    {
+        let CallBuilder {
+            canister_id,
+            method,
+            args,
+            cycles,
+            retry,
+            timeout_ns,
+        } = self;
+
+        if cycles > 0 {
+            let balance = crate::env::cycle_balance();
+            check_cycles_available(balance, cycles, cycles_reserve())?;
+            log_call_start_with_cycles(canister_id, &method, cycles);
+        } else {
+            log_call_start(canister_id, &method);
+        }
+
+        let max_attempts = retry.map(|p| p.max_attempts.max(1)).unwrap_or(1);
+        let started_at = crate::env::time();
+        let mut attempt = 1;
+
+        loop {
+            let result = ic_cdk::api::call::call_raw128(canister_id, &method, &args, cycles).await;
+
+            match result {
+                Ok(reply) => {
+                    log_call_success(canister_id, &method);
+                    return candid::decode_one(&reply).map_err(|e| {
+                        format!("Failed to decode reply from {}.{}: {}", canister_id, method, e)
+                    });
+                }
+                Err(e) => {
+                    log_call_error(canister_id, &method, &e);
+
+                    let timed_out = timeout_ns
+                        .map(|t| crate::env::time().saturating_sub(started_at) >= t)
+                        .unwrap_or(false);
+                    if attempt >= max_attempts || !is_transient(e.0) || timed_out {
+                        return Err(format_call_error(canister_id, &method, e));
+                    }
+
+                    attempt += 1;
+                    log_message(&format!(
+                        "↻ Retrying {}.{} (attempt {}/{})",
+                        canister_id, method, attempt, max_attempts
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Send as a one-way message (see `call_one_way`)
+    #[allow(deprecated)]
+    pub fn notify(self) -> Result<(), CallError> {
+        if self.cycles > 0 {
+            log_call_start_with_cycles(self.canister_id, &self.method, self.cycles);
+        } else {
+            log_call_start(self.canister_id, &self.method);
+        }
+
+        let result =
+            ic_cdk::api::call::notify_raw(self.canister_id, &self.method, &self.args, self.cycles);
+        log_notify_result(self.canister_id, &self.method, result)
+    }
+}
+
+/// Make an intercanister call, retrying transient rejections
+///
+/// Shorthand for `CallBuilder::new(..).args(args).retry(policy).await_call()`.
+pub async fn call_with_retry<T, R>(
+    canister_id: Principal,
+    method: &str,
+    args: T,
+    policy: RetryPolicy,
+) -> Result<R, String>
+where
+    T: CandidType,
+    R: DeserializeOwned + CandidType,
+{
+    CallBuilder::new(canister_id, method)
+        .args(args)
+        .retry(policy)
+        .await_call()
+        .await
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Circuit Breaker
+// ═══════════════════════════════════════════════════════════════
+
+/// Default number of consecutive failures before the circuit opens
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// Default cool-down before an open circuit lets a probe through (30s)
+pub const DEFAULT_COOLDOWN_NANOS: u64 = 30_000_000_000;
+
+thread_local! {
+    static BREAKER: RefCell<CircuitBreaker> = RefCell::new(CircuitBreaker::default());
+}
+
+/// Per-target circuit breaker
+///
+/// A target's circuit opens after `failure_threshold` consecutive failures.
+/// While open, calls are rejected immediately. After `cooldown_nanos` the
+/// circuit half-opens: a single call is let through as a probe, and a
+/// failure re-opens it while a success closes it. If the probe never
+/// reports back, another one is allowed after a further cool-down.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown_nanos: u64,
+    targets: HashMap<Principal, BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<u64>,
+    /// When the half-open probe was let through
+    probe_started: Option<u64>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown_nanos: u64) -> Self {
+        Self {
+            failure_threshold,
+            cooldown_nanos,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Check whether a call to `target` may proceed at time `now`
+    pub fn allow(&mut self, target: Principal, now: u64) -> bool {
+        let cooldown = self.cooldown_nanos;
+        let Some(state) = self.targets.get_mut(&target) else {
+            return true;
+        };
+        let Some(opened_at) = state.opened_at else {
+            return true;
+        };
+
+        let waited = |since: u64| now.saturating_sub(since) >= cooldown;
+        if !waited(opened_at) || state.probe_started.is_some_and(|t| !waited(t)) {
+            return false;
+        }
+        state.probe_started = Some(now);
+        true
+    }
+
+    /// Record a successful call, closing the circuit
+    pub fn record_success(&mut self, target: Principal) {
+        self.targets.remove(&target);
+    }
+
+    /// Record a failed call, opening the circuit if the threshold is reached
+    pub fn record_failure(&mut self, target: Principal, now: u64) {
+        let threshold = self.failure_threshold;
+        let state = self.targets.entry(target).or_default();
+        state.consecutive_failures += 1;
+
+        if state.probe_started.is_some() || state.consecutive_failures >= threshold {
+            state.opened_at = Some(now);
+            state.probe_started = None;
+        }
+    }
+
+    /// Check whether the circuit for `target` is currently open
+    pub fn is_open(&self, target: &Principal) -> bool {
+        self.targets
+            .get(target)
+            .map(|state| state.opened_at.is_some())
+            .unwrap_or(false)
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN_NANOS)
+    }
+}
+
+/// Configure the circuit breaker used by `call_guarded`
+///
+/// This resets the state of all targets.
+pub fn configure_circuit_breaker(failure_threshold: u32, cooldown_nanos: u64) {
+    BREAKER.with(|b| *b.borrow_mut() = CircuitBreaker::new(failure_threshold, cooldown_nanos));
+}
+
+/// Check whether the circuit for a target canister is open
+pub fn is_circuit_open(canister_id: Principal) -> bool {
+    BREAKER.with(|b| b.borrow().is_open(&canister_id))
+}
+
+/// Whether a rejection code indicates the target is unhealthy
+///
+/// Explicit rejects from the callee (`CanisterReject`) are application-level
+/// answers, so `call_guarded` treats them like a success; traps, system
+/// errors and invalid destinations count as failures.
+#[allow(deprecated)]
+pub fn is_downstream_failure(code: ic_cdk::api::call::RejectionCode) -> bool {
+    !matches!(
+        code,
+        ic_cdk::api::call::RejectionCode::CanisterReject | ic_cdk::api::call::RejectionCode::NoError
+    )
+}
+
+/// Make an intercanister call through the circuit breaker
+///
+/// Fails fast with `Err("circuit open")` while the target's circuit is open.
+/// See `CircuitBreaker` for the open/half-open behaviour.
+#[allow(deprecated)]
+pub async fn call_guarded<T, R>(
+    canister_id: Principal,
+    method: &str,
+    args: T,
+) -> Result<R, String>
+where
+    T: CandidType,
+    R: DeserializeOwned + CandidType,
+{
+    if !BREAKER.with(|b| b.borrow_mut().allow(canister_id, crate::env::time())) {
+        log_message(&format!("✗ Call {}.{} skipped: circuit open", canister_id, method));
+        return Err("circuit open".to_string());
+    }
+
+    log_call_start(canister_id, method);
+
+    let result: Result<(R,), _> = ic_cdk::api::call::call(canister_id, method, (args,)).await;
 
     match &result {
         Ok(_) => {
             log_call_success(canister_id, method);
-            Ok(())
+            BREAKER.with(|b| b.borrow_mut().record_success(canister_id));
         }
         Err(e) => {
-            let err_msg = format!("Notify failed: {:?}", e);
-            log_message(&err_msg);
-            Err(err_msg)
+            log_call_error(canister_id, method, e);
+            BREAKER.with(|b| {
+                let mut breaker = b.borrow_mut();
+                if is_downstream_failure(e.0) {
+                    breaker.record_failure(canister_id, crate::env::time());
+                } else {
+                    breaker.record_success(canister_id);
+                }
+            });
         }
     }
+
+    result
+        .map(|r| r.0)
+        .map_err(|e| format_call_error(canister_id, method, e))
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -138,6 +662,14 @@ fn log_message(msg: &str) {
     ic_cdk::println!("{}", msg);
 }
 
+fn log_debug(msg: &str) {
+    #[cfg(feature = "telemetry")]
+    crate::telemetry::log_debug(msg);
+
+    #[cfg(not(feature = "telemetry"))]
+    ic_cdk::println!("[DEBUG] {}", msg);
+}
+
 /// Convenience function to call a method that takes no arguments
 pub async fn call_no_args<R>(
     canister_id: Principal,
@@ -167,4 +699,85 @@ mod tests {
         assert!(formatted.contains("test_method"));
         assert!(formatted.contains("Test error"));
     }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_call_error_into_string() {
+        let error = CallError {
+            code: ic_cdk::api::call::RejectionCode::DestinationInvalid,
+            message: "Notify failed".to_string(),
+        };
+
+        let message: String = error.into();
+        assert_eq!(message, "Notify failed: DestinationInvalid");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_is_transient() {
+        use ic_cdk::api::call::RejectionCode;
+
+        assert!(is_transient(RejectionCode::SysTransient));
+        assert!(!is_transient(RejectionCode::CanisterReject));
+        assert!(!is_transient(RejectionCode::CanisterError));
+        assert_eq!(RetryPolicy::default().max_attempts, 3);
+    }
+
+    #[test]
+    fn test_check_cycles_available() {
+        assert!(check_cycles_available(1_000, 500, 0).is_ok());
+        assert!(check_cycles_available(1_000, 500, 500).is_ok());
+        assert_eq!(
+            check_cycles_available(1_000, 501, 500),
+            Err("insufficient cycles".to_string())
+        );
+        assert!(check_cycles_available(100, 0, 500).is_ok());
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let target = Principal::anonymous();
+        let mut breaker = CircuitBreaker::new(2, 100);
+
+        assert!(breaker.allow(target, 0));
+        breaker.record_failure(target, 0);
+        assert!(!breaker.is_open(&target));
+        breaker.record_failure(target, 10);
+        assert!(breaker.is_open(&target));
+
+        // Open until the cool-down elapses
+        assert!(!breaker.allow(target, 50));
+
+        // Half-open probe fails: re-opens immediately
+        assert!(breaker.allow(target, 110));
+        breaker.record_failure(target, 110);
+        assert!(!breaker.allow(target, 150));
+
+        // Half-open probe succeeds: closes
+        assert!(breaker.allow(target, 210));
+        breaker.record_success(target);
+        assert!(!breaker.is_open(&target));
+        assert!(breaker.allow(target, 211));
+    }
+
+    #[test]
+    fn test_circuit_breaker_single_probe() {
+        let target = Principal::anonymous();
+        let mut breaker = CircuitBreaker::new(1, 100);
+        breaker.record_failure(target, 0);
+
+        // Only one call is let through while the probe is in flight
+        assert!(breaker.allow(target, 100));
+        assert!(!breaker.allow(target, 101));
+        assert!(!breaker.allow(target, 150));
+
+        // A probe that never reports back is replaced after another cool-down
+        assert!(breaker.allow(target, 200));
+        assert!(!breaker.allow(target, 201));
+
+        // Any outcome moves the state on
+        breaker.record_success(target);
+        assert!(breaker.allow(target, 202));
+        assert!(breaker.allow(target, 203));
+    }
 }
\ No newline at end of file
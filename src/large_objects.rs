@@ -3,9 +3,12 @@
 // This module provides utilities for uploading large files to IC canisters
 // using either sequential or parallel chunk uploads.
 
+use serde::Serialize;
 use std::cell::RefCell;
 use std::collections::HashMap;
 
+use crate::http::{self, HttpError, HttpRequest, HttpResponse};
+
 // ═══════════════════════════════════════════════════════════════
 //  Thread-Local Buffers
 // ═══════════════════════════════════════════════════════════════
@@ -16,6 +19,9 @@ thread_local! {
 
     /// Map of chunk_id -> data for parallel uploads
     static BUFFER_MAP: RefCell<HashMap<u32, Vec<u8>>> = RefCell::new(HashMap::new());
+
+    /// Named sequential buffers for independent uploads
+    static NAMED_BUFFERS: RefCell<HashMap<String, Vec<u8>>> = RefCell::new(HashMap::new());
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -56,6 +62,64 @@ pub fn load_to_buffer(data: Vec<u8>) {
     });
 }
 
+// ═══════════════════════════════════════════════════════════════
+//  Named Sequential Buffers
+// ═══════════════════════════════════════════════════════════════
+
+/// Append a chunk to the named sequential buffer (created on first use)
+///
+/// Each name is an independent buffer, so unrelated uploads don't have to
+/// share the default buffer.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::update]
+/// fn upload_chunk(name: String, chunk: Vec<u8>) {
+///     large_objects::append_named(&name, chunk);
+/// }
+///
+/// #[ic_cdk::update]
+/// fn finish_upload(name: String) {
+///     let data = large_objects::take_named(&name);
+///     save_file(&name, data);
+/// }
+/// ```
+pub fn append_named(name: &str, chunk: Vec<u8>) {
+    NAMED_BUFFERS.with(|buffers| {
+        buffers
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_default()
+            .extend(chunk);
+    });
+}
+
+/// Get the size of a named buffer (0 if it doesn't exist)
+pub fn buffer_size_named(name: &str) -> usize {
+    NAMED_BUFFERS.with(|buffers| buffers.borrow().get(name).map_or(0, |b| b.len()))
+}
+
+/// Take a named buffer's data, removing the buffer
+pub fn take_named(name: &str) -> Vec<u8> {
+    NAMED_BUFFERS.with(|buffers| buffers.borrow_mut().remove(name).unwrap_or_default())
+}
+
+/// Discard a named buffer
+pub fn clear_named(name: &str) {
+    NAMED_BUFFERS.with(|buffers| {
+        buffers.borrow_mut().remove(name);
+    });
+}
+
+/// Names of all named buffers, sorted
+pub fn named_buffers() -> Vec<String> {
+    NAMED_BUFFERS.with(|buffers| {
+        let mut names: Vec<String> = buffers.borrow().keys().cloned().collect();
+        names.sort();
+        names
+    })
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Parallel Buffer API
 // ═══════════════════════════════════════════════════════════════
@@ -67,6 +131,35 @@ pub fn append_parallel_chunk(chunk_id: u32, chunk: Vec<u8>) {
     });
 }
 
+/// Append a chunk with ID, rejecting a conflicting duplicate
+///
+/// Re-sending a chunk with identical bytes is a no-op, so client retries are
+/// safe. Sending different bytes for an ID that is already present is an
+/// error and leaves the existing chunk untouched.
+pub fn append_parallel_chunk_strict(chunk_id: u32, chunk: Vec<u8>) -> Result<(), String> {
+    BUFFER_MAP.with(|buffer_map| {
+        let mut buffer_map = buffer_map.borrow_mut();
+        match buffer_map.get(&chunk_id) {
+            Some(existing) if *existing == chunk => Ok(()),
+            Some(existing) => Err(format!(
+                "Chunk {} already uploaded with different data ({} bytes, got {} bytes)",
+                chunk_id,
+                existing.len(),
+                chunk.len()
+            )),
+            None => {
+                buffer_map.insert(chunk_id, chunk);
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Check whether a chunk ID is present in the parallel buffer
+pub fn has_chunk(chunk_id: u32) -> bool {
+    BUFFER_MAP.with(|buffer_map| buffer_map.borrow().contains_key(&chunk_id))
+}
+
 /// Get number of chunks in the parallel buffer
 pub fn parallel_chunk_count() -> usize {
     BUFFER_MAP.with(|buffer_map| buffer_map.borrow().len())
@@ -123,6 +216,29 @@ pub fn missing_chunks(expected_count: u32) -> Vec<u32> {
     })
 }
 
+/// Size of the object the parallel chunks will assemble into, in bytes
+///
+/// Same value as `parallel_buffer_size`, computed without assembling or
+/// cloning the data. Use it to check an upload before consolidating.
+pub fn parallel_assembled_len() -> usize {
+    parallel_buffer_size()
+}
+
+/// Consolidate parallel chunks, failing if the result would not be `expected_len` bytes
+///
+/// The length is checked before anything is moved, so on mismatch the chunks
+/// stay in the parallel buffer for the client to fix.
+pub fn consolidate_parallel_chunks_checked(expected_len: usize) -> Result<usize, String> {
+    let assembled_len = parallel_assembled_len();
+    if assembled_len != expected_len {
+        return Err(format!(
+            "Assembled size {} does not match expected {} bytes",
+            assembled_len, expected_len
+        ));
+    }
+    consolidate_parallel_chunks()
+}
+
 /// Consolidate parallel chunks into the sequential buffer
 pub fn consolidate_parallel_chunks() -> Result<usize, String> {
     let (chunk_data, total_size) = BUFFER_MAP.with(|buffer_map| {
@@ -163,6 +279,31 @@ pub fn consolidate_parallel_chunks() -> Result<usize, String> {
     Ok(total_size)
 }
 
+/// Copy parallel chunks into the sequential buffer, keeping the chunks
+///
+/// Unlike `consolidate_parallel_chunks`, the parallel buffer is left intact,
+/// so a failed persistence step can be retried without the client
+/// re-uploading. Call `clear_parallel_chunks` once the data is stored. The
+/// data is held twice on the heap until then.
+///
+/// # Example
+/// ```rust,ignore
+/// large_objects::consolidate_parallel_chunks_keep()?;
+/// storage::save_bytes(&REGISTRY, &key, large_objects::get_buffer_data())?;
+/// large_objects::clear_parallel_chunks();
+/// ```
+pub fn consolidate_parallel_chunks_keep() -> Result<usize, String> {
+    let data = get_parallel_data()?;
+    if data.is_empty() {
+        return Err("No parallel chunks to consolidate".to_string());
+    }
+
+    let total_size = data.len();
+    BUFFER.with(|buffer| *buffer.borrow_mut() = data);
+
+    Ok(total_size)
+}
+
 /// Get consolidated data from parallel chunks (without moving to BUFFER)
 pub fn get_parallel_data() -> Result<Vec<u8>, String> {
     BUFFER_MAP.with(|buffer_map| {
@@ -201,6 +342,185 @@ pub fn remove_parallel_chunk(chunk_id: u32) -> bool {
     })
 }
 
+// ═══════════════════════════════════════════════════════════════
+//  Content Hashing (requires "hashing" feature)
+// ═══════════════════════════════════════════════════════════════
+
+/// SHA-256 of the sequential buffer
+#[cfg(feature = "hashing")]
+pub fn buffer_sha256() -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+
+    BUFFER.with(|buffer| Sha256::digest(buffer.borrow().as_slice()).into())
+}
+
+/// SHA-256 of the parallel chunks in chunk ID order (without consolidating them)
+#[cfg(feature = "hashing")]
+pub fn parallel_data_sha256() -> Result<[u8; 32], String> {
+    use sha2::{Digest, Sha256};
+
+    BUFFER_MAP.with(|buffer_map| {
+        let buffer_map = buffer_map.borrow();
+
+        if buffer_map.is_empty() {
+            return Err("No parallel chunks available".to_string());
+        }
+
+        let mut sorted_ids: Vec<u32> = buffer_map.keys().copied().collect();
+        sorted_ids.sort();
+
+        let mut hasher = Sha256::new();
+        for chunk_id in sorted_ids {
+            hasher.update(&buffer_map[&chunk_id]);
+        }
+
+        Ok(hasher.finalize().into())
+    })
+}
+
+/// Verify the sequential buffer against a hex-encoded SHA-256 digest
+///
+/// # Example
+/// ```rust,ignore
+/// large_objects::verify_buffer(&expected_sha256)?;
+/// let data = large_objects::get_buffer_data();
+/// ```
+#[cfg(feature = "hashing")]
+pub fn verify_buffer(expected_hex: &str) -> Result<(), String> {
+    let actual = to_hex(&buffer_sha256());
+
+    if actual.eq_ignore_ascii_case(expected_hex.trim()) {
+        Ok(())
+    } else {
+        Err(format!(
+            "SHA-256 mismatch: expected {}, got {}",
+            expected_hex.trim(),
+            actual
+        ))
+    }
+}
+
+#[cfg(feature = "hashing")]
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Client-Side Chunking
+// ═══════════════════════════════════════════════════════════════
+
+/// Split data into `(chunk_id, bytes)` pairs for `append_parallel_chunk`
+///
+/// IDs are 0-based and contiguous, matching what `parallel_chunks_complete`
+/// expects. The last chunk may be shorter than `chunk_size`; empty data gives
+/// no chunks.
+///
+/// # Panics
+/// Panics if `chunk_size` is 0.
+///
+/// # Example
+/// ```rust,ignore
+/// let chunks = large_objects::split_into_chunks(&model_bytes, 1_900_000);
+/// let expected = chunks.len() as u32;
+/// for (chunk_id, bytes) in chunks {
+///     agent.append_parallel_chunk(chunk_id, bytes).await?;
+/// }
+/// assert!(agent.parallel_chunks_complete(expected).await?);
+/// ```
+pub fn split_into_chunks(data: &[u8], chunk_size: usize) -> Vec<(u32, Vec<u8>)> {
+    assert!(chunk_size > 0, "chunk_size must be greater than 0");
+
+    data.chunks(chunk_size)
+        .enumerate()
+        .map(|(i, chunk)| (i as u32, chunk.to_vec()))
+        .collect()
+}
+
+/// Number of chunks `split_into_chunks` produces for `total_len` bytes
+///
+/// # Panics
+/// Panics if `chunk_size` is 0.
+pub fn chunk_count_for(total_len: usize, chunk_size: usize) -> u32 {
+    assert!(chunk_size > 0, "chunk_size must be greater than 0");
+    total_len.div_ceil(chunk_size) as u32
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  HTTP Upload
+// ═══════════════════════════════════════════════════════════════
+
+/// Header carrying the chunk ID for parallel uploads
+pub const CHUNK_ID_HEADER: &str = "X-Chunk-Id";
+
+/// Header carrying the expected chunk count for parallel uploads
+pub const CHUNK_COUNT_HEADER: &str = "X-Chunk-Count";
+
+/// Upload progress reported by `append_from_request`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UploadProgress {
+    pub buffer_size: usize,
+    /// Missing chunk IDs (parallel uploads with `X-Chunk-Count` only)
+    pub missing_chunks: Option<Vec<u32>>,
+}
+
+/// Append an HTTP request body to the upload buffers
+///
+/// With an `X-Chunk-Id` header the body is stored as that parallel chunk,
+/// otherwise it is appended to the sequential buffer. If `X-Chunk-Count` is
+/// also sent, the response lists the chunks still missing. Invalid headers,
+/// or `X-Chunk-Count` without `X-Chunk-Id`, produce a 400.
+///
+/// # Example
+/// ```rust,ignore
+/// fn upload(req: HttpRequest) -> HttpResult<HttpResponse> {
+///     Ok(large_objects::append_from_request(&req))
+/// }
+/// ```
+pub fn append_from_request(request: &HttpRequest) -> HttpResponse {
+    let parse_header = |name: &str| -> Result<Option<u32>, HttpError> {
+        http::get_header(&request.headers, name)
+            .map(|value| {
+                value
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| HttpError::BadRequest(format!("Invalid {} header", name)))
+            })
+            .transpose()
+    };
+
+    let chunk_id = match parse_header(CHUNK_ID_HEADER) {
+        Ok(id) => id,
+        Err(e) => return e.to_response(),
+    };
+    let chunk_count = match parse_header(CHUNK_COUNT_HEADER) {
+        Ok(count) => count,
+        Err(e) => return e.to_response(),
+    };
+
+    let progress = match (chunk_id, chunk_count) {
+        (Some(id), count) => {
+            append_parallel_chunk(id, request.body.clone());
+            UploadProgress {
+                buffer_size: parallel_buffer_size(),
+                missing_chunks: count.map(missing_chunks),
+            }
+        }
+        (None, Some(_)) => {
+            return HttpError::BadRequest(format!("Missing {} header", CHUNK_ID_HEADER))
+                .to_response();
+        }
+        (None, None) => {
+            append_chunk(request.body.clone());
+            UploadProgress {
+                buffer_size: buffer_size(),
+                missing_chunks: None,
+            }
+        }
+    };
+
+    http::success_response(&progress).unwrap_or_else(|e| e.to_response())
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Storage Status and Monitoring
 // ═══════════════════════════════════════════════════════════════
@@ -465,6 +785,24 @@ mod tests {
         assert_eq!(buffer_size(), 0);
     }
 
+    #[test]
+    fn test_named_buffers() {
+        append_named("a", vec![1, 2]);
+        append_named("b", vec![9]);
+        append_named("a", vec![3]);
+
+        assert_eq!(buffer_size_named("a"), 3);
+        assert_eq!(buffer_size_named("missing"), 0);
+        assert_eq!(named_buffers(), vec!["a", "b"]);
+
+        assert_eq!(take_named("a"), vec![1, 2, 3]);
+        assert_eq!(buffer_size_named("a"), 0);
+        assert_eq!(take_named("a"), Vec::<u8>::new());
+
+        clear_named("b");
+        assert!(named_buffers().is_empty());
+    }
+
     #[test]
     fn test_parallel_chunks() {
         clear_parallel_chunks();
@@ -479,4 +817,127 @@ mod tests {
         assert!(!parallel_chunks_complete(4));
         assert_eq!(parallel_chunk_ids(), vec![0, 1, 2]);
     }
+
+    #[test]
+    fn test_consolidate_parallel_chunks_checked() {
+        clear_parallel_chunks();
+        clear_buffer();
+
+        append_parallel_chunk(1, vec![3, 4, 5]);
+        append_parallel_chunk(0, vec![1, 2]);
+        assert_eq!(parallel_assembled_len(), 5);
+
+        assert!(consolidate_parallel_chunks_checked(4).is_err());
+        assert_eq!(parallel_chunk_count(), 2);
+
+        assert_eq!(consolidate_parallel_chunks_checked(5), Ok(5));
+        assert_eq!(get_buffer_data(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_consolidate_parallel_chunks_keep() {
+        clear_parallel_chunks();
+        clear_buffer();
+        // The error from get_parallel_data is passed through
+        assert_eq!(
+            consolidate_parallel_chunks_keep(),
+            Err("No parallel chunks available".to_string())
+        );
+
+        append_parallel_chunk(1, vec![3]);
+        append_parallel_chunk(0, vec![1, 2]);
+
+        assert_eq!(consolidate_parallel_chunks_keep(), Ok(3));
+        assert_eq!(get_buffer_data(), vec![1, 2, 3]);
+        assert_eq!(parallel_chunk_count(), 2);
+    }
+
+    #[test]
+    fn test_append_parallel_chunk_strict() {
+        clear_parallel_chunks();
+
+        assert!(!has_chunk(3));
+        append_parallel_chunk_strict(3, vec![1, 2]).unwrap();
+        assert!(has_chunk(3));
+
+        // Identical retry is fine, conflicting data is rejected
+        assert!(append_parallel_chunk_strict(3, vec![1, 2]).is_ok());
+        assert!(append_parallel_chunk_strict(3, vec![9, 9]).is_err());
+        assert_eq!(get_parallel_data().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_split_into_chunks() {
+        let data: Vec<u8> = (0..10).collect();
+        let chunks = split_into_chunks(&data, 4);
+
+        assert_eq!(chunks.len() as u32, chunk_count_for(data.len(), 4));
+        assert_eq!(chunks[2], (2, vec![8, 9]));
+        assert!(split_into_chunks(&[], 4).is_empty());
+        assert_eq!(chunk_count_for(0, 4), 0);
+        assert_eq!(chunk_count_for(8, 4), 2);
+
+        clear_parallel_chunks();
+        for (chunk_id, bytes) in chunks.into_iter().rev() {
+            append_parallel_chunk(chunk_id, bytes);
+        }
+        assert!(parallel_chunks_complete(chunk_count_for(data.len(), 4)));
+        assert_eq!(get_parallel_data().unwrap(), data);
+    }
+
+    #[cfg(feature = "hashing")]
+    #[test]
+    fn test_content_hashing() {
+        const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+        clear_buffer();
+        append_chunk(b"ab".to_vec());
+        append_chunk(b"c".to_vec());
+        assert!(verify_buffer(ABC_SHA256).is_ok());
+        assert!(verify_buffer(&ABC_SHA256.to_uppercase()).is_ok());
+        assert!(verify_buffer(&"0".repeat(64)).is_err());
+
+        clear_parallel_chunks();
+        assert!(parallel_data_sha256().is_err());
+        append_parallel_chunk(1, b"c".to_vec());
+        append_parallel_chunk(0, b"ab".to_vec());
+        assert_eq!(parallel_data_sha256().unwrap(), buffer_sha256());
+    }
+
+    #[test]
+    fn test_append_from_request() {
+        clear_buffer();
+        clear_parallel_chunks();
+
+        let request = |headers: &[(&str, &str)], body: &[u8]| HttpRequest {
+            method: "POST".to_string(),
+            url: "/upload".to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: body.to_vec(),
+        };
+
+        let response = append_from_request(&request(&[], &[1, 2, 3]));
+        assert_eq!(response.status_code, 200);
+        assert_eq!(buffer_size(), 3);
+
+        let response = append_from_request(&request(
+            &[("x-chunk-id", "1"), ("x-chunk-count", "3")],
+            &[4, 5],
+        ));
+        assert_eq!(response.status_code, 200);
+        assert_eq!(
+            String::from_utf8(response.body).unwrap(),
+            r#"{"buffer_size":2,"missing_chunks":[0,2]}"#
+        );
+
+        let response = append_from_request(&request(&[("X-Chunk-Id", "abc")], &[6]));
+        assert_eq!(response.status_code, 400);
+
+        let response = append_from_request(&request(&[("X-Chunk-Count", "3")], &[6]));
+        assert_eq!(response.status_code, 400);
+        assert_eq!(parallel_chunk_count(), 1);
+    }
 }
\ No newline at end of file
@@ -1,9 +1,16 @@
 //! # ic-dev-kit-rs
 
 pub mod auth;
+pub mod guards;
 pub mod http;
 pub mod large_objects;
 pub mod intercanister;
+pub mod rate_limit;
+
+mod env;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 #[cfg(feature = "telemetry")]
 pub mod telemetry;
@@ -11,6 +18,9 @@ pub mod telemetry;
 #[cfg(feature = "storage")]
 pub mod storage;
 
+#[cfg(feature = "storage")]
+pub mod idempotency;
+
 #[cfg(feature = "candle")]
 pub mod candle;
 
@@ -24,30 +34,42 @@ pub use candid::Principal;
 
 /// Prelude module
 pub mod prelude {
-    pub use crate::auth::{self, AuthError, AuthResult};
+    pub use crate::auth::{self, AuthError, AuthResult, PrincipalStorage};
+    pub use crate::guards::{self, GuardFn};
     pub use crate::http::{self, HttpError, HttpRequest, HttpResponse, HttpResult, HttpMethod};
     pub use crate::large_objects;
     pub use crate::intercanister;
+    pub use crate::rate_limit::{self, RateLimiter, RateLimitError, RateLimitKey};
     pub use candid::Principal;
 
     #[cfg(feature = "telemetry")]
     pub use crate::telemetry::{self, TelemetryError, TelemetryResult};
 
     #[cfg(feature = "storage")]
-    pub use crate::storage::{self, StorageRegistry};
+    pub use crate::storage::{self, ObjectMetadata, ObjectStore, StorageRegistry};
+
+    #[cfg(feature = "storage")]
+    pub use crate::auth::StableAuthStorage;
+
+    #[cfg(feature = "storage")]
+    pub use crate::idempotency::{self, IdempotencyState};
 
     #[cfg(feature = "candle")]
     pub use crate::candle::{
-        self, CandleModel, ModelMetadata, ModelManager, gguf,
+        self, Architecture, CandleModel, ModelMetadata, ModelManager, gguf,
     };
 
     #[cfg(feature = "text-generation")]
     pub use crate::text_generation::{
-        self, AutoregressiveModel, GenerationConfig,
+        self, AutoregressiveModel, GenerationConfig, GenerationConfigBuilder, GenerationContext,
         TokenizerHandle, GenerationResponse, StopReason,
-        generate_autoregressive, format_generation_stats, tokenizers,
+        generate_autoregressive, generate_autoregressive_n, format_generation_stats, sample_token,
+        tokenizers,
     };
 
     #[cfg(all(feature = "text-generation", feature = "storage"))]
-    pub use crate::model_server::{ModelServer, EmptyResult, InferenceRequest, InferenceResponse, ModelInfo};
+    pub use crate::model_server::{
+        ModelServer, ChunkRequest, ConcurrencyPolicy, EmptyResult, GenerationChunk,
+        GenerationStatus, InferenceRequest, InferenceResponse, ModelInfo, SetupState,
+    };
 }
@@ -5,16 +5,51 @@
 
 #![cfg(all(feature = "text-generation", feature = "storage"))]
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::thread::LocalKey;
 use candid::CandidType;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use crate::candle::*;
 use crate::text_generation::*;
 use crate::storage::StorageRegistry;
+use crate::http::{self, HttpResponse};
+
+/// Largest `repeat_last_n` a `ModelServer` accepts; larger values are clamped
+pub const MAX_REPEAT_LAST_N: usize = 1024;
 
 pub struct ModelServer<M: AutoregressiveModel> {
     model: RefCell<Option<M>>,
     tokenizer: RefCell<Option<Box<dyn TokenizerHandle>>>,
+    stream: RefCell<Option<StreamState>>,
+    setup: Cell<SetupState>,
+    policy: Cell<ConcurrencyPolicy>,
+    /// The last stream superseded under `ConcurrencyPolicy::CancelPrevious`
+    cancelled: RefCell<Option<(String, GenerationStatus)>>,
+    max_tokens_limit: Cell<Option<usize>>,
+}
+
+/// What happens when a generation starts while a stream is still running
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConcurrencyPolicy {
+    /// Fail with `Err("generation in progress")` (default)
+    Reject,
+    /// Cancel the running stream and start the new generation
+    CancelPrevious,
+}
+
+/// Lifecycle of `ModelServer::setup_from_storage`
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetupState {
+    NotLoaded,
+    Loading,
+    Loaded,
+}
+
+/// The active poll-based generation (the model has a single KV cache)
+struct StreamState {
+    session: String,
+    max_tokens: usize,
+    status: GenerationStatus,
 }
 
 impl<M: AutoregressiveModel> ModelServer<M> {
@@ -22,15 +57,138 @@ impl<M: AutoregressiveModel> ModelServer<M> {
         Self {
             model: RefCell::new(None),
             tokenizer: RefCell::new(None),
+            stream: RefCell::new(None),
+            setup: Cell::new(SetupState::NotLoaded),
+            policy: Cell::new(ConcurrencyPolicy::Reject),
+            cancelled: RefCell::new(None),
+            max_tokens_limit: Cell::new(None),
+        }
+    }
+
+    /// Cap `max_tokens` for every generation, whatever the client requests
+    ///
+    /// Requests above the cap are clamped (and logged with telemetry) rather
+    /// than rejected. There is no cap by default; set one for any endpoint
+    /// that takes configs from untrusted callers.
+    pub fn set_max_tokens_limit(&self, limit: usize) {
+        self.max_tokens_limit.set(Some(limit.max(1)));
+    }
+
+    pub fn max_tokens_limit(&self) -> Option<usize> {
+        self.max_tokens_limit.get()
+    }
+
+    /// Apply the server-side limits to a client-supplied config
+    fn clamp_config(&self, config: &GenerationConfig) -> GenerationConfig {
+        let mut config = config.clone();
+
+        if let Some(limit) = self.max_tokens_limit.get().filter(|&l| config.max_tokens > l) {
+            #[cfg(feature = "telemetry")]
+            crate::telemetry::log_warning(&format!(
+                "Clamped max_tokens from {} to {}",
+                config.max_tokens, limit
+            ));
+            config.max_tokens = limit;
+        }
+
+        if config.repeat_last_n > MAX_REPEAT_LAST_N {
+            #[cfg(feature = "telemetry")]
+            crate::telemetry::log_warning(&format!(
+                "Clamped repeat_last_n from {} to {}",
+                config.repeat_last_n, MAX_REPEAT_LAST_N
+            ));
+            config.repeat_last_n = MAX_REPEAT_LAST_N;
+        }
+
+        config
+    }
+
+    /// Choose how `generate`, `generate_n` and `start_stream` treat a running stream
+    ///
+    /// With `Reject` (the default) the new request fails until the stream is
+    /// done. With `CancelPrevious` a new prompt supersedes the running stream,
+    /// whose status then reports `cancelled`; opt into it for interactive chat.
+    pub fn set_concurrency_policy(&self, policy: ConcurrencyPolicy) {
+        self.policy.set(policy);
+    }
+
+    pub fn concurrency_policy(&self) -> ConcurrencyPolicy {
+        self.policy.get()
+    }
+
+    /// Apply the concurrency policy before a new generation takes the model
+    fn begin_generation(&self) -> Result<(), String> {
+        let mut stream = self.stream.borrow_mut();
+        let Some(active) = stream.as_ref().filter(|s| !s.status.done) else {
+            return Ok(());
+        };
+
+        match self.policy.get() {
+            ConcurrencyPolicy::Reject => Err(format!(
+                "generation in progress (session {})",
+                active.session
+            )),
+            ConcurrencyPolicy::CancelPrevious => {
+                if let Some(previous) = stream.take() {
+                    let mut status = previous.status;
+                    status.done = true;
+                    status.cancelled = true;
+                    *self.cancelled.borrow_mut() = Some((previous.session, status));
+                }
+                Ok(())
+            }
         }
     }
 
+    /// Load the model and tokenizer from stable storage (single-flight)
+    ///
+    /// Returns `Err("setup in progress")` while another setup is running and
+    /// is a no-op once the model is loaded. A failed setup goes back to
+    /// `SetupState::NotLoaded` so it can be retried. To pick up re-uploaded
+    /// weights, use `reload_from_storage`.
     pub fn setup_from_storage<R: StorageRegistry>(
         &self,
         registry: &RefCell<R>,
         weights_key: &str,
         tokenizer_key: &str,
         get_tokenizer: impl FnOnce(&M) -> Box<dyn TokenizerHandle>,
+    ) -> Result<(), String> {
+        if self.setup.get() == SetupState::Loaded {
+            return Ok(());
+        }
+        self.reload_from_storage(registry, weights_key, tokenizer_key, get_tokenizer)
+    }
+
+    /// Load the model and tokenizer from stable storage, even if already loaded
+    ///
+    /// Still returns `Err("setup in progress")` while another setup is running.
+    pub fn reload_from_storage<R: StorageRegistry>(
+        &self,
+        registry: &RefCell<R>,
+        weights_key: &str,
+        tokenizer_key: &str,
+        get_tokenizer: impl FnOnce(&M) -> Box<dyn TokenizerHandle>,
+    ) -> Result<(), String> {
+        if self.setup.get() == SetupState::Loading {
+            return Err("setup in progress".to_string());
+        }
+
+        self.setup.set(SetupState::Loading);
+        let result = self.load_from_storage(registry, weights_key, tokenizer_key, get_tokenizer);
+        self.setup.set(if result.is_ok() {
+            SetupState::Loaded
+        } else {
+            SetupState::NotLoaded
+        });
+        result
+    }
+
+    fn load_from_storage<R: StorageRegistry>(
+        &self,
+        registry: &RefCell<R>,
+        weights_key: &str,
+        tokenizer_key: &str,
+        get_tokenizer: impl FnOnce(&M) -> Box<dyn TokenizerHandle>,
     ) -> Result<(), String> {
         let weights = crate::storage::load_bytes(registry, weights_key)
             .ok_or(format!("Weights not found: {}", weights_key))?;
@@ -47,6 +205,15 @@ impl<M: AutoregressiveModel> ModelServer<M> {
         Ok(())
     }
 
+    /// Whether the model is not loaded, loading, or ready
+    pub fn setup_state(&self) -> SetupState {
+        self.setup.get()
+    }
+
+    /// Generate a full response
+    ///
+    /// `max_tokens` is clamped to `max_tokens_limit` and `repeat_last_n` to
+    /// `MAX_REPEAT_LAST_N` (as in `generate_n` and `start_stream`).
     pub fn generate(
         &self,
         prompt: String,
@@ -58,12 +225,224 @@ impl<M: AutoregressiveModel> ModelServer<M> {
         let model = model.as_mut().ok_or("Model not initialized")?;
         let tokenizer = tokenizer.as_ref().ok_or("Tokenizer not initialized")?;
 
+        let config = &self.clamp_config(config);
+        config.validate()?;
+        self.begin_generation()?;
         generate_autoregressive(model, prompt, tokenizer.as_ref(), config)
     }
 
+    /// Process a prompt without generating tokens (see `AutoregressiveModel::prefill`)
+    ///
+    /// Returns the number of prompt tokens processed. Like `generate`, it is
+    /// subject to the concurrency policy and ends any finished stream.
+    pub fn warmup(&self, prompt: String) -> Result<usize, String> {
+        let mut model = self.model.borrow_mut();
+        let tokenizer = self.tokenizer.borrow();
+
+        let model = model.as_mut().ok_or("Model not initialized")?;
+        let tokenizer = tokenizer.as_ref().ok_or("Tokenizer not initialized")?;
+
+        self.begin_generation()?;
+        *self.stream.borrow_mut() = None;
+        model.prefill(prompt, tokenizer.as_ref(), &GenerationConfig::default())
+    }
+
+    /// Generate `config.completions()` candidates (see `generate_autoregressive_n`)
+    pub fn generate_n(
+        &self,
+        prompt: String,
+        config: &GenerationConfig,
+    ) -> Result<Vec<GenerationResponse>, String> {
+        let mut model = self.model.borrow_mut();
+        let tokenizer = self.tokenizer.borrow();
+
+        let model = model.as_mut().ok_or("Model not initialized")?;
+        let tokenizer = tokenizer.as_ref().ok_or("Tokenizer not initialized")?;
+
+        let config = &self.clamp_config(config);
+        config.validate()?;
+        self.begin_generation()?;
+        generate_autoregressive_n(model, prompt, tokenizer.as_ref(), config)
+    }
+
+    /// Start a poll-based generation for `session`, returning the first token
+    ///
+    /// A running session is cancelled or causes an error, depending on the
+    /// concurrency policy. Advance it with `continue_stream` from update calls
+    /// and poll `generation_status` from queries.
+    pub fn start_stream(
+        &self,
+        session: String,
+        prompt: String,
+        config: &GenerationConfig,
+    ) -> Result<String, String> {
+        let mut model = self.model.borrow_mut();
+        let tokenizer = self.tokenizer.borrow();
+
+        let model = model.as_mut().ok_or("Model not initialized")?;
+        let tokenizer = tokenizer.as_ref().ok_or("Tokenizer not initialized")?;
+
+        let config = &self.clamp_config(config);
+        config.validate()?;
+        self.begin_generation()?;
+        *self.stream.borrow_mut() = None;
+
+        let start_instructions = crate::env::performance_counter();
+        let (text, error) = match model.init_generation(prompt, tokenizer.as_ref(), config) {
+            Ok(text) => (text, None),
+            Err(e) if is_non_finite_logits(&e) => (String::new(), Some(e)),
+            Err(e) => return Err(e),
+        };
+        let instructions_used = crate::env::performance_counter() - start_instructions;
+
+        let tokens_so_far = model.generated_token_count();
+        *self.stream.borrow_mut() = Some(StreamState {
+            session,
+            max_tokens: config.max_tokens,
+            status: GenerationStatus {
+                tokens_so_far,
+                instructions_used,
+                done: error.is_some()
+                    || model.is_generation_complete()
+                    || tokens_so_far >= config.max_tokens,
+                cancelled: false,
+                error,
+            },
+        });
+
+        Ok(text)
+    }
+
+    /// Generate up to `max_new_tokens` more tokens for `session`
+    ///
+    /// Stops early at EOS, `max_tokens` or the per-call instruction budget.
+    /// Non-finite logits end the session, with the reason in
+    /// `GenerationStatus::error`. Returns the newly generated text (empty once
+    /// the session is done).
+    pub fn continue_stream(&self, session: &str, max_new_tokens: usize) -> Result<String, String> {
+        let mut model = self.model.borrow_mut();
+        let tokenizer = self.tokenizer.borrow();
+        let mut stream = self.stream.borrow_mut();
+
+        let model = model.as_mut().ok_or("Model not initialized")?;
+        let tokenizer = tokenizer.as_ref().ok_or("Tokenizer not initialized")?;
+        let Some(stream) = stream.as_mut().filter(|s| s.session == session) else {
+            if self.cancelled.borrow().as_ref().is_some_and(|(s, _)| s == session) {
+                return Err(format!("Generation session cancelled: {}", session));
+            }
+            return Err(format!("No active generation session: {}", session));
+        };
+
+        let start_instructions = crate::env::performance_counter();
+        let mut text = String::new();
+
+        for _ in 0..max_new_tokens {
+            let finished = stream.status.error.is_some()
+                || model.is_generation_complete()
+                || model.generated_token_count() >= stream.max_tokens;
+            let used = crate::env::performance_counter() - start_instructions;
+            if finished || used > INSTRUCTION_BUDGET {
+                break;
+            }
+            let context = GenerationContext {
+                remaining_instructions: INSTRUCTION_BUDGET - used,
+                tokens_so_far: model.generated_token_count(),
+            };
+            match model.generate_next_token_with_context(tokenizer.as_ref(), &context) {
+                Ok(token_text) => text.push_str(&token_text),
+                Err(e) if is_non_finite_logits(&e) => stream.status.error = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let status = &mut stream.status;
+        status.tokens_so_far = model.generated_token_count();
+        status.instructions_used += crate::env::performance_counter() - start_instructions;
+        status.done = status.error.is_some()
+            || model.is_generation_complete()
+            || status.tokens_so_far >= stream.max_tokens;
+
+        Ok(text)
+    }
+
+    /// Advance `session` by up to `max_tokens_this_call` tokens for a remote caller
+    ///
+    /// `continue_stream` packaged for a coordinator canister that drives a
+    /// worker with repeated intercanister calls, since a single call cannot
+    /// finish a long generation. Start the session with `start_stream` (the
+    /// `start_generation_stream` endpoint), then call `generate_chunk` until
+    /// `done`. The `generate_chunk` endpoint takes a single `ChunkRequest`, so
+    /// it can be called with `intercanister::call` or `call_with_retry`.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// // Coordinator canister
+    /// let args = candid::encode_args((session.clone(), request))
+    ///     .map_err(|e| format!("Failed to encode arguments: {}", e))?;
+    /// let reply = intercanister::call_raw(worker, "start_generation_stream", args).await?;
+    /// let (first,): (Result<String, String>,) =
+    ///     candid::decode_args(&reply).map_err(|e| e.to_string())?;
+    /// let mut text = first?;
+    ///
+    /// let chunk_request = ChunkRequest { session, max_tokens_this_call: 32 };
+    /// loop {
+    ///     let chunk: Result<GenerationChunk, String> = intercanister::call_with_retry(
+    ///         worker,
+    ///         "generate_chunk",
+    ///         chunk_request.clone(),
+    ///         RetryPolicy::new(3),
+    ///     )
+    ///     .await?;
+    ///     let chunk = chunk?;
+    ///     text.push_str(&chunk.new_text);
+    ///     if chunk.done {
+    ///         break;
+    ///     }
+    /// }
+    /// ```
+    pub fn generate_chunk(
+        &self,
+        session: &str,
+        max_tokens_this_call: usize,
+    ) -> Result<GenerationChunk, String> {
+        let start_instructions = crate::env::performance_counter();
+        let new_text = self.continue_stream(session, max_tokens_this_call)?;
+        let done = self.generation_status(session).map(|s| s.done).unwrap_or(true);
+
+        Ok(GenerationChunk {
+            new_text,
+            done,
+            instructions_used: crate::env::performance_counter() - start_instructions,
+        })
+    }
+
+    /// Progress of the poll-based generation for `session`
+    ///
+    /// Read-only, so it can be served from a query while updates advance
+    /// the generation. Returns `None` unless `session` is the active one or
+    /// the last cancelled one.
+    pub fn generation_status(&self, session: &str) -> Option<GenerationStatus> {
+        let active = self
+            .stream
+            .borrow()
+            .as_ref()
+            .filter(|s| s.session == session)
+            .map(|s| s.status.clone());
+
+        active.or_else(|| {
+            self.cancelled
+                .borrow()
+                .as_ref()
+                .filter(|(s, _)| s == session)
+                .map(|(_, status)| status.clone())
+        })
+    }
+
     pub fn reset(&self) -> Result<(), String> {
         let mut model = self.model.borrow_mut();
         model.as_mut().ok_or("Model not initialized")?.reset();
+        *self.stream.borrow_mut() = None;
+        *self.cancelled.borrow_mut() = None;
         Ok(())
     }
 
@@ -78,6 +457,64 @@ impl<M: AutoregressiveModel> ModelServer<M> {
     pub fn metadata(&self) -> Option<ModelMetadata> {
         self.model.borrow().as_ref().map(|m| m.metadata())
     }
+
+    /// Approximate weight footprint of the loaded model in bytes
+    ///
+    /// Best-effort estimate from `ModelMetadata::parameters`; see
+    /// `candle::estimate_footprint`.
+    pub fn estimated_footprint(&self) -> Option<usize> {
+        self.metadata().as_ref().and_then(estimate_footprint)
+    }
+
+    /// Snapshot of the server state, as returned by `get_model_info`
+    pub fn info(&self) -> ModelInfo {
+        ModelInfo {
+            loaded: self.is_loaded(),
+            current_tokens: self.token_count(),
+            metadata: self.metadata(),
+            estimated_footprint: self.estimated_footprint(),
+            heap_usage: heap_usage(),
+        }
+    }
+}
+
+/// Serve `ModelInfo` as JSON, for exposing the model over the HTTP gateway
+///
+/// # Example
+/// ```rust,ignore
+/// fn model_info(_req: HttpRequest) -> HttpResult<HttpResponse> {
+///     Ok(MODEL.with(|s| model_server::model_info_response(s)))
+/// }
+///
+/// router.get("/api/model", model_info);
+/// ```
+pub fn model_info_response<M: AutoregressiveModel>(server: &ModelServer<M>) -> HttpResponse {
+    http::success_response(&server.info()).unwrap_or_else(|e| e.to_response())
+}
+
+/// Generate with a fresh seed from `raw_rand` (update calls only)
+///
+/// Awaits `random_seed` before borrowing the server, since a `thread_local`
+/// can't be held across an `.await`. Overrides `config.seed`; fails before
+/// generating if `random_seed` does.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::update]
+/// async fn generate_random(prompt: String) -> Result<GenerationResponse, String> {
+///     model_server::generate_with_random_seed(&MODEL, prompt, &GenerationConfig::default()).await
+/// }
+/// ```
+pub async fn generate_with_random_seed<M: AutoregressiveModel + 'static>(
+    server: &'static LocalKey<ModelServer<M>>,
+    prompt: String,
+    config: &GenerationConfig,
+) -> Result<GenerationResponse, String> {
+    let config = GenerationConfig {
+        seed: random_seed().await?,
+        ..config.clone()
+    };
+    server.with(|s| s.generate(prompt, &config))
 }
 
 // Response types
@@ -93,6 +530,30 @@ pub struct InferenceRequest {
     pub config: Option<GenerationConfig>,
 }
 
+impl InferenceRequest {
+    /// Build a request from query parameters (`?prompt=hi&max_tokens=50`)
+    ///
+    /// The prompt is percent-decoded; sampling parameters are read with
+    /// `GenerationConfig::from_query`. Returns `None` without a `prompt`.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// router.get("/generate", |req| {
+    ///     let params = http::extract_query_params(&req.url);
+    ///     let request = InferenceRequest::from_query(&params)
+    ///         .ok_or_else(|| HttpError::bad_request("missing prompt"))?;
+    ///     ...
+    /// });
+    /// ```
+    pub fn from_query(params: &std::collections::HashMap<String, String>) -> Option<Self> {
+        let prompt = http::percent_decode(params.get("prompt")?);
+        Some(Self {
+            prompt,
+            config: Some(GenerationConfig::from_query(params)),
+        })
+    }
+}
+
 #[derive(CandidType, Deserialize)]
 pub struct InferenceResponse {
     pub generated_text: String,
@@ -100,6 +561,12 @@ pub struct InferenceResponse {
     pub instructions_used: u64,
     pub success: bool,
     pub error: Option<String>,
+    /// Additional candidate completions when `num_completions > 1`
+    pub alternatives: Option<Vec<String>>,
+    /// Part of `instructions_used` spent on the prompt (see `GenerationResponse`)
+    pub prefill_instructions: Option<u64>,
+    /// Part of `instructions_used` spent generating after the first token
+    pub decode_instructions: Option<u64>,
 }
 
 impl From<GenerationResponse> for InferenceResponse {
@@ -110,20 +577,94 @@ impl From<GenerationResponse> for InferenceResponse {
             instructions_used: resp.instructions_used,
             success: true,
             error: None,
+            alternatives: None,
+            prefill_instructions: Some(resp.prefill_instructions),
+            decode_instructions: Some(resp.decode_instructions),
         }
     }
 }
 
-#[derive(CandidType, Deserialize)]
+impl From<Vec<GenerationResponse>> for InferenceResponse {
+    /// The first completion is the main result; the rest become `alternatives`.
+    /// Token and instruction counts are totals over all completions.
+    fn from(responses: Vec<GenerationResponse>) -> Self {
+        let mut responses = responses.into_iter();
+        let Some(first) = responses.next() else {
+            return Self {
+                generated_text: String::new(),
+                tokens_generated: 0,
+                instructions_used: 0,
+                success: false,
+                error: Some("No completions generated".to_string()),
+                alternatives: None,
+                prefill_instructions: None,
+                decode_instructions: None,
+            };
+        };
+
+        let mut response = Self::from(first);
+        let mut alternatives = Vec::new();
+        for alternative in responses {
+            response.tokens_generated += alternative.tokens_generated;
+            response.instructions_used += alternative.instructions_used;
+            response.prefill_instructions = response
+                .prefill_instructions
+                .map(|n| n + alternative.prefill_instructions);
+            response.decode_instructions = response
+                .decode_instructions
+                .map(|n| n + alternative.decode_instructions);
+            alternatives.push(alternative.text);
+        }
+        response.alternatives = Some(alternatives);
+        response
+    }
+}
+
+/// Progress of a poll-based generation (see `ModelServer::start_stream`)
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct GenerationStatus {
+    pub tokens_so_far: usize,
+    /// Instructions spent across all update calls of the session
+    pub instructions_used: u64,
+    pub done: bool,
+    /// Superseded by a newer generation (see `ConcurrencyPolicy::CancelPrevious`)
+    pub cancelled: bool,
+    /// Why the session stopped early, e.g. `NON_FINITE_LOGITS`
+    pub error: Option<String>,
+}
+
+/// Arguments of the `generate_chunk` endpoint, as one record for `intercanister::call`
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChunkRequest {
+    pub session: String,
+    pub max_tokens_this_call: u32,
+}
+
+/// Text produced by one `ModelServer::generate_chunk` call
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct GenerationChunk {
+    pub new_text: String,
+    pub done: bool,
+    /// Instructions spent in this call
+    pub instructions_used: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize)]
 pub struct ModelInfo {
     pub loaded: bool,
     pub current_tokens: usize,
     pub metadata: Option<ModelMetadata>,
+    /// Approximate weight footprint in bytes (best-effort)
+    pub estimated_footprint: Option<usize>,
+    /// Current wasm heap size in bytes
+    pub heap_usage: usize,
 }
 
 /// Macro to generate all IC endpoints for a model server
 ///
-/// This generates: setup_model, generate, reset_generation, is_model_loaded, get_model_info
+/// This generates: setup_model, generate, start_generation_stream,
+/// continue_generation_stream, generate_chunk, get_generation_status, reset_generation,
+/// is_model_loaded, get_setup_state, get_model_info
 #[macro_export]
 macro_rules! generate_model_endpoints {
     (
@@ -133,7 +674,10 @@ macro_rules! generate_model_endpoints {
         tokenizer_key: $tokenizer_key:expr,
         get_tokenizer: $get_tokenizer:expr
     ) => {
-        use $crate::model_server::{EmptyResult, InferenceRequest, InferenceResponse, ModelInfo};
+        use $crate::model_server::{
+            ChunkRequest, EmptyResult, GenerationChunk, GenerationStatus, InferenceRequest,
+            InferenceResponse, ModelInfo, SetupState,
+        };
 
         #[ic_cdk::update(guard = "ic_dev_kit_rs::auth::is_authorized")]
         pub fn setup_model() -> EmptyResult {
@@ -166,8 +710,14 @@ macro_rules! generate_model_endpoints {
             let config = request.config.unwrap_or_default();
 
             $server.with(|s| {
-                match s.generate(request.prompt, &config) {
-                    Ok(response) => response.into(),
+                let result = if config.completions() > 1 {
+                    s.generate_n(request.prompt, &config).map(InferenceResponse::from)
+                } else {
+                    s.generate(request.prompt, &config).map(InferenceResponse::from)
+                };
+
+                match result {
+                    Ok(response) => response,
                     Err(e) => {
                         #[cfg(feature = "telemetry")]
                         $crate::telemetry::log_error(&format!("Generation failed: {}", e));
@@ -177,12 +727,43 @@ macro_rules! generate_model_endpoints {
                             instructions_used: 0,
                             success: false,
                             error: Some(e),
+                            alternatives: None,
+                            prefill_instructions: None,
+                            decode_instructions: None,
                         }
                     }
                 }
             })
         }
 
+        #[ic_cdk::update]
+        pub fn start_generation_stream(
+            session: String,
+            request: InferenceRequest,
+        ) -> Result<String, String> {
+            let config = request.config.unwrap_or_default();
+            $server.with(|s| s.start_stream(session, request.prompt, &config))
+        }
+
+        #[ic_cdk::update]
+        pub fn continue_generation_stream(
+            session: String,
+            max_new_tokens: u32,
+        ) -> Result<String, String> {
+            $server.with(|s| s.continue_stream(&session, max_new_tokens as usize))
+        }
+
+        #[ic_cdk::update]
+        pub fn generate_chunk(request: ChunkRequest) -> Result<GenerationChunk, String> {
+            let max_tokens = request.max_tokens_this_call as usize;
+            $server.with(|s| s.generate_chunk(&request.session, max_tokens))
+        }
+
+        #[ic_cdk::query]
+        pub fn get_generation_status(session: String) -> Option<GenerationStatus> {
+            $server.with(|s| s.generation_status(&session))
+        }
+
         #[ic_cdk::update(guard = "ic_dev_kit_rs::auth::is_authorized")]
         pub fn reset_generation() -> EmptyResult {
             $server.with(|s| match s.reset() {
@@ -196,13 +777,276 @@ macro_rules! generate_model_endpoints {
             $server.with(|s| s.is_loaded())
         }
 
+        #[ic_cdk::query]
+        pub fn get_setup_state() -> SetupState {
+            $server.with(|s| s.setup_state())
+        }
+
         #[ic_cdk::query]
         pub fn get_model_info() -> ModelInfo {
-            $server.with(|s| ModelInfo {
-                loaded: s.is_loaded(),
-                current_tokens: s.token_count(),
-                metadata: s.metadata(),
-            })
+            $server.with(|s| s.info())
         }
     };
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_generation::tokenizers;
+    use crate::testing::TestRegistry;
+
+    /// Emits the bytes of its weights one token at a time (`!` acts as NaN logits)
+    struct EchoModel {
+        weights: Vec<u8>,
+        position: usize,
+        max_tokens: usize,
+    }
+
+    impl CandleModel for EchoModel {
+        fn load(weights: Vec<u8>, _config: Option<Vec<u8>>) -> Result<Self, String> {
+            Ok(EchoModel {
+                weights,
+                position: 0,
+                max_tokens: 0,
+            })
+        }
+
+        fn metadata(&self) -> ModelMetadata {
+            ModelMetadata {
+                name: String::from_utf8_lossy(&self.weights).into_owned(),
+                version: "1".to_string(),
+                architecture: "echo".to_string(),
+                parameters: self.weights.len() as u64,
+                context_length: None,
+            }
+        }
+
+        fn reset(&mut self) {
+            self.position = 0;
+        }
+    }
+
+    impl AutoregressiveModel for EchoModel {
+        fn init_generation(
+            &mut self,
+            _prompt: String,
+            tokenizer: &dyn TokenizerHandle,
+            config: &GenerationConfig,
+        ) -> Result<String, String> {
+            self.position = 0;
+            self.max_tokens = config.max_tokens;
+            self.generate_next_token(tokenizer)
+        }
+
+        fn generate_next_token(
+            &mut self,
+            tokenizer: &dyn TokenizerHandle,
+        ) -> Result<String, String> {
+            let byte = *self.weights.get(self.position).ok_or("no more weights")?;
+            if byte == b'!' {
+                return Err(NON_FINITE_LOGITS.to_string());
+            }
+            self.position += 1;
+            tokenizer.decode(&[u32::from(byte)])
+        }
+
+        fn is_generation_complete(&self) -> bool {
+            self.position >= self.weights.len().min(self.max_tokens)
+        }
+
+        fn generated_token_count(&self) -> usize {
+            self.position
+        }
+    }
+
+    fn registry_with(weights: &[u8]) -> RefCell<TestRegistry> {
+        let registry = RefCell::new(TestRegistry::default());
+        crate::storage::save_bytes(&registry, "weights", weights.to_vec());
+        crate::storage::save_bytes(&registry, "tokenizer", Vec::new());
+        registry
+    }
+
+    fn setup(server: &ModelServer<EchoModel>, registry: &RefCell<TestRegistry>) {
+        server
+            .setup_from_storage(registry, "weights", "tokenizer", |_| {
+                Box::new(tokenizers::byte_level())
+            })
+            .unwrap();
+    }
+
+    fn reload(server: &ModelServer<EchoModel>, registry: &RefCell<TestRegistry>) {
+        server
+            .reload_from_storage(registry, "weights", "tokenizer", |_| {
+                Box::new(tokenizers::byte_level())
+            })
+            .unwrap();
+    }
+
+    fn model_name(server: &ModelServer<EchoModel>) -> String {
+        server.metadata().unwrap().name
+    }
+
+    #[test]
+    fn test_setup_and_reload() {
+        let server = ModelServer::<EchoModel>::new();
+        let registry = registry_with(b"old");
+        assert_eq!(server.setup_state(), SetupState::NotLoaded);
+
+        setup(&server, &registry);
+        assert_eq!(server.setup_state(), SetupState::Loaded);
+        assert_eq!(model_name(&server), "old");
+
+        // Once loaded, setup is a no-op
+        crate::storage::save_bytes(&registry, "weights", b"new".to_vec());
+        setup(&server, &registry);
+        assert_eq!(model_name(&server), "old");
+
+        // Resetting the generation state keeps the model loaded
+        server.reset().unwrap();
+        assert_eq!(server.setup_state(), SetupState::Loaded);
+        setup(&server, &registry);
+        assert_eq!(model_name(&server), "old");
+
+        // reload_from_storage picks up the re-uploaded weights
+        reload(&server, &registry);
+        assert_eq!(server.setup_state(), SetupState::Loaded);
+        assert_eq!(model_name(&server), "new");
+    }
+
+    #[test]
+    fn test_warmup_without_prefill_override() {
+        crate::testing::set_performance_counter(0);
+        let server = ModelServer::<EchoModel>::new();
+        assert!(server.warmup("hi".to_string()).is_err());
+
+        setup(&server, &registry_with(b"weights"));
+        assert_eq!(server.warmup("hi".to_string()), Ok(2));
+        assert_eq!(server.model.borrow().as_ref().unwrap().position, 0);
+
+        // Warmup follows the concurrency policy like any other generation
+        let config = GenerationConfig::default();
+        server.start_stream("s".to_string(), "hi".to_string(), &config).unwrap();
+        assert_eq!(server.concurrency_policy(), ConcurrencyPolicy::Reject);
+        assert!(server.warmup("hi".to_string()).is_err());
+        assert!(!server.generation_status("s").unwrap().done);
+
+        server.set_concurrency_policy(ConcurrencyPolicy::CancelPrevious);
+        assert_eq!(server.warmup("hi".to_string()), Ok(2));
+        assert!(server.generation_status("s").unwrap().cancelled);
+        assert!(server.continue_stream("s", 1).is_err());
+        crate::testing::reset();
+    }
+
+    #[test]
+    fn test_generate_and_stream() {
+        crate::testing::set_performance_counter(0);
+        let server = ModelServer::<EchoModel>::new();
+        setup(&server, &registry_with(b"abcdef"));
+        let config = GenerationConfig {
+            max_tokens: 4,
+            ..Default::default()
+        };
+
+        let response = server.generate("prompt".to_string(), &config).unwrap();
+        assert_eq!(response.text, "abcd");
+        assert_eq!(response.stopped_reason, StopReason::MaxTokens);
+
+        let first = server.start_stream("s".to_string(), "prompt".to_string(), &config);
+        assert_eq!(first.unwrap(), "a");
+        assert_eq!(server.continue_stream("s", 2).unwrap(), "bc");
+        assert_eq!(server.continue_stream("s", 2).unwrap(), "d");
+        assert!(server.generation_status("s").unwrap().done);
+        crate::testing::reset();
+    }
+
+    #[test]
+    fn test_non_finite_logits() {
+        crate::testing::set_performance_counter(0);
+        let server = ModelServer::<EchoModel>::new();
+        setup(&server, &registry_with(b"ab!c"));
+        let config = GenerationConfig {
+            max_tokens: 4,
+            num_completions: Some(2),
+            ..Default::default()
+        };
+
+        for response in server.generate_n("prompt".to_string(), &config).unwrap() {
+            assert_eq!(response.text, "ab");
+            let reason = StopReason::Error(NON_FINITE_LOGITS.to_string());
+            assert_eq!(response.stopped_reason, reason);
+        }
+
+        server.start_stream("s".to_string(), "prompt".to_string(), &config).unwrap();
+        assert_eq!(server.continue_stream("s", 3).unwrap(), "b");
+        let status = server.generation_status("s").unwrap();
+        assert!(status.done);
+        assert_eq!(status.error.as_deref(), Some(NON_FINITE_LOGITS));
+        assert_eq!(server.continue_stream("s", 3).unwrap(), "");
+
+        // Failing on the first token still leaves a finished session
+        reload(&server, &registry_with(b"!"));
+        let first = server.start_stream("t".to_string(), "prompt".to_string(), &config);
+        assert_eq!(first.unwrap(), "");
+        assert!(server.generation_status("t").unwrap().done);
+        crate::testing::reset();
+    }
+
+    #[test]
+    fn test_clamp_config() {
+        let server = ModelServer::<EchoModel>::new();
+        let config = GenerationConfig {
+            max_tokens: 50,
+            repeat_last_n: 64,
+            ..Default::default()
+        };
+
+        // No limit by default
+        assert_eq!(server.max_tokens_limit(), None);
+        assert_eq!(server.clamp_config(&config).max_tokens, 50);
+
+        server.set_max_tokens_limit(10);
+        assert_eq!(server.clamp_config(&config).max_tokens, 10);
+
+        let under = GenerationConfig {
+            max_tokens: 5,
+            ..config.clone()
+        };
+        assert_eq!(server.clamp_config(&under).max_tokens, 5);
+
+        server.set_max_tokens_limit(0);
+        assert_eq!(server.max_tokens_limit(), Some(1));
+        assert_eq!(server.clamp_config(&config).max_tokens, 1);
+    }
+
+    #[test]
+    fn test_clamp_repeat_last_n() {
+        let server = ModelServer::<EchoModel>::new();
+        let config = GenerationConfig {
+            repeat_last_n: MAX_REPEAT_LAST_N * 4,
+            ..Default::default()
+        };
+        assert_eq!(server.clamp_config(&config).repeat_last_n, MAX_REPEAT_LAST_N);
+
+        let config = GenerationConfig {
+            repeat_last_n: 64,
+            ..Default::default()
+        };
+        assert_eq!(server.clamp_config(&config).repeat_last_n, 64);
+    }
+
+    #[test]
+    fn test_failed_setup_can_be_retried() {
+        let server = ModelServer::<EchoModel>::new();
+        let registry = RefCell::new(TestRegistry::default());
+
+        let result = server.setup_from_storage(&registry, "weights", "tokenizer", |_| {
+            Box::new(tokenizers::byte_level())
+        });
+        assert_eq!(result, Err("Weights not found: weights".to_string()));
+        assert_eq!(server.setup_state(), SetupState::NotLoaded);
+
+        crate::storage::save_bytes(&registry, "weights", b"w".to_vec());
+        crate::storage::save_bytes(&registry, "tokenizer", Vec::new());
+        setup(&server, &registry);
+        assert_eq!(server.setup_state(), SetupState::Loaded);
+    }
+}
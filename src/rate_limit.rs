@@ -0,0 +1,255 @@
+// Rate limiting module for Internet Computer canisters
+use candid::Principal;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::http::{self, HttpError, HttpRequest, HttpResponse};
+
+// ═══════════════════════════════════════════════════════════════
+//  Error Types
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Rate limit exceeded, retry after {retry_after_secs}s")]
+pub struct RateLimitError {
+    pub retry_after_secs: u64,
+}
+
+impl RateLimitError {
+    /// Convert to a 429 response with a `Retry-After` header
+    pub fn to_response(&self) -> HttpResponse {
+        HttpError::from(self.clone()).to_response()
+    }
+}
+
+impl From<RateLimitError> for HttpError {
+    fn from(e: RateLimitError) -> Self {
+        HttpError::TooManyRequests {
+            retry_after_secs: e.retry_after_secs,
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Rate Limiter
+// ═══════════════════════════════════════════════════════════════
+
+/// Fixed-window rate limiter
+///
+/// Each key may make `max_per_window` calls per `window_ns` nanoseconds.
+/// Keys are caller principals for guards, or any string (e.g. an API key
+/// header) for HTTP requests.
+pub struct RateLimiter {
+    pub max_per_window: u32,
+    pub window_ns: u64,
+    windows: RefCell<HashMap<String, (u64, u32)>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_per_window: u32, window_ns: u64) -> Self {
+        Self {
+            max_per_window,
+            window_ns,
+            windows: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Count a call from `principal` against the limit
+    pub fn check(&self, principal: Principal) -> Result<(), RateLimitError> {
+        self.check_key_at(&principal.to_text(), crate::env::time())
+    }
+
+    /// Count a call for an arbitrary key against the limit
+    pub fn check_key(&self, key: &str) -> Result<(), RateLimitError> {
+        self.check_key_at(key, crate::env::time())
+    }
+
+    /// Count an HTTP request against the limit, keyed by header or query param
+    ///
+    /// The key is taken from the `key_name` header, falling back to the query
+    /// parameter of the same name. Requests without a key share one bucket.
+    pub fn check_request(&self, request: &HttpRequest, key_name: &str) -> Result<(), RateLimitError> {
+        let key = key_from_request(request, key_name).unwrap_or_default();
+        self.check_key(&key)
+    }
+
+    /// Count a call for `key` at time `now` (nanoseconds)
+    pub fn check_key_at(&self, key: &str, now: u64) -> Result<(), RateLimitError> {
+        let mut windows = self.windows.borrow_mut();
+        let (window_start, count) = windows.entry(key.to_string()).or_insert((now, 0));
+
+        if now.saturating_sub(*window_start) >= self.window_ns {
+            *window_start = now;
+            *count = 0;
+        }
+
+        if *count >= self.max_per_window {
+            let remaining_ns = (*window_start + self.window_ns).saturating_sub(now);
+            return Err(RateLimitError {
+                retry_after_secs: remaining_ns.div_ceil(1_000_000_000),
+            });
+        }
+
+        *count += 1;
+        Ok(())
+    }
+
+    /// Drop windows that have expired (call periodically to bound memory)
+    pub fn prune(&self, now: u64) {
+        let window_ns = self.window_ns;
+        self.windows
+            .borrow_mut()
+            .retain(|_, (start, _)| now.saturating_sub(*start) < window_ns);
+    }
+}
+
+/// Get a rate-limit key from a header, falling back to a query parameter
+pub fn key_from_request(request: &HttpRequest, name: &str) -> Option<String> {
+    http::get_header(&request.headers, name)
+        .map(|v| v.to_string())
+        .or_else(|| http::extract_query_params(&request.url).remove(name))
+}
+
+/// How a router-level rate limit derives its key from a request
+///
+/// Requests without a key share one bucket, as with `check_request`.
+#[derive(Clone)]
+pub enum RateLimitKey {
+    /// Header value, falling back to the query parameter of the same name
+    Header(String),
+    /// Key computed by a caller-provided function
+    Custom(fn(&HttpRequest) -> String),
+}
+
+impl RateLimitKey {
+    pub fn header(name: impl Into<String>) -> Self {
+        RateLimitKey::Header(name.into())
+    }
+
+    /// Get the key for `request`
+    pub fn key_for(&self, request: &HttpRequest) -> String {
+        match self {
+            RateLimitKey::Header(name) => key_from_request(request, name).unwrap_or_default(),
+            RateLimitKey::Custom(f) => f(request),
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Global Rate Limiter (Thread-Local for IC)
+// ═══════════════════════════════════════════════════════════════
+
+thread_local! {
+    static LIMITER: RefCell<Option<RateLimiter>> = RefCell::new(None);
+}
+
+/// Initialize the global rate limiter used by `rate_limit_guard`
+pub fn init(max_per_window: u32, window_ns: u64) {
+    LIMITER.with(|l| *l.borrow_mut() = Some(RateLimiter::new(max_per_window, window_ns)));
+}
+
+fn with_limiter<R, F>(f: F) -> R
+where
+    F: FnOnce(&RateLimiter) -> R,
+{
+    LIMITER.with(|l| {
+        let limiter_ref = l.borrow();
+        let limiter = limiter_ref
+            .as_ref()
+            .expect("Rate limiter not initialized - call rate_limit::init() first");
+        f(limiter)
+    })
+}
+
+/// Guard function limiting calls per caller principal
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::init]
+/// fn init() {
+///     rate_limit::init(10, 60_000_000_000); // 10 calls per minute
+/// }
+///
+/// #[ic_cdk::update(guard = "rate_limit::rate_limit_guard")]
+/// fn expensive_method() { }
+/// ```
+pub fn rate_limit_guard() -> Result<(), String> {
+    let caller = crate::env::caller();
+    with_limiter(|limiter| limiter.check(caller).map_err(|e| e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_window() {
+        let limiter = RateLimiter::new(2, 10_000_000_000);
+
+        assert!(limiter.check_key_at("alice", 0).is_ok());
+        assert!(limiter.check_key_at("alice", 1).is_ok());
+        assert_eq!(
+            limiter.check_key_at("alice", 2_500_000_000),
+            Err(RateLimitError { retry_after_secs: 8 })
+        );
+
+        // Other keys are independent
+        assert!(limiter.check_key_at("bob", 2).is_ok());
+
+        // New window
+        assert!(limiter.check_key_at("alice", 10_000_000_000).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limit_guard_with_mocked_time() {
+        use crate::testing;
+
+        init(1, 10_000_000_000);
+        testing::set_caller(Principal::from_slice(&[1]));
+        testing::set_time(0);
+
+        assert!(rate_limit_guard().is_ok());
+        assert!(rate_limit_guard().is_err());
+
+        testing::advance_time(10_000_000_000);
+        assert!(rate_limit_guard().is_ok());
+        testing::reset();
+    }
+
+    #[test]
+    fn test_key_from_request() {
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            url: "/api/data?api_key=abc".to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+        assert_eq!(key_from_request(&request, "api_key"), Some("abc".to_string()));
+
+        let request = HttpRequest {
+            headers: vec![("Api_Key".to_string(), "xyz".to_string())],
+            ..request
+        };
+        assert_eq!(key_from_request(&request, "api_key"), Some("xyz".to_string()));
+    }
+
+    #[test]
+    fn test_rate_limit_key() {
+        let request = HttpRequest {
+            method: "POST".to_string(),
+            url: "/generate?api_key=abc".to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+        assert_eq!(RateLimitKey::header("api_key").key_for(&request), "abc");
+        assert_eq!(RateLimitKey::header("missing").key_for(&request), "");
+        assert_eq!(RateLimitKey::Custom(|r| r.method.clone()).key_for(&request), "POST");
+    }
+
+    #[test]
+    fn test_rate_limit_response() {
+        let response = RateLimitError { retry_after_secs: 5 }.to_response();
+        assert_eq!(response.status_code, 429);
+        assert_eq!(http::get_header(&response.headers, "retry-after"), Some("5"));
+    }
+}
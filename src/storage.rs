@@ -4,13 +4,55 @@
 
 use candid::{CandidType, Decode, Encode};
 use ic_stable_structures::StableBTreeMap;
-use std::cell::RefCell;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
 
 /// Storage registry trait - implement this for your registry type
 pub trait StorageRegistry {
     fn insert(&mut self, key: String, value: Vec<u8>);
     fn get(&self, key: &String) -> Option<Vec<u8>>;
     fn remove(&mut self, key: &String) -> Option<Vec<u8>>;
+
+    /// Visit every entry
+    ///
+    /// The default `len`, `total_bytes`, `iter` and `keys_with_prefix` (and so
+    /// `stats` and `delete_prefix`) are built on this.
+    fn visit_entries(&self, f: &mut dyn FnMut(&String, &[u8]));
+
+    /// Number of entries (default: counts `visit_entries`)
+    fn len(&self) -> usize {
+        let mut count = 0;
+        self.visit_entries(&mut |_, _| count += 1);
+        count
+    }
+
+    /// Whether the registry has no entries
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total size of all values in bytes (default: sums `visit_entries`)
+    fn total_bytes(&self) -> usize {
+        let mut total = 0;
+        self.visit_entries(&mut |_, value| total += value.len());
+        total
+    }
+
+    /// Iterate over all entries (default: collects `visit_entries` first)
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + '_> {
+        let mut entries = Vec::new();
+        self.visit_entries(&mut |key, value| entries.push((key.clone(), value.to_vec())));
+        Box::new(entries.into_iter())
+    }
+
+    /// Keys starting with `prefix` (default: filters `iter`)
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .collect()
+    }
 }
 
 // Implement for StableBTreeMap
@@ -29,6 +71,71 @@ where
     fn remove(&mut self, key: &String) -> Option<Vec<u8>> {
         StableBTreeMap::remove(self, key)
     }
+
+    fn visit_entries(&self, f: &mut dyn FnMut(&String, &[u8])) {
+        for (key, value) in self.keys().zip(self.values()) {
+            f(&key, &value);
+        }
+    }
+
+    fn len(&self) -> usize {
+        StableBTreeMap::len(self) as usize
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.values().map(|value| value.len()).sum()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + '_> {
+        Box::new(self.keys().zip(self.values()))
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.keys_range(prefix.to_string()..)
+            .take_while(|key| key.starts_with(prefix))
+            .collect()
+    }
+}
+
+/// Aggregate statistics for a registry
+#[derive(CandidType, candid::Deserialize, Clone, Debug, PartialEq)]
+pub struct StorageStats {
+    pub entries: usize,
+    /// Total size of all values (keys not included)
+    pub total_bytes: usize,
+}
+
+/// Get the entry count and total value size of a registry
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::query]
+/// fn storage_stats() -> storage::StorageStats {
+///     REGISTRY.with(|reg| storage::stats(reg))
+/// }
+/// ```
+pub fn stats<R: StorageRegistry>(registry: &RefCell<R>) -> StorageStats {
+    let registry = registry.borrow();
+    StorageStats {
+        entries: registry.len(),
+        total_bytes: registry.total_bytes(),
+    }
+}
+
+/// Copy every entry out of a registry, e.g. for a full backup
+///
+/// The whole registry is loaded into heap memory; for large registries page
+/// through `StorageRegistry::iter` instead.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::query(guard = "auth::is_authorized")]
+/// fn export() -> Vec<(String, Vec<u8>)> {
+///     REGISTRY.with(|reg| storage::export_all(reg))
+/// }
+/// ```
+pub fn export_all<R: StorageRegistry>(registry: &RefCell<R>) -> Vec<(String, Vec<u8>)> {
+    registry.borrow().iter().collect()
 }
 
 /// Save any CandidType to storage with automatic serialization
@@ -94,6 +201,61 @@ where
     })
 }
 
+/// Save any `Serialize` type to storage as JSON
+///
+/// For types that don't implement `CandidType`, or when off-chain systems read
+/// the raw bytes. JSON is larger and slower to encode than Candid and has no
+/// type information, so prefer `save_candid` when both are available.
+///
+/// # Example
+/// ```rust,ignore
+/// REGISTRY.with(|reg| storage::save_json(reg, "config", &app_config))?;
+/// ```
+pub fn save_json<T: Serialize, R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    data: &T,
+) -> Result<(), String> {
+    match serde_json::to_vec(data) {
+        Ok(serialized_bytes) => {
+            registry.borrow_mut().insert(key.to_string(), serialized_bytes);
+            #[cfg(feature = "telemetry")]
+            crate::telemetry::log_info(&format!("Saved JSON to stable storage: {}", key));
+            Ok(())
+        }
+        Err(e) => {
+            let err_msg = format!("Failed to serialize JSON for key {}: {}", key, e);
+            #[cfg(feature = "telemetry")]
+            crate::telemetry::log_error(&err_msg);
+            Err(err_msg)
+        }
+    }
+}
+
+/// Load a JSON value saved with `save_json`
+pub fn load_json<T: DeserializeOwned, R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+) -> Option<T> {
+    registry.borrow().get(&key.to_string()).and_then(|serialized_bytes| {
+        match serde_json::from_slice(&serialized_bytes) {
+            Ok(data) => {
+                #[cfg(feature = "telemetry")]
+                crate::telemetry::log_info(&format!("Loaded JSON from stable storage: {}", key));
+                Some(data)
+            }
+            Err(_e) => {
+                #[cfg(feature = "telemetry")]
+                crate::telemetry::log_error(&format!(
+                    "Failed to deserialize JSON for key {}: {}",
+                    key, _e
+                ));
+                None
+            }
+        }
+    })
+}
+
 /// Save raw bytes to storage
 pub fn save_bytes<R: StorageRegistry>(
     registry: &RefCell<R>,
@@ -132,6 +294,38 @@ pub fn delete<R: StorageRegistry>(
     removed
 }
 
+/// Delete every entry whose key starts with `prefix`, returning the count
+///
+/// Finds the keys with `StorageRegistry::keys_with_prefix`: a range scan for
+/// `StableBTreeMap`, and a walk over `visit_entries` for other registries.
+///
+/// # Example
+/// ```rust,ignore
+/// let removed = REGISTRY.with(|reg| storage::delete_prefix(reg, "session:"));
+/// ```
+pub fn delete_prefix<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    prefix: &str,
+) -> usize {
+    let keys = registry.borrow().keys_with_prefix(prefix);
+
+    let mut registry = registry.borrow_mut();
+    let removed = keys
+        .iter()
+        .filter(|key| registry.remove(key).is_some())
+        .count();
+
+    if removed > 0 {
+        #[cfg(feature = "telemetry")]
+        crate::telemetry::log_info(&format!(
+            "Deleted {} entries with prefix {} from stable storage",
+            removed, prefix
+        ));
+    }
+
+    removed
+}
+
 /// Check if key exists in storage
 pub fn exists<R: StorageRegistry>(
     registry: &RefCell<R>,
@@ -148,35 +342,693 @@ pub fn size<R: StorageRegistry>(
     registry.borrow().get(&key.to_string()).map(|bytes| bytes.len())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
+// ═══════════════════════════════════════════════════════════════
+//  Capacity Checks
+// ═══════════════════════════════════════════════════════════════
+
+/// Maximum stable memory a canister can use (500 GiB)
+pub const MAX_STABLE_MEMORY_BYTES: u64 = 500 * 1024 * 1024 * 1024;
+
+/// Size of a stable memory page
+const WASM_PAGE_SIZE: u64 = 65536;
+
+thread_local! {
+    static STABLE_MEMORY_LIMIT: Cell<u64> = Cell::new(MAX_STABLE_MEMORY_BYTES);
+}
+
+/// Lower the stable memory limit used by `available_stable_memory`
+///
+/// Set this to the canister's memory allocation, or below it to keep headroom.
+pub fn set_stable_memory_limit(bytes: u64) {
+    STABLE_MEMORY_LIMIT.with(|l| l.set(bytes));
+}
 
-    // Simple test registry
-    struct TestRegistry {
-        map: HashMap<String, Vec<u8>>,
+/// Get the stable memory limit (default `MAX_STABLE_MEMORY_BYTES`)
+pub fn stable_memory_limit() -> u64 {
+    STABLE_MEMORY_LIMIT.with(|l| l.get())
+}
+
+/// Bytes of stable memory that can still be allocated
+///
+/// The limit minus the pages already allocated (pages × 64 KiB). Free space
+/// inside allocated pages is not counted, so this is conservative.
+pub fn available_stable_memory() -> u64 {
+    available_after(ic_cdk::stable::stable_size() * WASM_PAGE_SIZE)
+}
+
+/// Check whether `bytes` more data fits in the available stable memory
+///
+/// Map overhead (keys, node headers) is not included; leave a margin for
+/// small writes.
+pub fn can_fit(bytes: usize) -> bool {
+    bytes as u64 <= available_stable_memory()
+}
+
+fn available_after(allocated: u64) -> u64 {
+    stable_memory_limit().saturating_sub(allocated)
+}
+
+/// `save_bytes` that fails instead of trapping when stable memory is short
+///
+/// # Example
+/// ```rust,ignore
+/// REGISTRY.with(|reg| storage::try_save_bytes(reg, "upload", data))?;
+/// ```
+pub fn try_save_bytes<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    bytes: Vec<u8>,
+) -> Result<(), String> {
+    if !can_fit(key.len() + bytes.len()) {
+        return Err("insufficient stable memory".to_string());
+    }
+    save_bytes(registry, key, bytes);
+    Ok(())
+}
+
+/// `save_candid` that fails instead of trapping when stable memory is short
+pub fn try_save_candid<T: CandidType, R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    data: &T,
+) -> Result<(), String> {
+    let bytes = Encode!(data)
+        .map_err(|e| format!("Failed to serialize data for key {}: {:?}", key, e))?;
+    try_save_bytes(registry, key, bytes)
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Compare-and-Set (optimistic concurrency)
+// ═══════════════════════════════════════════════════════════════
+
+/// Set `key` to `new` only if its current value equals `expected`
+///
+/// `expected: None` means the key must be absent. On mismatch nothing is
+/// written and the current value is returned (empty if the key is absent).
+/// Update calls run one at a time, so the check and the write are atomic
+/// within a call; use this to detect changes made across an `await`.
+///
+/// # Example
+/// ```rust,ignore
+/// let before = REGISTRY.with(|reg| storage::load_bytes(reg, "counter"));
+/// let result = do_async_work().await;
+/// REGISTRY.with(|reg| storage::compare_and_set(reg, "counter", before, result))
+///     .map_err(|_| "counter changed concurrently".to_string())?;
+/// ```
+pub fn compare_and_set<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    expected: Option<Vec<u8>>,
+    new: Vec<u8>,
+) -> Result<(), Vec<u8>> {
+    let mut registry = registry.borrow_mut();
+    let key = key.to_string();
+    let current = registry.get(&key);
+
+    if current != expected {
+        return Err(current.unwrap_or_default());
+    }
+
+    registry.insert(key, new);
+    Ok(())
+}
+
+/// Typed `compare_and_set`: compares Candid encodings of `expected` and the stored value
+pub fn save_candid_cas<T: CandidType, R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    expected: Option<&T>,
+    new: &T,
+) -> Result<(), String> {
+    let encode = |data: &T| {
+        Encode!(data).map_err(|e| format!("Failed to serialize data for key {}: {:?}", key, e))
+    };
+
+    let expected = expected.map(encode).transpose()?;
+    let new = encode(new)?;
+
+    compare_and_set(registry, key, expected, new)
+        .map_err(|_| format!("Compare-and-set failed for key {}: value changed", key))
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Secondary Indexes
+// ═══════════════════════════════════════════════════════════════
+
+/// Secondary index stored in the same registry as the records it indexes
+///
+/// Each `(field, value)` pair maps to a sorted list of primary keys, stored as
+/// Candid under `{prefix}{field}:{value}`. Update the index in the same update
+/// call as the primary write so both change together.
+///
+/// # Example
+/// ```rust,ignore
+/// REGISTRY.with(|reg| {
+///     storage::save_candid(reg, &task.id, &task)?;
+///     storage::Index::new(reg).add_to_index("owner", &task.owner.to_text(), &task.id)
+/// })?;
+///
+/// let ids = REGISTRY.with(|reg| storage::Index::new(reg).lookup("owner", &caller.to_text()));
+/// ```
+pub struct Index<'a, R: StorageRegistry> {
+    registry: &'a RefCell<R>,
+    prefix: String,
+}
+
+impl<'a, R: StorageRegistry> Index<'a, R> {
+    /// Default prefix for index entries
+    pub const DEFAULT_PREFIX: &'static str = "idx:";
+
+    pub fn new(registry: &'a RefCell<R>) -> Self {
+        Self::with_prefix(registry, Self::DEFAULT_PREFIX)
+    }
+
+    pub fn with_prefix(registry: &'a RefCell<R>, prefix: impl Into<String>) -> Self {
+        Self {
+            registry,
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Record that `key` has `field == value`
+    pub fn add_to_index(&self, field: &str, value: &str, key: &str) -> Result<(), String> {
+        let mut keys = self.lookup(field, value);
+
+        if let Err(pos) = keys.binary_search_by(|k| k.as_str().cmp(key)) {
+            keys.insert(pos, key.to_string());
+            save_candid(self.registry, &self.index_key(field, value), &keys)?;
+        }
+
+        Ok(())
     }
 
-    impl StorageRegistry for TestRegistry {
-        fn insert(&mut self, key: String, value: Vec<u8>) {
-            self.map.insert(key, value);
+    /// Remove `key` from the `field == value` entry
+    pub fn remove_from_index(&self, field: &str, value: &str, key: &str) -> Result<(), String> {
+        let mut keys = self.lookup(field, value);
+
+        if let Ok(pos) = keys.binary_search_by(|k| k.as_str().cmp(key)) {
+            keys.remove(pos);
+            let index_key = self.index_key(field, value);
+            if keys.is_empty() {
+                delete(self.registry, &index_key);
+            } else {
+                save_candid(self.registry, &index_key, &keys)?;
+            }
         }
 
-        fn get(&self, key: &String) -> Option<Vec<u8>> {
-            self.map.get(key).cloned()
+        Ok(())
+    }
+
+    /// Primary keys with `field == value`, sorted
+    pub fn lookup(&self, field: &str, value: &str) -> Vec<String> {
+        load_candid(self.registry, &self.index_key(field, value)).unwrap_or_default()
+    }
+
+    fn index_key(&self, field: &str, value: &str) -> String {
+        format!("{}{}:{}", self.prefix, field, value)
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  LRU Cache
+// ═══════════════════════════════════════════════════════════════
+
+/// Bounded cache that evicts the least-recently-used key when full
+///
+/// Values are stored under `{prefix}v:{key}` and the recency list (oldest
+/// first) as Candid under `{prefix}order`, all in the wrapped registry. IC
+/// calls run one at a time, so no locking is needed.
+///
+/// Every `get` hit and every `put` rewrites the whole recency list, so each
+/// access costs an extra write proportional to the number of cached keys.
+/// Keep capacities modest (hundreds to low thousands of keys). `get` in a
+/// query call still returns the value, but the recency update is discarded.
+///
+/// # Example
+/// ```rust,ignore
+/// let cache = storage::LruStore::new(reg, 100);
+/// if let Some(evicted) = cache.put("page:/", html.into_bytes())? {
+///     ic_cdk::println!("evicted {}", evicted);
+/// }
+/// let hit = cache.get("page:/");
+/// ```
+pub struct LruStore<'a, R: StorageRegistry> {
+    registry: &'a RefCell<R>,
+    prefix: String,
+    capacity: usize,
+}
+
+impl<'a, R: StorageRegistry> LruStore<'a, R> {
+    /// Default prefix for cache entries
+    pub const DEFAULT_PREFIX: &'static str = "lru:";
+
+    /// Create a cache holding at most `capacity` keys (at least 1)
+    pub fn new(registry: &'a RefCell<R>, capacity: usize) -> Self {
+        Self::with_prefix(registry, Self::DEFAULT_PREFIX, capacity)
+    }
+
+    pub fn with_prefix(
+        registry: &'a RefCell<R>,
+        prefix: impl Into<String>,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            registry,
+            prefix: prefix.into(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Get a cached value, marking it most recently used
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let value = load_bytes(self.registry, &self.value_key(key))?;
+
+        let mut order = self.order();
+        if let Some(pos) = order.iter().position(|k| k == key) {
+            if pos + 1 < order.len() {
+                let key = order.remove(pos);
+                order.push(key);
+                // Recency is best-effort; the value is returned either way
+                let _ = self.save_order(order);
+            }
+        }
+
+        Some(value)
+    }
+
+    /// Insert or replace a value, returning the key evicted to make room
+    pub fn put(&self, key: &str, value: Vec<u8>) -> Result<Option<String>, String> {
+        let mut order = self.order();
+        order.retain(|k| k != key);
+
+        let evicted = if order.len() >= self.capacity {
+            let oldest = order.remove(0);
+            delete(self.registry, &self.value_key(&oldest));
+            Some(oldest)
+        } else {
+            None
+        };
+
+        order.push(key.to_string());
+        save_bytes(self.registry, &self.value_key(key), value);
+        self.save_order(order)?;
+
+        Ok(evicted)
+    }
+
+    /// Remove a cached value
+    pub fn remove(&self, key: &str) -> Result<bool, String> {
+        let mut order = self.order();
+        order.retain(|k| k != key);
+        self.save_order(order)?;
+        Ok(delete(self.registry, &self.value_key(key)))
+    }
+
+    /// Number of cached keys
+    pub fn len(&self) -> usize {
+        self.order().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Cached keys, least recently used first
+    pub fn order(&self) -> Vec<String> {
+        load_candid(self.registry, &self.order_key()).unwrap_or_default()
+    }
+
+    fn save_order(&self, order: Vec<String>) -> Result<(), String> {
+        save_candid(self.registry, &self.order_key(), &order)
+    }
+
+    fn value_key(&self, key: &str) -> String {
+        format!("{}v:{}", self.prefix, key)
+    }
+
+    fn order_key(&self) -> String {
+        format!("{}order", self.prefix)
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Object Store (bytes plus metadata)
+// ═══════════════════════════════════════════════════════════════
+
+/// Metadata kept next to each object in an `ObjectStore`
+#[derive(CandidType, candid::Deserialize, Clone, Debug, PartialEq)]
+pub struct ObjectMetadata {
+    pub content_type: String,
+    /// Size of the object in bytes
+    pub size: u64,
+    /// Time of the last write (nanoseconds)
+    pub updated_at: u64,
+}
+
+/// Content type recorded for objects written through `StorageRegistry`
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Stable object store keeping bytes and `ObjectMetadata` in two maps
+///
+/// Each map needs its own memory, e.g. two `MemoryId`s of a `MemoryManager`.
+/// The store implements `StorageRegistry` over the bytes, so the free
+/// functions (`save_bytes`, `delete_prefix`, ...) work on it too; writes made
+/// that way keep the metadata in sync, with a generic content type for new
+/// objects.
+///
+/// # Example
+/// ```rust,ignore
+/// thread_local! {
+///     static OBJECTS: RefCell<ObjectStore<Memory>> = RefCell::new(ObjectStore::init(
+///         MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))),
+///         MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))),
+///     ));
+/// }
+///
+/// OBJECTS.with(|o| o.borrow_mut().put("logo.png", bytes, "image/png"));
+/// let (meta, bytes) = OBJECTS.with(|o| o.borrow().get("logo.png")).unwrap();
+/// ```
+pub struct ObjectStore<M: ic_stable_structures::Memory> {
+    data: StableBTreeMap<String, Vec<u8>, M>,
+    meta: StableBTreeMap<String, Vec<u8>, M>,
+}
+
+impl<M: ic_stable_structures::Memory> ObjectStore<M> {
+    /// Open (or create) a store over separate data and metadata memories
+    pub fn init(data_memory: M, meta_memory: M) -> Self {
+        Self {
+            data: StableBTreeMap::init(data_memory),
+            meta: StableBTreeMap::init(meta_memory),
         }
+    }
+
+    /// Store an object, replacing any existing one, and return its metadata
+    pub fn put(&mut self, key: &str, bytes: Vec<u8>, content_type: &str) -> ObjectMetadata {
+        let metadata = ObjectMetadata {
+            content_type: content_type.to_string(),
+            size: bytes.len() as u64,
+            updated_at: crate::env::time(),
+        };
+        self.data.insert(key.to_string(), bytes);
+        self.write_meta(key, &metadata);
+        metadata
+    }
+
+    /// Get an object and its metadata
+    pub fn get(&self, key: &str) -> Option<(ObjectMetadata, Vec<u8>)> {
+        let bytes = self.data.get(&key.to_string())?;
+        let metadata = self.meta(key).unwrap_or_else(|| ObjectMetadata {
+            content_type: DEFAULT_CONTENT_TYPE.to_string(),
+            size: bytes.len() as u64,
+            updated_at: 0,
+        });
+        Some((metadata, bytes))
+    }
+
+    /// Get an object's metadata without loading its bytes
+    pub fn meta(&self, key: &str) -> Option<ObjectMetadata> {
+        let bytes = self.meta.get(&key.to_string())?;
+        Decode!(&bytes, ObjectMetadata).ok()
+    }
+
+    /// All keys with their metadata, in key order (object bytes are not loaded)
+    pub fn list(&self) -> Vec<(String, ObjectMetadata)> {
+        self.meta
+            .keys()
+            .zip(self.meta.values())
+            .filter_map(|(key, bytes)| Decode!(&bytes, ObjectMetadata).ok().map(|m| (key, m)))
+            .collect()
+    }
+
+    /// Delete an object and its metadata
+    pub fn delete(&mut self, key: &str) -> bool {
+        let key = key.to_string();
+        self.meta.remove(&key);
+        self.data.remove(&key).is_some()
+    }
+
+    fn write_meta(&mut self, key: &str, metadata: &ObjectMetadata) {
+        let bytes = Encode!(metadata).expect("Failed to encode object metadata");
+        self.meta.insert(key.to_string(), bytes);
+    }
+}
+
+impl<M: ic_stable_structures::Memory> StorageRegistry for ObjectStore<M> {
+    fn insert(&mut self, key: String, value: Vec<u8>) {
+        let content_type = self
+            .meta(&key)
+            .map(|m| m.content_type)
+            .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string());
+        self.put(&key, value, &content_type);
+    }
+
+    fn get(&self, key: &String) -> Option<Vec<u8>> {
+        self.data.get(key)
+    }
+
+    fn remove(&mut self, key: &String) -> Option<Vec<u8>> {
+        self.meta.remove(key);
+        self.data.remove(key)
+    }
+
+    fn visit_entries(&self, f: &mut dyn FnMut(&String, &[u8])) {
+        self.data.visit_entries(f);
+    }
+
+    fn len(&self) -> usize {
+        StorageRegistry::len(&self.data)
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.data.total_bytes()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + '_> {
+        StorageRegistry::iter(&self.data)
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.data.keys_with_prefix(prefix)
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Chunked Storage (objects larger than the value size limit)
+// ═══════════════════════════════════════════════════════════════
+
+/// Metadata stored under `key#meta` for chunked objects
+#[derive(CandidType, candid::Deserialize, Clone, Debug, PartialEq)]
+pub struct ChunkedMeta {
+    pub chunk_count: u64,
+    pub chunk_size: u64,
+    pub total_size: u64,
+}
+
+/// Store data as `key#0`, `key#1`, … plus a `key#meta` entry
+///
+/// Use this for objects that exceed the registry's per-value size limit.
+/// Any previous chunked object under `key` is removed first.
+///
+/// # Example
+/// ```rust,ignore
+/// let data = large_objects::get_buffer_data();
+/// REGISTRY.with(|reg| storage::store_chunked(reg, "model.gguf", data, 1024 * 1024))?;
+/// ```
+pub fn store_chunked<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    data: Vec<u8>,
+    chunk_size: usize,
+) -> Result<(), String> {
+    if chunk_size == 0 {
+        return Err("chunk_size must be greater than zero".to_string());
+    }
+
+    delete_chunked(registry, key);
 
-        fn remove(&mut self, key: &String) -> Option<Vec<u8>> {
-            self.map.remove(key)
+    let meta = ChunkedMeta {
+        chunk_count: data.len().div_ceil(chunk_size) as u64,
+        chunk_size: chunk_size as u64,
+        total_size: data.len() as u64,
+    };
+
+    {
+        let mut registry = registry.borrow_mut();
+        for (index, chunk) in data.chunks(chunk_size).enumerate() {
+            registry.insert(chunk_key(key, index), chunk.to_vec());
         }
     }
 
+    save_candid(registry, &meta_key(key), &meta)
+}
+
+/// Reassemble a chunked object stored with `store_chunked`
+///
+/// Returns None if the metadata or any chunk is missing.
+pub fn load_chunked<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+) -> Option<Vec<u8>> {
+    let meta: ChunkedMeta = load_candid(registry, &meta_key(key))?;
+    let registry = registry.borrow();
+
+    let mut data = Vec::with_capacity(meta.total_size as usize);
+    for index in 0..meta.chunk_count as usize {
+        data.extend(registry.get(&chunk_key(key, index))?);
+    }
+
+    (data.len() as u64 == meta.total_size).then_some(data)
+}
+
+/// Number of chunks in a chunked object
+pub fn chunk_count<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+) -> Option<usize> {
+    chunked_meta(registry, key).map(|meta| meta.chunk_count as usize)
+}
+
+/// Metadata (chunk count, chunk size, total size) of a chunked object
+pub fn chunked_meta<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+) -> Option<ChunkedMeta> {
+    load_candid(registry, &meta_key(key))
+}
+
+/// Read a single chunk of a chunked object
+///
+/// Every chunk except the last holds exactly `chunk_size` bytes, so byte
+/// offset `o` lives in chunk `o / chunk_size` at position `o % chunk_size`.
+/// Reading chunk by chunk keeps peak memory at one chunk, not the whole object.
+pub fn read_chunk<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    index: usize,
+) -> Option<Vec<u8>> {
+    registry.borrow().get(&chunk_key(key, index))
+}
+
+/// Read bytes `start..=end` of a chunked object, loading only the chunks needed
+///
+/// Returns None if the range is out of bounds or a chunk is missing.
+///
+/// # Example
+/// ```rust,ignore
+/// let meta = REGISTRY.with(|reg| storage::chunked_meta(reg, "video"))?;
+/// let (start, end) = http::parse_range(&req.headers, meta.total_size)?;
+/// let body = REGISTRY.with(|reg| storage::read_chunked_range(reg, "video", start, end))?;
+/// ```
+pub fn read_chunked_range<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+    start: u64,
+    end: u64,
+) -> Option<Vec<u8>> {
+    let meta = chunked_meta(registry, key)?;
+    if start > end || end >= meta.total_size {
+        return None;
+    }
+
+    let first_chunk = start / meta.chunk_size;
+    let last_chunk = end / meta.chunk_size;
+    let mut data = Vec::with_capacity((end - start + 1) as usize);
+
+    for index in first_chunk..=last_chunk {
+        let chunk = read_chunk(registry, key, index as usize)?;
+        let chunk_start = index * meta.chunk_size;
+
+        let from = start.saturating_sub(chunk_start) as usize;
+        let to = ((end - chunk_start + 1) as usize).min(chunk.len());
+        data.extend_from_slice(chunk.get(from..to)?);
+    }
+
+    Some(data)
+}
+
+/// Delete all parts of a chunked object
+///
+/// Returns true if the object existed.
+pub fn delete_chunked<R: StorageRegistry>(
+    registry: &RefCell<R>,
+    key: &str,
+) -> bool {
+    let Some(meta) = load_candid::<ChunkedMeta, R>(registry, &meta_key(key)) else {
+        return false;
+    };
+
+    let mut registry = registry.borrow_mut();
+    for index in 0..meta.chunk_count as usize {
+        registry.remove(&chunk_key(key, index));
+    }
+    registry.remove(&meta_key(key));
+
+    true
+}
+
+fn chunk_key(key: &str, index: usize) -> String {
+    format!("{}#{}", key, index)
+}
+
+fn meta_key(key: &str) -> String {
+    format!("{}#meta", key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRegistry;
+
+    #[test]
+    fn test_available_after() {
+        assert_eq!(available_after(0), MAX_STABLE_MEMORY_BYTES);
+
+        set_stable_memory_limit(10 * WASM_PAGE_SIZE);
+        assert_eq!(available_after(4 * WASM_PAGE_SIZE), 6 * WASM_PAGE_SIZE);
+        assert_eq!(available_after(20 * WASM_PAGE_SIZE), 0);
+    }
+
+    #[test]
+    fn test_export_all() {
+        let registry = RefCell::new(TestRegistry::default());
+        save_bytes(&registry, "a", vec![1]);
+        save_bytes(&registry, "b", vec![2, 3]);
+
+        let mut entries = export_all(&registry);
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![("a".to_string(), vec![1]), ("b".to_string(), vec![2, 3])]
+        );
+    }
+
+    #[test]
+    fn test_delete_prefix() {
+        // TestRegistry only has the required methods, so this exercises the
+        // default keys_with_prefix -> iter -> visit_entries path
+        let registry = RefCell::new(TestRegistry::default());
+        save_bytes(&registry, "session:1", vec![1]);
+        save_bytes(&registry, "session:2", vec![2]);
+        save_bytes(&registry, "sessions", vec![3]);
+        save_bytes(&registry, "user:session:1", vec![4]);
+
+        assert_eq!(delete_prefix(&registry, "session:"), 2);
+        assert!(!exists(&registry, "session:1"));
+        assert!(!exists(&registry, "session:2"));
+        assert!(exists(&registry, "sessions"));
+        assert!(exists(&registry, "user:session:1"));
+        assert_eq!(registry.borrow().len(), 2);
+
+        assert_eq!(delete_prefix(&registry, "session:"), 0);
+    }
+
     #[test]
     fn test_save_load_bytes() {
-        let registry = RefCell::new(TestRegistry {
-            map: HashMap::new(),
-        });
+        let registry = RefCell::new(TestRegistry::default());
 
         save_bytes(&registry, "test", vec![1, 2, 3]);
         let loaded = load_bytes(&registry, "test");
@@ -186,12 +1038,193 @@ mod tests {
 
     #[test]
     fn test_exists() {
-        let registry = RefCell::new(TestRegistry {
-            map: HashMap::new(),
-        });
+        let registry = RefCell::new(TestRegistry::default());
 
         assert!(!exists(&registry, "test"));
         save_bytes(&registry, "test", vec![1, 2, 3]);
         assert!(exists(&registry, "test"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_chunked_storage() {
+        let registry = RefCell::new(TestRegistry::default());
+        let data: Vec<u8> = (0..10).collect();
+
+        store_chunked(&registry, "big", data.clone(), 4).unwrap();
+        assert_eq!(load_bytes(&registry, "big#2"), Some(vec![8, 9]));
+        assert_eq!(load_chunked(&registry, "big"), Some(data));
+        assert!(store_chunked(&registry, "big", vec![1], 0).is_err());
+
+        // Overwriting with fewer chunks drops the stale ones
+        store_chunked(&registry, "big", vec![1, 2], 4).unwrap();
+        assert!(!exists(&registry, "big#1"));
+        assert_eq!(load_chunked(&registry, "big"), Some(vec![1, 2]));
+
+        assert!(delete_chunked(&registry, "big"));
+        assert!(registry.borrow().is_empty());
+        assert!(!delete_chunked(&registry, "big"));
+        assert_eq!(load_chunked(&registry, "big"), None);
+    }
+
+    #[test]
+    fn test_chunked_reads() {
+        let registry = RefCell::new(TestRegistry::default());
+        let data: Vec<u8> = (0..10).collect();
+        store_chunked(&registry, "big", data.clone(), 4).unwrap();
+
+        assert_eq!(chunk_count(&registry, "big"), Some(3));
+        assert_eq!(read_chunk(&registry, "big", 1), Some(vec![4, 5, 6, 7]));
+        assert_eq!(read_chunk(&registry, "big", 3), None);
+        assert_eq!(chunk_count(&registry, "missing"), None);
+
+        assert_eq!(read_chunked_range(&registry, "big", 3, 8), Some(data[3..=8].to_vec()));
+        assert_eq!(read_chunked_range(&registry, "big", 4, 7), Some(vec![4, 5, 6, 7]));
+        assert_eq!(read_chunked_range(&registry, "big", 9, 9), Some(vec![9]));
+        assert_eq!(read_chunked_range(&registry, "big", 0, 10), None);
+        assert_eq!(read_chunked_range(&registry, "big", 5, 4), None);
+    }
+
+    #[test]
+    fn test_compare_and_set() {
+        let registry = RefCell::new(TestRegistry::default());
+
+        assert!(compare_and_set(&registry, "v", None, vec![1]).is_ok());
+        assert_eq!(compare_and_set(&registry, "v", None, vec![2]), Err(vec![1]));
+        assert_eq!(compare_and_set(&registry, "v", Some(vec![9]), vec![2]), Err(vec![1]));
+        assert!(compare_and_set(&registry, "v", Some(vec![1]), vec![2]).is_ok());
+        assert_eq!(load_bytes(&registry, "v"), Some(vec![2]));
+
+        assert!(save_candid_cas(&registry, "n", None, &1u64).is_ok());
+        assert!(save_candid_cas(&registry, "n", Some(&5u64), &2u64).is_err());
+        assert!(save_candid_cas(&registry, "n", Some(&1u64), &2u64).is_ok());
+        assert_eq!(load_candid::<u64, _>(&registry, "n"), Some(2));
+    }
+
+    #[test]
+    fn test_secondary_index() {
+        let registry = RefCell::new(TestRegistry::default());
+        let index = Index::new(&registry);
+
+        index.add_to_index("owner", "alice", "task-2").unwrap();
+        index.add_to_index("owner", "alice", "task-1").unwrap();
+        index.add_to_index("owner", "alice", "task-1").unwrap();
+        index.add_to_index("owner", "bob", "task-3").unwrap();
+
+        assert_eq!(index.lookup("owner", "alice"), vec!["task-1", "task-2"]);
+        assert_eq!(index.lookup("owner", "carol"), Vec::<String>::new());
+        assert!(exists(&registry, "idx:owner:bob"));
+
+        index.remove_from_index("owner", "alice", "task-1").unwrap();
+        assert_eq!(index.lookup("owner", "alice"), vec!["task-2"]);
+
+        index.remove_from_index("owner", "bob", "task-3").unwrap();
+        assert!(!exists(&registry, "idx:owner:bob"));
+    }
+
+    #[test]
+    fn test_lru_store() {
+        let registry = RefCell::new(TestRegistry::default());
+        let cache = LruStore::new(&registry, 2);
+
+        assert_eq!(cache.put("a", vec![1]), Ok(None));
+        assert_eq!(cache.put("b", vec![2]), Ok(None));
+
+        // Reading "a" makes "b" the eviction candidate
+        assert_eq!(cache.get("a"), Some(vec![1]));
+        assert_eq!(cache.put("c", vec![3]), Ok(Some("b".to_string())));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.order(), vec!["a", "c"]);
+
+        // Replacing an existing key doesn't evict
+        assert_eq!(cache.put("a", vec![9]), Ok(None));
+        assert_eq!(cache.order(), vec!["c", "a"]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.capacity(), 2);
+
+        assert_eq!(cache.remove("c"), Ok(true));
+        assert_eq!(cache.len(), 1);
+        assert!(!exists(&registry, "lru:v:c"));
+    }
+
+    #[test]
+    fn test_save_load_json() {
+        #[derive(Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Config {
+            name: String,
+            retries: u32,
+        }
+
+        let registry = RefCell::new(TestRegistry::default());
+        let config = Config {
+            name: "sync".to_string(),
+            retries: 3,
+        };
+
+        save_json(&registry, "config", &config).unwrap();
+        assert_eq!(
+            load_bytes(&registry, "config"),
+            Some(br#"{"name":"sync","retries":3}"#.to_vec())
+        );
+        assert_eq!(load_json::<Config, _>(&registry, "config"), Some(config));
+
+        save_bytes(&registry, "bad", vec![0xff]);
+        assert_eq!(load_json::<Config, _>(&registry, "bad"), None);
+    }
+
+    #[test]
+    fn test_stats() {
+        let registry = RefCell::new(TestRegistry::default());
+        assert_eq!(
+            stats(&registry),
+            StorageStats {
+                entries: 0,
+                total_bytes: 0,
+            }
+        );
+
+        save_bytes(&registry, "a", vec![1, 2, 3]);
+        save_bytes(&registry, "b", vec![4]);
+        assert_eq!(
+            stats(&registry),
+            StorageStats {
+                entries: 2,
+                total_bytes: 4,
+            }
+        );
+        assert!(!registry.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_object_store() {
+        use ic_stable_structures::VectorMemory;
+
+        crate::testing::set_time(42);
+        let store = RefCell::new(ObjectStore::init(
+            VectorMemory::default(),
+            VectorMemory::default(),
+        ));
+
+        let meta = store.borrow_mut().put("logo.png", vec![1, 2, 3], "image/png");
+        assert_eq!(meta.size, 3);
+        assert_eq!(meta.updated_at, 42);
+        assert_eq!(store.borrow().get("logo.png"), Some((meta.clone(), vec![1, 2, 3])));
+
+        // Registry writes keep the content type and update the size
+        save_bytes(&store, "logo.png", vec![9]);
+        assert_eq!(store.borrow().meta("logo.png").unwrap().content_type, "image/png");
+        assert_eq!(store.borrow().meta("logo.png").unwrap().size, 1);
+
+        save_bytes(&store, "raw", vec![0]);
+        let list = store.borrow().list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[1].0, "raw");
+        assert_eq!(list[1].1.content_type, DEFAULT_CONTENT_TYPE);
+
+        assert_eq!(delete_prefix(&store, "logo"), 1);
+        assert_eq!(store.borrow().meta("logo.png"), None);
+        assert!(!store.borrow_mut().delete("logo.png"));
+        assert!(store.borrow_mut().delete("raw"));
+        assert!(store.borrow().list().is_empty());
+        crate::testing::reset();
+    }
+}
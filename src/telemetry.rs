@@ -2,11 +2,13 @@
 
 #![cfg(feature = "telemetry")]
 
-use candid::Principal;
+use candid::{CandidType, Principal};
 use canistergeek_ic_rust::api_type::*;
 use ic_cdk;
-use std::cell::RefCell;
-use std::collections::HashSet;
+use serde::Deserialize;
+use crate::http::{HttpError, HttpMethod, HttpRequest, HttpResponse};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
 // ═══════════════════════════════════════════════════════════════
 //  Error Types
@@ -26,6 +28,57 @@ pub enum TelemetryError {
 
 pub type TelemetryResult<T> = Result<T, TelemetryError>;
 
+// ═══════════════════════════════════════════════════════════════
+//  Log Levels
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl LogLevel {
+    /// Lowercase name used in structured (JSON) logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warning => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+
+    /// Bracketed prefix used in plain-text logs
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "[DEBUG]",
+            LogLevel::Info => "[INFO]",
+            LogLevel::Warning => "[WARN]",
+            LogLevel::Error => "[ERROR]",
+        }
+    }
+
+    /// Recover the level of a message written by this module
+    ///
+    /// Recognises both the bracketed prefixes and structured JSON lines.
+    /// Messages without either (e.g. from `log_message`) are treated as `Info`.
+    pub fn from_message(message: &str) -> LogLevel {
+        const LEVELS: [LogLevel; 4] = [LogLevel::Debug, LogLevel::Info, LogLevel::Warning, LogLevel::Error];
+
+        for level in LEVELS {
+            if message.starts_with(level.prefix())
+                || message.starts_with(&format!(r#"{{"level":"{}""#, level.as_str()))
+            {
+                return level;
+            }
+        }
+        LogLevel::Info
+    }
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Monitoring Principals Storage
 // ═══════════════════════════════════════════════════════════════
@@ -55,8 +108,11 @@ impl MonitoringAuth {
         self.principals.borrow().contains(principal)
     }
 
+    /// Check if a principal is a controller of this canister
+    ///
+    /// Doesn't depend on the allowlist; prefer the free `caller_is_controller`.
     pub fn is_controller(&self, principal: &Principal) -> bool {
-        ic_cdk::api::is_controller(principal)
+        crate::env::is_controller(principal)
     }
 
     pub fn add_monitoring_principal(&self, principal: Principal) -> TelemetryResult<()> {
@@ -73,10 +129,9 @@ impl MonitoringAuth {
         self.principals.borrow().iter().cloned().collect()
     }
 
+    /// Allow controllers and allowlisted monitoring principals
     pub fn check_access(&self) -> TelemetryResult<()> {
-        let caller = ic_cdk::api::msg_caller();
-
-        if self.is_controller(&caller) || self.is_monitoring_authorized(&caller) {
+        if caller_is_controller() || self.is_monitoring_authorized(&crate::env::caller()) {
             Ok(())
         } else {
             Err(TelemetryError::Unauthorized)
@@ -96,6 +151,47 @@ impl Default for MonitoringAuth {
 
 thread_local! {
     static AUTH: RefCell<Option<MonitoringAuth>> = RefCell::new(None);
+
+    /// When set, the convenience log wrappers emit single-line JSON
+    static JSON_LOGS: Cell<bool> = Cell::new(false);
+
+    /// Messages below this level are dropped
+    static LOG_LEVEL: Cell<LogLevel> = Cell::new(LogLevel::Info);
+
+    /// Instruction statistics per span label
+    static SPANS: RefCell<HashMap<String, SpanStats>> = RefCell::new(HashMap::new());
+
+    /// Lifetime (total instructions, call count) per method timer name
+    static METHOD_STATS: RefCell<BTreeMap<String, (u64, u64)>> = RefCell::new(BTreeMap::new());
+
+    /// Application-defined counters and gauges
+    static COUNTERS: RefCell<BTreeMap<String, u64>> = RefCell::new(BTreeMap::new());
+    static GAUGES: RefCell<BTreeMap<String, f64>> = RefCell::new(BTreeMap::new());
+
+    /// Rolling window of recent outcomes (true = success)
+    static OUTCOMES: RefCell<VecDeque<bool>> = RefCell::new(VecDeque::new());
+    static ERROR_RATE_ALERT: RefCell<Option<ErrorRateAlert>> = RefCell::new(None);
+
+    /// When set, `log_error` also records a failed outcome
+    static RECORD_LOGGED_ERRORS: Cell<bool> = Cell::new(false);
+
+    /// Log entries at or before this time are hidden by `get_canister_log`
+    static LOGS_CLEARED_AT: Cell<Option<u64>> = Cell::new(None);
+
+    /// Balance required by `min_cycles_guard`
+    static MIN_CYCLES: Cell<u128> = Cell::new(0);
+
+    /// `collect_metrics` warns when the balance is below this
+    static LOW_CYCLES_THRESHOLD: Cell<Option<u128>> = Cell::new(None);
+}
+
+/// Number of recent outcomes used to compute the error rate
+pub const ERROR_RATE_WINDOW: usize = 100;
+
+struct ErrorRateAlert {
+    threshold: f32,
+    on_trip: fn(f32),
+    tripped: bool,
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -117,10 +213,26 @@ pub fn init_with_principals(principals: Vec<Principal>) {
 }
 
 /// Initialize from saved state (for post-upgrade)
+///
+/// App metrics and the log level are left untouched; use `init_from_saved_v2`
+/// to restore them as well.
 pub fn init_from_saved(
     monitor_data: Option<canistergeek_ic_rust::monitor::PostUpgradeStableData>,
     logger_data: Option<canistergeek_ic_rust::logger::PostUpgradeStableData>,
     principals: Option<Vec<Principal>>,
+) {
+    init_from_saved_v2(monitor_data, logger_data, principals, None, None);
+}
+
+/// Initialize from saved state, also restoring app metrics and the log level
+///
+/// `None` leaves the corresponding state at its current value.
+pub fn init_from_saved_v2(
+    monitor_data: Option<canistergeek_ic_rust::monitor::PostUpgradeStableData>,
+    logger_data: Option<canistergeek_ic_rust::logger::PostUpgradeStableData>,
+    principals: Option<Vec<Principal>>,
+    app_metrics: Option<AppMetricsSnapshot>,
+    log_level: Option<LogLevel>,
 ) {
     // Initialize monitor
     if let Some(data) = monitor_data {
@@ -142,6 +254,14 @@ pub fn init_from_saved(
             }
         );
     });
+
+    if let Some(snapshot) = app_metrics {
+        restore_app_metrics(snapshot);
+    }
+
+    if let Some(level) = log_level {
+        set_log_level(level);
+    }
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -173,6 +293,23 @@ pub fn is_monitoring_authorized() -> Result<(), String> {
     })
 }
 
+/// Check if the current caller is a controller of this canister
+pub fn caller_is_controller() -> bool {
+    crate::env::is_controller(&crate::env::caller())
+}
+
+/// Guard function for controller-only endpoints (e.g. clearing logs)
+///
+/// Unlike `is_monitoring_authorized`, allowlisted monitoring principals are
+/// rejected.
+pub fn require_controller() -> Result<(), String> {
+    if caller_is_controller() {
+        Ok(())
+    } else {
+        Err("Controller access required".to_string())
+    }
+}
+
 /// Add a principal to the monitoring allowlist
 pub fn add_monitoring_principal(principal: Principal) -> Result<(), String> {
     with_auth(|auth| {
@@ -210,8 +347,12 @@ pub fn update_information() {
 }
 
 /// Alternative: use the shortcut function
+///
+/// Also logs a warning if the cycle balance is below the threshold set with
+/// `set_low_cycles_threshold`.
 pub fn collect_metrics() {
     canistergeek_ic_rust::monitor::collect_metrics();
+    check_low_cycles();
 }
 
 /// Get canister information
@@ -219,42 +360,544 @@ pub fn get_information(request: GetInformationRequest) -> GetInformationResponse
     canistergeek_ic_rust::get_information(request)
 }
 
+// ═══════════════════════════════════════════════════════════════
+//  Public API - Cycles
+// ═══════════════════════════════════════════════════════════════
+
+/// Current cycle balance of this canister
+pub fn cycles_balance() -> u128 {
+    crate::env::cycle_balance()
+}
+
+/// Fail unless the cycle balance is at least `min`
+///
+/// Call at the start of expensive methods; for a named guard, see
+/// `min_cycles_guard`.
+pub fn require_min_cycles(min: u128) -> Result<(), String> {
+    let balance = cycles_balance();
+    if balance < min {
+        return Err(format!(
+            "Insufficient cycles: balance {} is below required {}",
+            balance, min
+        ));
+    }
+    Ok(())
+}
+
+/// Set the balance required by `min_cycles_guard` (default: 0)
+pub fn set_min_cycles(min: u128) {
+    MIN_CYCLES.with(|m| m.set(min));
+}
+
+/// Guard rejecting calls while the balance is below `set_min_cycles`
+///
+/// # Example
+/// ```rust,ignore
+/// telemetry::set_min_cycles(500_000_000_000);
+///
+/// #[ic_cdk::update(guard = "telemetry::min_cycles_guard")]
+/// fn generate(request: InferenceRequest) -> InferenceResponse { ... }
+/// ```
+pub fn min_cycles_guard() -> Result<(), String> {
+    require_min_cycles(MIN_CYCLES.with(|m| m.get()))
+}
+
+/// Warn from `collect_metrics` when the balance drops below `threshold`
+pub fn set_low_cycles_threshold(threshold: Option<u128>) {
+    LOW_CYCLES_THRESHOLD.with(|t| t.set(threshold));
+}
+
+/// Log a warning if the balance is below the low-cycles threshold
+///
+/// Returns true when the balance is low.
+pub fn check_low_cycles() -> bool {
+    let Some(threshold) = LOW_CYCLES_THRESHOLD.with(|t| t.get()) else {
+        return false;
+    };
+
+    let balance = cycles_balance();
+    if balance >= threshold {
+        return false;
+    }
+    log_warning(format!(
+        "Cycle balance {} is below threshold {}, top up soon",
+        balance, threshold
+    ));
+    true
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Public API - Logging
 // ═══════════════════════════════════════════════════════════════
 
 /// Log a message
 pub fn log_message(message: impl Into<String>) {
-    canistergeek_ic_rust::logger::log_message(message.into());
+    crate::env::log(message.into());
+}
+
+/// Log a single-line JSON object, e.g. `{"level":"info","msg":"...","k":"v"}`
+///
+/// Fields are written after `level` and `msg`, in the order given.
+pub fn log_structured(level: LogLevel, message: &str, fields: &[(&str, &str)]) {
+    if level < current_log_level() {
+        return;
+    }
+    crate::env::log(format_structured(level, message, fields));
+}
+
+/// Set the minimum level written by the leveled log functions
+///
+/// Defaults to `LogLevel::Info`, so debug messages are suppressed unless enabled.
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.with(|l| l.set(level));
+}
+
+/// Get the current minimum log level
+pub fn current_log_level() -> LogLevel {
+    LOG_LEVEL.with(|l| l.get())
+}
+
+/// Enable or disable JSON output for the convenience wrappers
+pub fn set_json_logs(enabled: bool) {
+    JSON_LOGS.with(|j| j.set(enabled));
+}
+
+/// Whether the convenience wrappers currently emit JSON
+pub fn json_logs_enabled() -> bool {
+    JSON_LOGS.with(|j| j.get())
 }
 
 /// Log an info message (convenience wrapper)
 pub fn log_info(message: impl Into<String>) {
-    let msg = format!("[INFO] {}", message.into());
-    canistergeek_ic_rust::logger::log_message(msg);
+    log_at(LogLevel::Info, message.into());
 }
 
 /// Log a warning message (convenience wrapper)
 pub fn log_warning(message: impl Into<String>) {
-    let msg = format!("[WARN] {}", message.into());
-    canistergeek_ic_rust::logger::log_message(msg);
+    log_at(LogLevel::Warning, message.into());
 }
 
 /// Log an error message (convenience wrapper)
 pub fn log_error(message: impl Into<String>) {
-    let msg = format!("[ERROR] {}", message.into());
-    canistergeek_ic_rust::logger::log_message(msg);
+    if RECORD_LOGGED_ERRORS.with(|r| r.get()) {
+        record_outcome(false);
+    }
+    log_at(LogLevel::Error, message.into());
 }
 
 /// Log a debug message (convenience wrapper)
 pub fn log_debug(message: impl Into<String>) {
-    let msg = format!("[DEBUG] {}", message.into());
-    canistergeek_ic_rust::logger::log_message(msg);
+    log_at(LogLevel::Debug, message.into());
+}
+
+fn log_at(level: LogLevel, message: String) {
+    if level < current_log_level() {
+        return;
+    }
+
+    if json_logs_enabled() {
+        log_structured(level, &message, &[]);
+    } else {
+        crate::env::log(format!("{} {}", level.prefix(), message));
+    }
 }
 
 /// Get canister log
+///
+/// Entries hidden by `clear_logs` are dropped from `messages` responses.
 pub fn get_canister_log(request: CanisterLogRequest) -> Option<CanisterLogResponse<'static>> {
-    canistergeek_ic_rust::logger::get_canister_log(Some(request))
+    let mut response = canistergeek_ic_rust::logger::get_canister_log(Some(request))?;
+
+    if let Some(cleared_at) = LOGS_CLEARED_AT.with(|c| c.get()) {
+        retain_log_entries(&mut response, |entry| entry.timeNanos > cleared_at);
+    }
+
+    Some(response)
+}
+
+/// Clear the canister log
+///
+/// Canistergeek has no API to delete messages, so this hides every entry
+/// logged up to now from `get_canister_log` (its ring buffer still evicts old
+/// entries as usual). The clear point is not persisted across upgrades.
+/// Exposed to controllers as the `clear_canister_log` endpoint.
+pub fn clear_logs() {
+    LOGS_CLEARED_AT.with(|c| c.set(Some(crate::env::time())));
+}
+
+/// Number of log entries visible through `get_canister_log`
+pub fn log_count() -> usize {
+    let request = CanisterLogRequest::getMessages(GetLogMessagesParameters {
+        count: u32::MAX,
+        filter: None,
+        fromTimeNanos: None,
+    });
+
+    match get_canister_log(request) {
+        Some(CanisterLogResponse::messages(messages)) => messages.data.len(),
+        _ => 0,
+    }
+}
+
+fn retain_log_entries(
+    response: &mut CanisterLogResponse<'_>,
+    keep: impl Fn(&LogMessageData) -> bool,
+) {
+    if let CanisterLogResponse::messages(messages) = response {
+        messages.data.retain(|entry| keep(entry));
+    }
+}
+
+/// Get canister log entries at or above `min_level`
+///
+/// Best-effort text filter: levels are recovered from the `[INFO]`/`[ERROR]`
+/// prefixes or JSON `level` field written by this module (see
+/// `LogLevel::from_message`), so messages logged any other way count as `Info`.
+pub fn get_canister_log_filtered(
+    request: CanisterLogRequest,
+    min_level: LogLevel,
+) -> Option<CanisterLogResponse<'static>> {
+    let mut response = get_canister_log(request)?;
+    retain_log_entries(&mut response, |entry| {
+        LogLevel::from_message(&entry.message) >= min_level
+    });
+    Some(response)
+}
+
+fn format_structured(level: LogLevel, message: &str, fields: &[(&str, &str)]) -> String {
+    let json = |s: &str| serde_json::Value::from(s).to_string();
+
+    let mut line = format!(r#"{{"level":{},"msg":{}"#, json(level.as_str()), json(message));
+    for (key, value) in fields {
+        line.push_str(&format!(",{}:{}", json(key), json(value)));
+    }
+    line.push('}');
+    line
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Public API - App Metrics
+// ═══════════════════════════════════════════════════════════════
+
+/// Increment an application counter
+pub fn increment_counter(name: &str, by: u64) {
+    COUNTERS.with(|c| *c.borrow_mut().entry(name.to_string()).or_insert(0) += by);
+}
+
+/// Set an application gauge
+pub fn set_gauge(name: &str, value: f64) {
+    GAUGES.with(|g| {
+        g.borrow_mut().insert(name.to_string(), value);
+    });
+}
+
+/// Get the current value of a counter
+pub fn counter_value(name: &str) -> Option<u64> {
+    COUNTERS.with(|c| c.borrow().get(name).copied())
+}
+
+/// Get the current value of a gauge
+pub fn gauge_value(name: &str) -> Option<f64> {
+    GAUGES.with(|g| g.borrow().get(name).copied())
+}
+
+/// Saved app counters and gauges (for upgrade)
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AppMetricsSnapshot {
+    pub counters: Vec<(String, u64)>,
+    pub gauges: Vec<(String, f64)>,
+}
+
+/// Snapshot the current app counters and gauges
+pub fn app_metrics_snapshot() -> AppMetricsSnapshot {
+    AppMetricsSnapshot {
+        counters: COUNTERS.with(|c| c.borrow().iter().map(|(k, v)| (k.clone(), *v)).collect()),
+        gauges: GAUGES.with(|g| g.borrow().iter().map(|(k, v)| (k.clone(), *v)).collect()),
+    }
+}
+
+/// Replace the app counters and gauges with a saved snapshot
+pub fn restore_app_metrics(snapshot: AppMetricsSnapshot) {
+    COUNTERS.with(|c| *c.borrow_mut() = snapshot.counters.into_iter().collect());
+    GAUGES.with(|g| *g.borrow_mut() = snapshot.gauges.into_iter().collect());
+}
+
+/// Render app counters, gauges and span statistics in Prometheus text format
+pub fn prometheus_text() -> String {
+    let mut out = String::new();
+
+    COUNTERS.with(|c| {
+        for (name, value) in c.borrow().iter() {
+            let name = prometheus_name(name);
+            out.push_str(&format!("# TYPE {} counter\n{} {}\n", name, name, value));
+        }
+    });
+
+    GAUGES.with(|g| {
+        for (name, value) in g.borrow().iter() {
+            let name = prometheus_name(name);
+            out.push_str(&format!("# TYPE {} gauge\n{} {}\n", name, name, value));
+        }
+    });
+
+    SPANS.with(|s| {
+        let spans = s.borrow();
+        if spans.is_empty() {
+            return;
+        }
+
+        let mut labels: Vec<&String> = spans.keys().collect();
+        labels.sort();
+
+        out.push_str("# TYPE span_instructions summary\n");
+        for label in labels {
+            let stats = &spans[label];
+            let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+            out.push_str(&format!(
+                "span_instructions_sum{{label=\"{}\"}} {}\nspan_instructions_count{{label=\"{}\"}} {}\n",
+                label, stats.total, label, stats.count
+            ));
+        }
+    });
+
+    out
+}
+
+/// HTTP handler serving `prometheus_text()` for scraping
+///
+/// Responses served through the HTTP gateway are public - only mount this
+/// on a route you are happy to expose.
+///
+/// # Example
+/// ```rust,ignore
+/// router.get("/metrics", |req| Ok(telemetry::metrics_handler(req)));
+/// ```
+pub fn metrics_handler(req: HttpRequest) -> HttpResponse {
+    if !matches!(HttpMethod::from_str(&req.method), Some(HttpMethod::GET) | Some(HttpMethod::HEAD)) {
+        return HttpError::MethodNotAllowed.to_response();
+    }
+
+    HttpResponse {
+        status_code: 200,
+        headers: vec![(
+            "Content-Type".to_string(),
+            "text/plain; version=0.0.4".to_string(),
+        )],
+        body: prometheus_text().into_bytes(),
+        upgrade: None,
+    }
+}
+
+/// Replace characters that are not valid in Prometheus metric names
+fn prometheus_name(name: &str) -> String {
+    name.chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if c.is_ascii_alphabetic() || c == '_' || c == ':' || (i > 0 && c.is_ascii_digit()) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Public API - Error Rate
+// ═══════════════════════════════════════════════════════════════
+
+/// Record the outcome of an operation in the rolling error-rate window
+///
+/// Fires the alert callback once when the error rate reaches the configured
+/// threshold; the alert re-arms after the rate drops back below it.
+pub fn record_outcome(success: bool) {
+    let rate = OUTCOMES.with(|o| {
+        let mut outcomes = o.borrow_mut();
+        outcomes.push_back(success);
+        while outcomes.len() > ERROR_RATE_WINDOW {
+            outcomes.pop_front();
+        }
+        failure_ratio(&outcomes)
+    });
+
+    let trip = ERROR_RATE_ALERT.with(|a| {
+        let mut alert = a.borrow_mut();
+        let alert = alert.as_mut()?;
+        if rate >= alert.threshold {
+            if !alert.tripped {
+                alert.tripped = true;
+                return Some(alert.on_trip);
+            }
+        } else {
+            alert.tripped = false;
+        }
+        None
+    });
+
+    if let Some(on_trip) = trip {
+        on_trip(rate);
+    }
+}
+
+/// Fraction of failed outcomes in the current window (0.0 when empty)
+pub fn error_rate() -> f32 {
+    OUTCOMES.with(|o| failure_ratio(&o.borrow()))
+}
+
+/// Call `on_trip` with the current rate when the error rate reaches `threshold`
+pub fn set_error_rate_alert(threshold: f32, on_trip: fn(f32)) {
+    ERROR_RATE_ALERT.with(|a| {
+        *a.borrow_mut() = Some(ErrorRateAlert {
+            threshold,
+            on_trip,
+            tripped: false,
+        });
+    });
+}
+
+/// Remove the error-rate alert
+pub fn clear_error_rate_alert() {
+    ERROR_RATE_ALERT.with(|a| *a.borrow_mut() = None);
+}
+
+/// Make `log_error` automatically record a failed outcome
+pub fn set_record_logged_errors(enabled: bool) {
+    RECORD_LOGGED_ERRORS.with(|r| r.set(enabled));
+}
+
+/// Clear the rolling outcome window
+pub fn reset_error_rate() {
+    OUTCOMES.with(|o| o.borrow_mut().clear());
+}
+
+fn failure_ratio(outcomes: &VecDeque<bool>) -> f32 {
+    if outcomes.is_empty() {
+        return 0.0;
+    }
+    let failures = outcomes.iter().filter(|ok| !**ok).count();
+    failures as f32 / outcomes.len() as f32
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Public API - Profiling
+// ═══════════════════════════════════════════════════════════════
+
+/// Instruction statistics for a span label
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct SpanStats {
+    pub count: u64,
+    pub total: u64,
+    pub max: u64,
+    pub min: u64,
+}
+
+/// Run a closure and record the instructions it used under `label`
+///
+/// # Example
+/// ```rust,ignore
+/// let result = telemetry::span("tokenize", || tokenizer.encode(&prompt));
+/// let stats = telemetry::span_stats("tokenize");
+/// ```
+pub fn span<R>(label: &str, f: impl FnOnce() -> R) -> R {
+    let start = crate::env::performance_counter();
+    let result = f();
+    let used = crate::env::performance_counter() - start;
+
+    record_span(label, used);
+    log_debug(format!("Span '{}' used {} instructions", label, used));
+
+    result
+}
+
+/// Get recorded statistics for a span label
+pub fn span_stats(label: &str) -> Option<SpanStats> {
+    SPANS.with(|s| s.borrow().get(label).cloned())
+}
+
+/// Clear all recorded span statistics
+pub fn clear_span_stats() {
+    SPANS.with(|s| s.borrow_mut().clear());
+}
+
+fn record_span(label: &str, instructions: u64) {
+    SPANS.with(|s| {
+        let mut spans = s.borrow_mut();
+        match spans.get_mut(label) {
+            Some(stats) => {
+                stats.count += 1;
+                stats.total += instructions;
+                stats.max = stats.max.max(instructions);
+                stats.min = stats.min.min(instructions);
+            }
+            None => {
+                spans.insert(label.to_string(), SpanStats {
+                    count: 1,
+                    total: instructions,
+                    max: instructions,
+                    min: instructions,
+                });
+            }
+        }
+    });
+}
+
+/// RAII guard returned by `method_timer`
+///
+/// Records the instructions used between creation and drop.
+pub struct MethodTimer {
+    name: String,
+    start: u64,
+}
+
+impl Drop for MethodTimer {
+    fn drop(&mut self) {
+        let used = crate::env::performance_counter().saturating_sub(self.start);
+        record_method(&self.name, used);
+    }
+}
+
+/// Start timing a method; the cost is recorded when the guard is dropped
+///
+/// `performance_counter(0)` restarts after every `await`, so in async methods
+/// only the instructions since the last await are counted.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::update]
+/// fn process_batch(items: Vec<Item>) {
+///     let _timer = telemetry::method_timer("process_batch");
+///     // ...
+/// }
+/// ```
+pub fn method_timer(name: &str) -> MethodTimer {
+    MethodTimer {
+        name: name.to_string(),
+        start: crate::env::performance_counter(),
+    }
+}
+
+/// Lifetime statistics per timed method: (name, total instructions, call count)
+pub fn method_stats() -> Vec<(String, u64, u64)> {
+    METHOD_STATS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(name, (total, calls))| (name.clone(), *total, *calls))
+            .collect()
+    })
+}
+
+/// Clear all method timer statistics
+pub fn clear_method_stats() {
+    METHOD_STATS.with(|m| m.borrow_mut().clear());
+}
+
+fn record_method(name: &str, instructions: u64) {
+    METHOD_STATS.with(|m| {
+        let mut stats = m.borrow_mut();
+        let (total, calls) = stats.entry(name.to_string()).or_insert((0, 0));
+        *total = total.saturating_add(instructions);
+        *calls += 1;
+    });
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -273,8 +916,10 @@ pub fn get_canister_log(request: CanisterLogRequest) -> Option<CanisterLogRespon
 ///     let monitor = canistergeek_ic_rust::monitor::pre_upgrade_stable_data();
 ///     let logger = canistergeek_ic_rust::logger::pre_upgrade_stable_data();
 ///     let principals = ic_dev_kit_rs::telemetry::save_principals_to_bytes();
+///     let metrics = ic_dev_kit_rs::telemetry::app_metrics_snapshot();
+///     let log_level = ic_dev_kit_rs::telemetry::current_log_level();
 ///
-///     ic_cdk::storage::stable_save((monitor, logger, principals))
+///     ic_cdk::storage::stable_save((monitor, logger, principals, metrics, log_level))
 ///         .expect("Failed to save telemetry");
 /// }
 ///
@@ -282,20 +927,24 @@ pub fn get_canister_log(request: CanisterLogRequest) -> Option<CanisterLogRespon
 /// fn post_upgrade() {
 ///     use canistergeek_ic_rust::{monitor, logger};
 ///
-///     let (monitor_data, logger_data, principals_bytes): (
+///     let (monitor_data, logger_data, principals_bytes, metrics, log_level): (
 ///         monitor::PostUpgradeStableData,
 ///         logger::PostUpgradeStableData,
 ///         Vec<u8>,
+///         AppMetricsSnapshot,
+///         LogLevel,
 ///     ) = ic_cdk::storage::stable_restore().expect("Failed to restore");
 ///
 ///     let principals = candid::decode_args(&principals_bytes)
 ///         .ok()
 ///         .map(|(p,): (Vec<Principal>,)| p);
 ///
-///     ic_dev_kit_rs::telemetry::init_from_saved(
+///     ic_dev_kit_rs::telemetry::init_from_saved_v2(
 ///         Some(monitor_data),
 ///         Some(logger_data),
 ///         principals,
+///         Some(metrics),
+///         Some(log_level),
 ///     );
 /// }
 /// ```
@@ -306,6 +955,19 @@ pub fn save_principals_to_bytes() -> Vec<u8> {
     candid::encode_args((&principals,)).unwrap_or_default()
 }
 
+/// Save the current minimum log level to bytes
+pub fn save_log_level_to_bytes() -> Vec<u8> {
+    candid::encode_args((current_log_level(),)).unwrap_or_default()
+}
+
+/// Restore the minimum log level from bytes produced by `save_log_level_to_bytes`
+pub fn load_log_level_from_bytes(bytes: &[u8]) -> TelemetryResult<()> {
+    let (level,): (LogLevel,) = candid::decode_args(bytes)
+        .map_err(|e| TelemetryError::SerializationError(format!("{:?}", e)))?;
+    set_log_level(level);
+    Ok(())
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  IC CDK Exported Functions (Optional)
 // ═══════════════════════════════════════════════════════════════
@@ -328,6 +990,18 @@ pub fn get_canister_log_query(request: CanisterLogRequest) -> Option<CanisterLog
     get_canister_log(request)
 }
 
+/// Update to clear the canister log (controllers only)
+#[ic_cdk::update(guard = "require_controller")]
+pub fn clear_canister_log() {
+    clear_logs();
+}
+
+/// Query to get the number of visible log entries (guarded)
+#[ic_cdk::query(guard = "is_monitoring_authorized")]
+pub fn get_canister_log_count() -> usize {
+    log_count()
+}
+
 /// Update to add monitoring principal (requires controller or monitoring access)
 #[ic_cdk::update(guard = "is_monitoring_authorized")]
 pub fn authorize_monitoring(principal: Principal) {
@@ -350,6 +1024,44 @@ pub fn get_monitoring_principals() -> Vec<Principal> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_controller_access() {
+        use crate::testing;
+
+        let controller = Principal::from_slice(&[1]);
+        let monitor = Principal::from_slice(&[2]);
+        init_with_principals(vec![monitor]);
+        testing::set_controllers(vec![controller]);
+
+        testing::set_caller(controller);
+        assert!(require_controller().is_ok());
+        assert!(is_monitoring_authorized().is_ok());
+
+        testing::set_caller(monitor);
+        assert!(require_controller().is_err());
+        assert!(is_monitoring_authorized().is_ok());
+
+        testing::set_caller(Principal::from_slice(&[3]));
+        assert!(is_monitoring_authorized().is_err());
+        testing::reset();
+    }
+
+    #[test]
+    fn test_require_min_cycles() {
+        crate::testing::set_cycle_balance(1_000);
+
+        assert_eq!(cycles_balance(), 1_000);
+        assert!(require_min_cycles(1_000).is_ok());
+        assert!(require_min_cycles(1_001).is_err());
+
+        assert!(min_cycles_guard().is_ok());
+        set_min_cycles(5_000);
+        assert!(min_cycles_guard().is_err());
+
+        assert!(!check_low_cycles());
+        crate::testing::reset();
+    }
+
     #[test]
     fn test_monitoring_auth() {
         let auth = MonitoringAuth::new();
@@ -371,4 +1083,157 @@ mod tests {
         auth.remove_monitoring_principal(&test_principal).unwrap();
         assert!(!auth.is_monitoring_authorized(&test_principal));
     }
+
+    #[test]
+    fn test_log_level_persistence() {
+        assert_eq!(current_log_level(), LogLevel::Info);
+        assert!(LogLevel::Debug < LogLevel::Info);
+        assert!(LogLevel::Error > LogLevel::Warning);
+
+        set_log_level(LogLevel::Error);
+        let bytes = save_log_level_to_bytes();
+        set_log_level(LogLevel::Debug);
+
+        load_log_level_from_bytes(&bytes).unwrap();
+        assert_eq!(current_log_level(), LogLevel::Error);
+    }
+
+    #[test]
+    fn test_span_stats() {
+        clear_span_stats();
+        assert!(span_stats("work").is_none());
+
+        record_span("work", 100);
+        record_span("work", 300);
+        record_span("work", 200);
+
+        let stats = span_stats("work").unwrap();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.total, 600);
+        assert_eq!(stats.max, 300);
+        assert_eq!(stats.min, 100);
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        increment_counter("http_requests", 2);
+        increment_counter("http_requests", 3);
+        set_gauge("queue-depth", 1.5);
+
+        assert_eq!(counter_value("http_requests"), Some(5));
+        assert_eq!(gauge_value("queue-depth"), Some(1.5));
+
+        let text = prometheus_text();
+        assert!(text.contains("# TYPE http_requests counter\nhttp_requests 5\n"));
+        assert!(text.contains("# TYPE queue_depth gauge\nqueue_depth 1.5\n"));
+    }
+
+    #[test]
+    fn test_app_metrics_snapshot_roundtrip() {
+        increment_counter("uploads", 7);
+        set_gauge("load", 0.25);
+        let snapshot = app_metrics_snapshot();
+
+        restore_app_metrics(AppMetricsSnapshot::default());
+        assert_eq!(counter_value("uploads"), None);
+
+        let bytes = candid::encode_one(&snapshot).unwrap();
+        let decoded: AppMetricsSnapshot = candid::decode_one(&bytes).unwrap();
+        restore_app_metrics(decoded);
+        assert_eq!(counter_value("uploads"), Some(7));
+        assert_eq!(gauge_value("load"), Some(0.25));
+    }
+
+    #[test]
+    fn test_error_rate_alert() {
+        thread_local! {
+            static TRIPS: Cell<u32> = Cell::new(0);
+        }
+
+        reset_error_rate();
+        set_error_rate_alert(0.5, |_rate| TRIPS.with(|t| t.set(t.get() + 1)));
+
+        record_outcome(true);
+        record_outcome(false);
+        assert_eq!(error_rate(), 0.5);
+        assert_eq!(TRIPS.with(|t| t.get()), 1);
+
+        // Stays tripped while above threshold
+        record_outcome(false);
+        assert_eq!(TRIPS.with(|t| t.get()), 1);
+
+        // Re-arms once the rate drops below the threshold
+        for _ in 0..3 {
+            record_outcome(true);
+        }
+        record_outcome(false);
+        record_outcome(false);
+        assert_eq!(TRIPS.with(|t| t.get()), 2);
+
+        clear_error_rate_alert();
+    }
+
+    #[test]
+    fn test_method_stats() {
+        clear_method_stats();
+
+        record_method("upload", 100);
+        record_method("upload", 50);
+        record_method("generate", 1_000);
+
+        assert_eq!(
+            method_stats(),
+            vec![
+                ("generate".to_string(), 1_000, 1),
+                ("upload".to_string(), 150, 2),
+            ]
+        );
+
+        clear_method_stats();
+        assert!(method_stats().is_empty());
+    }
+
+    #[test]
+    fn test_retain_log_entries() {
+        let old = LogMessageData {
+            timeNanos: 10,
+            message: "[INFO] old".to_string(),
+        };
+        let new = LogMessageData {
+            timeNanos: 20,
+            message: "[ERROR] new".to_string(),
+        };
+
+        let mut response = CanisterLogResponse::messages(CanisterLogMessages {
+            data: vec![&old, &new],
+            lastAnalyzedMessageTimeNanos: None,
+        });
+        retain_log_entries(&mut response, |entry| entry.timeNanos > 10);
+
+        match response {
+            CanisterLogResponse::messages(messages) => {
+                assert_eq!(messages.data.len(), 1);
+                assert_eq!(messages.data[0].message, "[ERROR] new");
+            }
+            _ => panic!("expected messages"),
+        }
+    }
+
+    #[test]
+    fn test_log_level_from_message() {
+        assert_eq!(LogLevel::from_message("[ERROR] boom"), LogLevel::Error);
+        assert_eq!(LogLevel::from_message("[WARN] careful"), LogLevel::Warning);
+        assert_eq!(LogLevel::from_message("[DEBUG] detail"), LogLevel::Debug);
+        assert_eq!(
+            LogLevel::from_message(&format_structured(LogLevel::Warning, "x", &[])),
+            LogLevel::Warning
+        );
+        assert_eq!(LogLevel::from_message("plain message"), LogLevel::Info);
+    }
+
+    #[test]
+    fn test_format_structured() {
+        let line = format_structured(LogLevel::Info, "say \"hi\"", &[("user", "alice")]);
+        assert_eq!(line, r#"{"level":"info","msg":"say \"hi\"","user":"alice"}"#);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,190 @@
+//! Test-only mocks for the canister environment
+//!
+//! Outside a canister, `ic_cdk::api::msg_caller()` and `time()` panic, which
+//! makes guards hard to unit test. With the `testing` feature enabled, `auth`,
+//! `telemetry`, `intercanister` and the other modules read the caller, time,
+//! canister id, controllers, cycle balance and instruction counter from here
+//! when a value has been set. The same mocks are on in this crate's own unit
+//! tests.
+//!
+//! With `storage`, `TestRegistry` is an in-memory `StorageRegistry`.
+//!
+//! Only enable this feature for tests (e.g. under `[dev-dependencies]`); it
+//! must never be compiled into a deployed canister.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[test]
+//! fn admin_only() {
+//!     testing::set_caller(admin);
+//!     auth::init_with_principals(vec![admin]);
+//!     assert!(auth::is_authorized().is_ok());
+//!
+//!     testing::set_caller(stranger);
+//!     assert!(auth::is_authorized().is_err());
+//!     testing::reset();
+//! }
+//! ```
+
+#![cfg(any(test, feature = "testing"))]
+
+use candid::Principal;
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    static CALLER: Cell<Option<Principal>> = Cell::new(None);
+    static TIME: Cell<Option<u64>> = Cell::new(None);
+    static CANISTER_ID: Cell<Option<Principal>> = Cell::new(None);
+    static CONTROLLERS: RefCell<Option<Vec<Principal>>> = RefCell::new(None);
+    static CYCLE_BALANCE: Cell<Option<u128>> = Cell::new(None);
+    static PERFORMANCE_COUNTER: Cell<Option<u64>> = Cell::new(None);
+    static LOGS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Set the principal returned as the message caller
+pub fn set_caller(caller: Principal) {
+    CALLER.with(|c| c.set(Some(caller)));
+}
+
+/// Set the IC time (nanoseconds)
+pub fn set_time(now: u64) {
+    TIME.with(|t| t.set(Some(now)));
+}
+
+/// Move the mocked time forward, starting from 0 if unset
+pub fn advance_time(by_nanos: u64) {
+    TIME.with(|t| t.set(Some(t.get().unwrap_or(0).saturating_add(by_nanos))));
+}
+
+/// Set this canister's own principal
+pub fn set_canister_id(id: Principal) {
+    CANISTER_ID.with(|c| c.set(Some(id)));
+}
+
+/// Set the controllers of this canister
+pub fn set_controllers(controllers: Vec<Principal>) {
+    CONTROLLERS.with(|c| *c.borrow_mut() = Some(controllers));
+}
+
+/// Set the canister's cycle balance
+pub fn set_cycle_balance(balance: u128) {
+    CYCLE_BALANCE.with(|c| c.set(Some(balance)));
+}
+
+/// Set the instruction counter (`performance_counter(0)`)
+pub fn set_performance_counter(count: u64) {
+    PERFORMANCE_COUNTER.with(|c| c.set(Some(count)));
+}
+
+/// Move the mocked instruction counter forward, starting from 0 if unset
+pub fn advance_performance_counter(by: u64) {
+    PERFORMANCE_COUNTER.with(|c| c.set(Some(c.get().unwrap_or(0).saturating_add(by))));
+}
+
+/// Take the log lines written since the last call (or `reset`)
+///
+/// With the `testing` feature, log lines from `telemetry` and `http` are
+/// always captured here rather than written to the canister log.
+pub fn take_logs() -> Vec<String> {
+    LOGS.with(|l| std::mem::take(&mut *l.borrow_mut()))
+}
+
+/// Clear all mocks so calls fall through to `ic_cdk` again
+pub fn reset() {
+    CALLER.with(|c| c.set(None));
+    TIME.with(|t| t.set(None));
+    CANISTER_ID.with(|c| c.set(None));
+    CONTROLLERS.with(|c| *c.borrow_mut() = None);
+    CYCLE_BALANCE.with(|c| c.set(None));
+    PERFORMANCE_COUNTER.with(|c| c.set(None));
+    LOGS.with(|l| l.borrow_mut().clear());
+}
+
+pub(crate) fn mock_caller() -> Option<Principal> {
+    CALLER.with(|c| c.get())
+}
+
+pub(crate) fn mock_time() -> Option<u64> {
+    TIME.with(|t| t.get())
+}
+
+pub(crate) fn mock_canister_id() -> Option<Principal> {
+    CANISTER_ID.with(|c| c.get())
+}
+
+#[cfg_attr(not(feature = "telemetry"), allow(dead_code))]
+pub(crate) fn mock_controllers() -> Option<Vec<Principal>> {
+    CONTROLLERS.with(|c| c.borrow().clone())
+}
+
+pub(crate) fn mock_cycle_balance() -> Option<u128> {
+    CYCLE_BALANCE.with(|c| c.get())
+}
+
+pub(crate) fn mock_performance_counter() -> Option<u64> {
+    PERFORMANCE_COUNTER.with(|c| c.get())
+}
+
+pub(crate) fn capture_log(message: String) {
+    LOGS.with(|l| l.borrow_mut().push(message));
+}
+
+/// In-memory `StorageRegistry`, ordered by key like `StableBTreeMap`
+///
+/// # Example
+/// ```rust,ignore
+/// let registry = RefCell::new(testing::TestRegistry::default());
+/// storage::save_bytes(&registry, "key", vec![1, 2, 3]);
+/// ```
+#[cfg(feature = "storage")]
+#[derive(Default)]
+pub struct TestRegistry {
+    map: std::collections::BTreeMap<String, Vec<u8>>,
+}
+
+#[cfg(feature = "storage")]
+impl crate::storage::StorageRegistry for TestRegistry {
+    fn insert(&mut self, key: String, value: Vec<u8>) {
+        self.map.insert(key, value);
+    }
+
+    fn get(&self, key: &String) -> Option<Vec<u8>> {
+        self.map.get(key).cloned()
+    }
+
+    fn remove(&mut self, key: &String) -> Option<Vec<u8>> {
+        self.map.remove(key)
+    }
+
+    fn visit_entries(&self, f: &mut dyn FnMut(&String, &[u8])) {
+        for (key, value) in &self.map {
+            f(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env;
+
+    #[test]
+    fn test_mocked_environment() {
+        let alice = Principal::from_slice(&[1]);
+        let bob = Principal::from_slice(&[2]);
+
+        set_caller(alice);
+        set_time(100);
+        advance_time(50);
+        set_controllers(vec![bob]);
+
+        assert_eq!(env::caller(), alice);
+        assert_eq!(env::time(), 150);
+        assert!(env::is_controller(&bob));
+        assert!(!env::is_controller(&alice));
+
+        reset();
+        assert_eq!(mock_caller(), None);
+        assert_eq!(mock_time(), None);
+    }
+}
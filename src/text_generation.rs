@@ -7,6 +7,7 @@
 
 use candid::CandidType;
 use serde::Deserialize;
+use std::collections::HashMap;
 use crate::candle::CandleModel;
 
 // ═══════════════════════════════════════════════════════════════
@@ -35,6 +36,29 @@ pub trait AutoregressiveModel: CandleModel {
         config: &GenerationConfig,
     ) -> Result<String, String>;
 
+    /// Run the prompt through the model without generating any tokens
+    ///
+    /// Useful for warming up / priming the KV cache and for measuring prompt
+    /// processing cost. `init_generation` can be implemented as `prefill`
+    /// followed by sampling the first token.
+    ///
+    /// The default calls `init_generation` and then `reset`, so it leaves no
+    /// generated state (nor a primed cache); override it to keep the prompt.
+    ///
+    /// # Returns
+    /// * Number of prompt tokens processed
+    fn prefill(
+        &mut self,
+        prompt: String,
+        tokenizer: &dyn TokenizerHandle,
+        config: &GenerationConfig,
+    ) -> Result<usize, String> {
+        let prompt_tokens = tokenizer.encode(&prompt)?.len();
+        self.init_generation(prompt, tokenizer, config)?;
+        self.reset();
+        Ok(prompt_tokens)
+    }
+
     /// Generate the next token in the sequence
     ///
     /// # Returns
@@ -44,11 +68,43 @@ pub trait AutoregressiveModel: CandleModel {
         tokenizer: &dyn TokenizerHandle,
     ) -> Result<String, String>;
 
+    /// Generate the next token, knowing how much instruction budget is left
+    ///
+    /// Called by `generate_autoregressive` and the model server each step.
+    /// Override to reduce work near the IC's instruction limit (e.g. switch to
+    /// greedy sampling). The default ignores `context` and calls
+    /// `generate_next_token`, so existing models need no changes.
+    fn generate_next_token_with_context(
+        &mut self,
+        tokenizer: &dyn TokenizerHandle,
+        _context: &GenerationContext,
+    ) -> Result<String, String> {
+        self.generate_next_token(tokenizer)
+    }
+
     /// Check if generation is complete (EOS reached)
     fn is_generation_complete(&self) -> bool;
 
     /// Get current token count in generation
     fn generated_token_count(&self) -> usize;
+
+    /// Token ids generated so far, if the model tracks them
+    ///
+    /// When available, `generate_autoregressive` decodes these with
+    /// `TokenizerHandle::decode_skip_special` for the final text, so stop
+    /// tokens like `<|im_end|>` don't leak into the output.
+    fn generated_tokens(&self) -> Option<Vec<u32>> {
+        None
+    }
+}
+
+/// Per-step state passed to `AutoregressiveModel::generate_next_token_with_context`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GenerationContext {
+    /// Instructions left before generation stops with `StopReason::InstructionLimit`
+    pub remaining_instructions: u64,
+    /// Tokens generated so far in this run
+    pub tokens_so_far: usize,
 }
 
 /// Handle to a tokenizer
@@ -58,6 +114,21 @@ pub trait TokenizerHandle {
     fn encode(&self, text: &str) -> Result<Vec<u32>, String>;
     fn decode(&self, tokens: &[u32]) -> Result<String, String>;
     fn vocab_size(&self) -> usize;
+
+    /// Encode several texts (default: calls `encode` for each)
+    fn encode_batch(&self, texts: &[&str]) -> Result<Vec<Vec<u32>>, String> {
+        texts.iter().map(|text| self.encode(text)).collect()
+    }
+
+    /// Decode, dropping special tokens such as `<|im_end|>` (default: `decode`)
+    fn decode_skip_special(&self, tokens: &[u32]) -> Result<String, String> {
+        self.decode(tokens)
+    }
+
+    /// Decode several token lists (default: calls `decode` for each)
+    fn decode_batch(&self, token_lists: &[&[u32]]) -> Result<Vec<String>, String> {
+        token_lists.iter().map(|tokens| self.decode(tokens)).collect()
+    }
 }
 
 /// Generation configuration for autoregressive models
@@ -70,6 +141,10 @@ pub struct GenerationConfig {
     pub repeat_last_n: usize,
     pub seed: u64,
     pub max_tokens: usize,
+    /// Number of completions for `generate_autoregressive_n` (`None` = 1)
+    ///
+    /// Optional so records from clients that predate the field still decode.
+    pub num_completions: Option<usize>,
 }
 
 impl Default for GenerationConfig {
@@ -82,10 +157,226 @@ impl Default for GenerationConfig {
             repeat_last_n: 64,
             seed: 42,
             max_tokens: 100,
+            num_completions: None,
         }
     }
 }
 
+impl GenerationConfig {
+    /// Replace the fixed seed with one derived from the call context
+    ///
+    /// See `derive_seed`. For unpredictable seeds use `random_seed`.
+    pub fn with_auto_seed(mut self) -> Self {
+        self.seed = derive_seed();
+        self
+    }
+
+    /// Number of completions to generate (at least 1)
+    pub fn completions(&self) -> usize {
+        self.num_completions.unwrap_or(1).max(1)
+    }
+
+    /// Start a validated config from the defaults
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let config = GenerationConfig::builder()
+    ///     .temperature(0.2)
+    ///     .max_tokens(256)
+    ///     .build()?;
+    /// ```
+    pub fn builder() -> GenerationConfigBuilder {
+        GenerationConfigBuilder::new()
+    }
+
+    /// Read a config from query parameters (e.g. `?temperature=0.8&max_tokens=50`)
+    ///
+    /// Missing or unparsable values keep their `GenerationConfig::default()`
+    /// value; unknown keys (such as `prompt`) are ignored. The result is not
+    /// validated.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let params = http::extract_query_params(&req.url);
+    /// let config = GenerationConfig::from_query(&params);
+    /// ```
+    pub fn from_query(params: &HashMap<String, String>) -> GenerationConfig {
+        fn parse<T: std::str::FromStr>(
+            params: &HashMap<String, String>,
+            key: &str,
+            default: T,
+        ) -> T {
+            params.get(key).and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+
+        let defaults = GenerationConfig::default();
+        GenerationConfig {
+            temperature: parse(params, "temperature", defaults.temperature),
+            top_p: parse(params, "top_p", defaults.top_p),
+            top_k: params.get("top_k").and_then(|v| v.parse().ok()).or(defaults.top_k),
+            repeat_penalty: parse(params, "repeat_penalty", defaults.repeat_penalty),
+            repeat_last_n: parse(params, "repeat_last_n", defaults.repeat_last_n),
+            seed: parse(params, "seed", defaults.seed),
+            max_tokens: parse(params, "max_tokens", defaults.max_tokens),
+            num_completions: params
+                .get("num_completions")
+                .and_then(|v| v.parse().ok())
+                .or(defaults.num_completions),
+        }
+    }
+
+    /// Check that sampling parameters are in range
+    ///
+    /// Requires `temperature >= 0`, `0 < top_p <= 1`, `max_tokens > 0` and
+    /// `repeat_penalty >= 1.0`. NaN values are rejected.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.temperature.is_nan() || self.temperature < 0.0 {
+            return Err(format!("temperature must be >= 0, got {}", self.temperature));
+        }
+        if self.top_p.is_nan() || self.top_p <= 0.0 || self.top_p > 1.0 {
+            return Err(format!("top_p must be in (0, 1], got {}", self.top_p));
+        }
+        if self.max_tokens == 0 {
+            return Err("max_tokens must be > 0".to_string());
+        }
+        if self.repeat_penalty.is_nan() || self.repeat_penalty < 1.0 {
+            return Err(format!("repeat_penalty must be >= 1.0, got {}", self.repeat_penalty));
+        }
+        Ok(())
+    }
+}
+
+/// Builder for `GenerationConfig` that validates on `build`
+///
+/// Unset fields keep their `GenerationConfig::default()` values.
+#[derive(Clone, Debug, Default)]
+pub struct GenerationConfigBuilder {
+    config: GenerationConfig,
+}
+
+impl GenerationConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.config.temperature = temperature;
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f64) -> Self {
+        self.config.top_p = top_p;
+        self
+    }
+
+    pub fn top_k(mut self, top_k: u32) -> Self {
+        self.config.top_k = Some(top_k);
+        self
+    }
+
+    pub fn repeat_penalty(mut self, repeat_penalty: f32) -> Self {
+        self.config.repeat_penalty = repeat_penalty;
+        self
+    }
+
+    pub fn repeat_last_n(mut self, repeat_last_n: usize) -> Self {
+        self.config.repeat_last_n = repeat_last_n;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = seed;
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.config.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn num_completions(mut self, num_completions: usize) -> Self {
+        self.config.num_completions = Some(num_completions);
+        self
+    }
+
+    /// Validate and return the config (see `GenerationConfig::validate`)
+    pub fn build(self) -> Result<GenerationConfig, String> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+//  Seed Derivation
+// ═══════════════════════════════════════════════════════════════
+
+/// Derive a sampling seed from the current time and caller principal
+///
+/// Canisters have no OS randomness, so this is deterministic: the same caller
+/// at the same block time gets the same seed, and anyone can predict it.
+/// That is fine for varying outputs between calls; for true randomness use
+/// `random_seed`, which calls the management canister's `raw_rand`.
+pub fn derive_seed() -> u64 {
+    mix_seed(crate::env::time(), crate::env::caller().as_slice())
+}
+
+/// Get an unpredictable sampling seed from the management canister's `raw_rand`
+///
+/// Same as `random_seed`.
+///
+/// # Example
+/// ```rust,ignore
+/// #[ic_cdk::update]
+/// async fn generate(prompt: String) -> Result<GenerationResponse, String> {
+///     let config = GenerationConfig { seed: derive_seed_secure().await?, ..Default::default() };
+///     SERVER.with(|s| s.generate(prompt, &config))
+/// }
+/// ```
+pub async fn derive_seed_secure() -> Result<u64, String> {
+    random_seed().await
+}
+
+/// Get a cryptographically random seed from the management canister's `raw_rand`
+///
+/// The 32 random bytes are folded into a u64 with `fold_seed`. `raw_rand` is an
+/// inter-canister call, so this fails in query calls; use `derive_seed` there.
+#[allow(deprecated)]
+pub async fn random_seed() -> Result<u64, String> {
+    if !ic_cdk::api::in_replicated_execution() {
+        return Err(
+            "raw_rand is unavailable in query calls - call from an update or use derive_seed()"
+                .to_string(),
+        );
+    }
+
+    let (bytes,) = ic_cdk::api::management_canister::main::raw_rand()
+        .await
+        .map_err(|(code, msg)| format!("raw_rand failed: {:?} - {}", code, msg))?;
+    Ok(fold_seed(&bytes))
+}
+
+/// Fold random bytes into a u64 seed (XOR of little-endian 8-byte chunks)
+pub fn fold_seed(bytes: &[u8]) -> u64 {
+    bytes.chunks(8).fold(0u64, |seed, chunk| {
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        seed ^ u64::from_le_bytes(word)
+    })
+}
+
+fn mix_seed(time: u64, caller: &[u8]) -> u64 {
+    caller
+        .iter()
+        .fold(splitmix64(time), |seed, byte| splitmix64(seed ^ *byte as u64))
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Generic Autoregressive Generation Function
 // ═══════════════════════════════════════════════════════════════
@@ -97,6 +388,8 @@ impl Default for GenerationConfig {
 /// - Instruction limit monitoring (IC-specific)
 /// - Token limit enforcement
 /// - EOS detection
+/// - Special-token stripping (for models implementing `generated_tokens`)
+/// - Config validation (see `GenerationConfig::validate`)
 /// - Error handling
 ///
 /// # Example
@@ -114,56 +407,203 @@ pub fn generate_autoregressive<T: AutoregressiveModel>(
     tokenizer: &dyn TokenizerHandle,
     config: &GenerationConfig,
 ) -> Result<GenerationResponse, String> {
-    let start_instructions = ic_cdk::api::performance_counter(0);
+    config.validate()?;
+    generate_within_budget(model, prompt, tokenizer, config, INSTRUCTION_BUDGET)
+}
+
+/// Generate `config.completions()` candidate completions for one prompt
+///
+/// The model is reset between runs and completion `i` uses seed `config.seed + i`.
+/// The instruction budget is shared: each run gets an equal share of what is
+/// left, and runs that can't start are returned empty with
+/// `StopReason::InstructionLimit`.
+///
+/// # Example
+/// ```rust,ignore
+/// let config = GenerationConfig { num_completions: Some(3), ..Default::default() };
+/// let candidates = generate_autoregressive_n(&mut my_llm, prompt, &tokenizer, &config)?;
+/// ```
+pub fn generate_autoregressive_n<T: AutoregressiveModel>(
+    model: &mut T,
+    prompt: String,
+    tokenizer: &dyn TokenizerHandle,
+    config: &GenerationConfig,
+) -> Result<Vec<GenerationResponse>, String> {
+    config.validate()?;
+
+    let count = config.completions();
+    let start_instructions = crate::env::performance_counter();
+    let mut responses = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let used = crate::env::performance_counter() - start_instructions;
+        let remaining = INSTRUCTION_BUDGET.saturating_sub(used);
+
+        if remaining == 0 {
+            responses.push(GenerationResponse {
+                text: String::new(),
+                tokens_generated: 0,
+                instructions_used: 0,
+                prefill_instructions: 0,
+                decode_instructions: 0,
+                stopped_reason: StopReason::InstructionLimit,
+            });
+            continue;
+        }
+
+        if i > 0 {
+            model.reset();
+        }
+
+        let run_config = GenerationConfig {
+            seed: config.seed.wrapping_add(i as u64),
+            ..config.clone()
+        };
+        let budget = completion_budget(remaining, count - i);
+        responses.push(generate_within_budget(
+            model,
+            prompt.clone(),
+            tokenizer,
+            &run_config,
+            budget,
+        )?);
+    }
+
+    Ok(responses)
+}
+
+/// Instructions a single update call may spend on generation
+pub(crate) const INSTRUCTION_BUDGET: u64 = 30_000_000_000;
+
+/// Equal share of the remaining budget for each completion still to run
+fn completion_budget(remaining: u64, completions_left: usize) -> u64 {
+    remaining / completions_left.max(1) as u64
+}
+
+fn generate_within_budget<T: AutoregressiveModel>(
+    model: &mut T,
+    prompt: String,
+    tokenizer: &dyn TokenizerHandle,
+    config: &GenerationConfig,
+    instruction_budget: u64,
+) -> Result<GenerationResponse, String> {
+    let start_instructions = crate::env::performance_counter();
 
     // Initialize with prompt and generate first token
-    let first_token = model.init_generation(prompt, tokenizer, config)?;
-    let mut generated_text = first_token;
+    let mut generated_text = match model.init_generation(prompt, tokenizer, config) {
+        Ok(first_token) => first_token,
+        Err(e) if is_non_finite_logits(&e) => {
+            return non_finite_response(model, tokenizer, String::new(), start_instructions, None);
+        }
+        Err(e) => return Err(e),
+    };
+    let prefill_instructions = crate::env::performance_counter() - start_instructions;
 
     // Generate remaining tokens
     for _ in 1..config.max_tokens {
         // Check if we hit EOS
         if model.is_generation_complete() {
-            let instructions_used = ic_cdk::api::performance_counter(0) - start_instructions;
+            let instructions_used = crate::env::performance_counter() - start_instructions;
             return Ok(GenerationResponse {
-                text: generated_text,
+                text: final_text(model, tokenizer, generated_text)?,
                 tokens_generated: model.generated_token_count(),
                 instructions_used,
+                prefill_instructions,
+                decode_instructions: instructions_used - prefill_instructions,
                 stopped_reason: StopReason::EndOfSequence,
             });
         }
 
-        // Check instruction limit (30B for IC)
-        let instructions_so_far = ic_cdk::api::performance_counter(0) - start_instructions;
-        if instructions_so_far > 30_000_000_000 {
+        // Check instruction limit (30B for IC, shared by n-best completions)
+        let instructions_so_far = crate::env::performance_counter() - start_instructions;
+        if instructions_so_far > instruction_budget {
             return Ok(GenerationResponse {
-                text: generated_text,
+                text: final_text(model, tokenizer, generated_text)?,
                 tokens_generated: model.generated_token_count(),
                 instructions_used: instructions_so_far,
+                prefill_instructions,
+                decode_instructions: instructions_so_far - prefill_instructions,
                 stopped_reason: StopReason::InstructionLimit,
             });
         }
 
         // Generate next token
-        let token_text = model.generate_next_token(tokenizer)?;
-        generated_text.push_str(&token_text);
+        let context = GenerationContext {
+            remaining_instructions: instruction_budget - instructions_so_far,
+            tokens_so_far: model.generated_token_count(),
+        };
+        match model.generate_next_token_with_context(tokenizer, &context) {
+            Ok(token_text) => generated_text.push_str(&token_text),
+            Err(e) if is_non_finite_logits(&e) => {
+                return non_finite_response(
+                    model,
+                    tokenizer,
+                    generated_text,
+                    start_instructions,
+                    Some(prefill_instructions),
+                );
+            }
+            Err(e) => return Err(e),
+        }
     }
 
     // Hit max tokens
-    let instructions_used = ic_cdk::api::performance_counter(0) - start_instructions;
+    let instructions_used = crate::env::performance_counter() - start_instructions;
     Ok(GenerationResponse {
-        text: generated_text,
+        text: final_text(model, tokenizer, generated_text)?,
         tokens_generated: model.generated_token_count(),
         instructions_used,
+        prefill_instructions,
+        decode_instructions: instructions_used - prefill_instructions,
         stopped_reason: StopReason::MaxTokens,
     })
 }
 
+/// Stop cleanly with `StopReason::Error` after a model reported non-finite logits
+///
+/// `prefill_instructions` is `None` when prefill itself failed.
+fn non_finite_response<T: AutoregressiveModel>(
+    model: &T,
+    tokenizer: &dyn TokenizerHandle,
+    generated_text: String,
+    start_instructions: u64,
+    prefill_instructions: Option<u64>,
+) -> Result<GenerationResponse, String> {
+    let instructions_used = crate::env::performance_counter() - start_instructions;
+    let prefill_instructions = prefill_instructions.unwrap_or(instructions_used);
+
+    Ok(GenerationResponse {
+        text: final_text(model, tokenizer, generated_text)?,
+        tokens_generated: model.generated_token_count(),
+        instructions_used,
+        prefill_instructions,
+        decode_instructions: instructions_used - prefill_instructions,
+        stopped_reason: StopReason::Error(NON_FINITE_LOGITS.to_string()),
+    })
+}
+
+/// Re-decode the generated tokens without special tokens, if the model tracks them
+fn final_text<T: AutoregressiveModel>(
+    model: &T,
+    tokenizer: &dyn TokenizerHandle,
+    accumulated: String,
+) -> Result<String, String> {
+    match model.generated_tokens() {
+        Some(tokens) => tokenizer.decode_skip_special(&tokens),
+        None => Ok(accumulated),
+    }
+}
+
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct GenerationResponse {
     pub text: String,
     pub tokens_generated: usize,
     pub instructions_used: u64,
+    /// Instructions spent in `init_generation` (tokenizing and processing the
+    /// prompt, plus the first token)
+    pub prefill_instructions: u64,
+    /// Instructions spent generating the remaining tokens
+    pub decode_instructions: u64,
     pub stopped_reason: StopReason,
 }
 
@@ -179,6 +619,65 @@ pub enum StopReason {
     Error(String),
 }
 
+// ═══════════════════════════════════════════════════════════════
+//  Sampling
+// ═══════════════════════════════════════════════════════════════
+
+/// Error returned by `sample_token` when the logits contain NaN or infinity
+///
+/// `generate_autoregressive` (and `generate_autoregressive_n`) turn this
+/// error from `init_generation` or `generate_next_token` into
+/// `StopReason::Error("non-finite logits")`; `ModelServer` streams end with
+/// it in `GenerationStatus::error`.
+pub const NON_FINITE_LOGITS: &str = "non-finite logits";
+
+/// Sample the next token, rejecting NaN/infinite logits
+///
+/// Use this in `AutoregressiveModel` implementations instead of calling
+/// `LogitsProcessor::sample` directly, so bad weights or overflow end
+/// generation cleanly instead of trapping or sampling garbage.
+///
+/// # Example
+/// ```rust,ignore
+/// fn generate_next_token(&mut self, tokenizer: &dyn TokenizerHandle) -> Result<String, String> {
+///     let logits = self.forward(self.last_token)?;
+///     let token = sample_token(&logits, &mut self.logits_processor)?;
+///     // ...
+/// }
+/// ```
+pub fn sample_token(
+    logits: &candle_core::Tensor,
+    processor: &mut candle_transformers::generation::LogitsProcessor,
+) -> Result<u32, String> {
+    let values = logits
+        .flatten_all()
+        .and_then(|l| l.to_dtype(candle_core::DType::F32))
+        .and_then(|l| l.to_vec1::<f32>())
+        .map_err(|e| format!("Failed to read logits: {}", e))?;
+    check_finite(&values)?;
+
+    processor
+        .sample(logits)
+        .map_err(|e| format!("Sampling failed: {}", e))
+}
+
+/// Whether a model error is `NON_FINITE_LOGITS`
+///
+/// Model methods return `String` errors, so this is how generation loops
+/// tell a clean stop from a real failure.
+pub fn is_non_finite_logits(error: &str) -> bool {
+    error == NON_FINITE_LOGITS
+}
+
+/// Check that every logit is finite, failing with `NON_FINITE_LOGITS`
+pub fn check_finite(logits: &[f32]) -> Result<(), String> {
+    if logits.iter().all(|l| l.is_finite()) {
+        Ok(())
+    } else {
+        Err(NON_FINITE_LOGITS.to_string())
+    }
+}
+
 // ═══════════════════════════════════════════════════════════════
 //  Utility Functions
 // ═══════════════════════════════════════════════════════════════
@@ -205,6 +704,113 @@ pub fn format_generation_stats(response: &GenerationResponse) -> String {
 pub mod tokenizers {
     use tokenizers::Tokenizer;
 
+    use super::TokenizerHandle;
+
+    /// `TokenizerHandle` backed by a HuggingFace tokenizer
+    pub struct TokenizerFromHf {
+        tokenizer: Tokenizer,
+    }
+
+    impl TokenizerFromHf {
+        /// Access the underlying HuggingFace tokenizer
+        pub fn inner(&self) -> &Tokenizer {
+            &self.tokenizer
+        }
+
+        /// EOS token id (see `find_eos_token`)
+        pub fn eos_token(&self) -> u32 {
+            find_eos_token(&self.tokenizer)
+        }
+    }
+
+    impl TokenizerHandle for TokenizerFromHf {
+        fn encode(&self, text: &str) -> Result<Vec<u32>, String> {
+            self.tokenizer
+                .encode(text, false)
+                .map(|encoding| encoding.get_ids().to_vec())
+                .map_err(|e| format!("Failed to encode: {}", e))
+        }
+
+        fn decode(&self, tokens: &[u32]) -> Result<String, String> {
+            self.tokenizer
+                .decode(tokens, false)
+                .map_err(|e| format!("Failed to decode: {}", e))
+        }
+
+        fn vocab_size(&self) -> usize {
+            self.tokenizer.get_vocab_size(true)
+        }
+
+        fn decode_skip_special(&self, tokens: &[u32]) -> Result<String, String> {
+            self.tokenizer
+                .decode(tokens, true)
+                .map_err(|e| format!("Failed to decode: {}", e))
+        }
+
+        fn encode_batch(&self, texts: &[&str]) -> Result<Vec<Vec<u32>>, String> {
+            self.tokenizer
+                .encode_batch(texts.to_vec(), false)
+                .map(|encodings| encodings.iter().map(|e| e.get_ids().to_vec()).collect())
+                .map_err(|e| format!("Failed to encode: {}", e))
+        }
+
+        fn decode_batch(&self, token_lists: &[&[u32]]) -> Result<Vec<String>, String> {
+            self.tokenizer
+                .decode_batch(token_lists, false)
+                .map_err(|e| format!("Failed to decode: {}", e))
+        }
+    }
+
+    /// Load a tokenizer from `tokenizer.json` bytes
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let model = MyModel::load(weights, Some(tokenizer_bytes.clone()))?;
+    /// let tokenizer = tokenizers::from_bytes(&tokenizer_bytes)?;
+    /// let response = generate_autoregressive(&mut model, prompt, &tokenizer, &config)?;
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<TokenizerFromHf, String> {
+        Tokenizer::from_bytes(bytes)
+            .map(|tokenizer| TokenizerFromHf { tokenizer })
+            .map_err(|e| format!("Failed to load tokenizer: {}", e))
+    }
+
+    /// Byte-level `TokenizerHandle` for models without a tokenizer file
+    ///
+    /// Each UTF-8 byte is one token (vocab size 256). Decoding replaces
+    /// invalid UTF-8 with U+FFFD; ids above 255 are an error. Meant for
+    /// exercising the generation loop during development.
+    pub struct ByteTokenizer;
+
+    impl TokenizerHandle for ByteTokenizer {
+        fn encode(&self, text: &str) -> Result<Vec<u32>, String> {
+            Ok(text.bytes().map(u32::from).collect())
+        }
+
+        fn decode(&self, tokens: &[u32]) -> Result<String, String> {
+            let bytes = tokens
+                .iter()
+                .map(|t| u8::try_from(*t).map_err(|_| format!("Token {} is not a byte", t)))
+                .collect::<Result<Vec<u8>, String>>()?;
+            Ok(String::from_utf8_lossy(&bytes).into_owned())
+        }
+
+        fn vocab_size(&self) -> usize {
+            256
+        }
+    }
+
+    /// Byte-level fallback tokenizer (see `ByteTokenizer`)
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let tokenizer = tokenizers::byte_level();
+    /// let response = generate_autoregressive(&mut model, prompt, &tokenizer, &config)?;
+    /// ```
+    pub fn byte_level() -> ByteTokenizer {
+        ByteTokenizer
+    }
+
     /// Find EOS token from common names
     pub fn find_eos_token(tokenizer: &Tokenizer) -> u32 {
         let vocab = tokenizer.get_vocab(true);
@@ -227,5 +833,282 @@ mod tests {
         let config = GenerationConfig::default();
         assert_eq!(config.temperature, 0.7);
         assert_eq!(config.max_tokens, 100);
+        assert_eq!(config.completions(), 1);
+    }
+
+    #[test]
+    fn test_generation_config_decodes_old_record() {
+        // Shape sent by clients built before `num_completions` existed
+        #[derive(CandidType)]
+        struct OldConfig {
+            temperature: f64,
+            top_p: f64,
+            top_k: Option<u32>,
+            repeat_penalty: f32,
+            repeat_last_n: usize,
+            seed: u64,
+            max_tokens: usize,
+        }
+
+        let old = OldConfig {
+            temperature: 0.2,
+            top_p: 0.5,
+            top_k: Some(10),
+            repeat_penalty: 1.3,
+            repeat_last_n: 32,
+            seed: 7,
+            max_tokens: 12,
+        };
+
+        let bytes = candid::Encode!(&Some(old)).unwrap();
+        let config = candid::Decode!(&bytes, Option<GenerationConfig>).unwrap().unwrap();
+        assert_eq!(config.temperature, 0.2);
+        assert_eq!(config.max_tokens, 12);
+        assert_eq!(config.num_completions, None);
+        assert_eq!(config.completions(), 1);
+    }
+
+    #[test]
+    fn test_generation_config_builder() {
+        let config = GenerationConfig::builder()
+            .temperature(0.0)
+            .top_p(1.0)
+            .top_k(40)
+            .max_tokens(8)
+            .build()
+            .unwrap();
+        assert_eq!(config.temperature, 0.0);
+        assert_eq!(config.top_k, Some(40));
+        assert_eq!(config.max_tokens, 8);
+        assert_eq!(config.seed, GenerationConfig::default().seed);
+    }
+
+    #[test]
+    fn test_generation_config_builder_rejects_invalid() {
+        assert!(GenerationConfig::builder().temperature(-0.1).build().is_err());
+        assert!(GenerationConfig::builder().temperature(f64::NAN).build().is_err());
+        assert!(GenerationConfig::builder().top_p(0.0).build().is_err());
+        assert!(GenerationConfig::builder().top_p(1.5).build().is_err());
+        assert!(GenerationConfig::builder().max_tokens(0).build().is_err());
+        assert!(GenerationConfig::builder().repeat_penalty(0.9).build().is_err());
+    }
+
+    #[test]
+    fn test_generation_config_from_query() {
+        let params = crate::http::extract_query_params(
+            "/generate?prompt=hi&temperature=0.8&max_tokens=50&top_k=20&seed=oops",
+        );
+        let config = GenerationConfig::from_query(&params);
+        assert_eq!(config.temperature, 0.8);
+        assert_eq!(config.max_tokens, 50);
+        assert_eq!(config.top_k, Some(20));
+        assert_eq!(config.seed, GenerationConfig::default().seed);
+        assert_eq!(config.top_p, GenerationConfig::default().top_p);
+    }
+
+    #[test]
+    fn test_check_finite() {
+        assert!(check_finite(&[0.0, -3.5, 12.0]).is_ok());
+        assert_eq!(check_finite(&[0.0, f32::NAN]), Err(NON_FINITE_LOGITS.to_string()));
+        assert!(is_non_finite_logits(&check_finite(&[f32::INFINITY]).unwrap_err()));
+        assert!(!is_non_finite_logits("Failed to read logits"));
+        assert!(check_finite(&[f32::INFINITY]).is_err());
+        assert!(check_finite(&[f32::NEG_INFINITY]).is_err());
+    }
+
+    #[test]
+    fn test_completion_budget() {
+        assert_eq!(completion_budget(30, 3), 10);
+        assert_eq!(completion_budget(30, 1), 30);
+        assert_eq!(completion_budget(30, 0), 30);
+    }
+
+    const TEST_TOKENIZER_JSON: &str = r#"{
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [
+            {"id": 3, "content": "<|im_end|>", "single_word": false, "lstrip": false,
+             "rstrip": false, "normalized": false, "special": true}
+        ],
+        "normalizer": null,
+        "pre_tokenizer": {"type": "Whitespace"},
+        "post_processor": null,
+        "decoder": null,
+        "model": {
+            "type": "WordLevel",
+            "vocab": {"hello": 0, "world": 1, "[UNK]": 2, "<|im_end|>": 3},
+            "unk_token": "[UNK]"
+        }
+    }"#;
+
+    #[test]
+    fn test_tokenizer_from_bytes() {
+        let tokenizer = tokenizers::from_bytes(TEST_TOKENIZER_JSON.as_bytes()).unwrap();
+
+        assert_eq!(tokenizer.encode("hello world").unwrap(), vec![0, 1]);
+        assert_eq!(tokenizer.decode(&[0, 1]).unwrap(), "hello world");
+        assert_eq!(tokenizer.vocab_size(), 4);
+        assert_eq!(tokenizer.eos_token(), 3);
+
+        assert!(tokenizers::from_bytes(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_byte_level_tokenizer() {
+        let tokenizer = tokenizers::byte_level();
+
+        let tokens = tokenizer.encode("hé").unwrap();
+        assert_eq!(tokens, vec![104, 0xc3, 0xa9]);
+        assert_eq!(tokenizer.decode(&tokens).unwrap(), "hé");
+        assert_eq!(tokenizer.decode(&tokens[..2]).unwrap(), "h\u{fffd}");
+        assert!(tokenizer.decode(&[256]).is_err());
+        assert_eq!(tokenizer.vocab_size(), 256);
+    }
+
+    #[test]
+    fn test_tokenizer_batch() {
+        struct CharTokenizer;
+
+        impl TokenizerHandle for CharTokenizer {
+            fn encode(&self, text: &str) -> Result<Vec<u32>, String> {
+                Ok(text.chars().map(|c| c as u32).collect())
+            }
+
+            fn decode(&self, tokens: &[u32]) -> Result<String, String> {
+                tokens
+                    .iter()
+                    .map(|t| char::from_u32(*t).ok_or("invalid token".to_string()))
+                    .collect()
+            }
+
+            fn vocab_size(&self) -> usize {
+                0x110000
+            }
+        }
+
+        let hf = tokenizers::from_bytes(TEST_TOKENIZER_JSON.as_bytes()).unwrap();
+        let encoded = hf.encode_batch(&["hello world", "world"]).unwrap();
+        assert_eq!(encoded, vec![vec![0, 1], vec![1]]);
+        assert_eq!(
+            hf.decode_batch(&[&[1, 0], &[0]]).unwrap(),
+            vec!["world hello".to_string(), "hello".to_string()]
+        );
+
+        // Default implementations loop over encode/decode
+        let encoded = CharTokenizer.encode_batch(&["ab", "c"]).unwrap();
+        assert_eq!(encoded, vec![vec![97, 98], vec![99]]);
+        assert_eq!(
+            CharTokenizer.decode_batch(&[&encoded[0], &encoded[1]]).unwrap(),
+            vec!["ab".to_string(), "c".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_decode_skip_special() {
+        let tokenizer = tokenizers::from_bytes(TEST_TOKENIZER_JSON.as_bytes()).unwrap();
+
+        assert_eq!(tokenizer.decode(&[0, 1, 3]).unwrap(), "hello world <|im_end|>");
+        assert_eq!(tokenizer.decode_skip_special(&[0, 1, 3]).unwrap(), "hello world");
+    }
+
+    #[test]
+    fn test_final_text_skips_special_tokens() {
+        /// Emits a fixed token sequence
+        struct Replay {
+            tokens: Vec<u32>,
+            position: usize,
+            tracks_ids: bool,
+        }
+
+        impl CandleModel for Replay {
+            fn load(_weights: Vec<u8>, _config: Option<Vec<u8>>) -> Result<Self, String> {
+                Ok(Replay {
+                    tokens: Vec::new(),
+                    position: 0,
+                    tracks_ids: false,
+                })
+            }
+
+            fn metadata(&self) -> crate::candle::ModelMetadata {
+                crate::candle::ModelMetadata {
+                    name: "replay".to_string(),
+                    version: "1".to_string(),
+                    architecture: "replay".to_string(),
+                    parameters: 0,
+                    context_length: None,
+                }
+            }
+
+            fn reset(&mut self) {
+                self.position = 0;
+            }
+        }
+
+        impl AutoregressiveModel for Replay {
+            fn init_generation(
+                &mut self,
+                _prompt: String,
+                tokenizer: &dyn TokenizerHandle,
+                _config: &GenerationConfig,
+            ) -> Result<String, String> {
+                self.position = 0;
+                self.generate_next_token(tokenizer)
+            }
+
+            fn generate_next_token(
+                &mut self,
+                tokenizer: &dyn TokenizerHandle,
+            ) -> Result<String, String> {
+                let token = *self.tokens.get(self.position).ok_or("no more tokens")?;
+                self.position += 1;
+                tokenizer.decode(&[token])
+            }
+
+            fn is_generation_complete(&self) -> bool {
+                self.position >= self.tokens.len()
+            }
+
+            fn generated_token_count(&self) -> usize {
+                self.position
+            }
+
+            fn generated_tokens(&self) -> Option<Vec<u32>> {
+                self.tracks_ids.then(|| self.tokens[..self.position].to_vec())
+            }
+        }
+
+        let tokenizer = tokenizers::from_bytes(TEST_TOKENIZER_JSON.as_bytes()).unwrap();
+        let run = |tracks_ids: bool| {
+            let mut model = Replay {
+                tokens: vec![0, 1, 3],
+                position: 0,
+                tracks_ids,
+            };
+            let config = GenerationConfig::default();
+            let mut text = model.init_generation(String::new(), &tokenizer, &config).unwrap();
+            while !model.is_generation_complete() {
+                text.push_str(&model.generate_next_token(&tokenizer).unwrap());
+            }
+            final_text(&model, &tokenizer, text).unwrap()
+        };
+
+        // Token ids are re-decoded without special tokens
+        assert_eq!(run(true), "hello world");
+        // Without them the streamed text is returned unchanged
+        assert_eq!(run(false), "helloworld<|im_end|>");
+    }
+
+    #[test]
+    fn test_seed_derivation() {
+        assert_eq!(mix_seed(1, &[1, 2, 3]), mix_seed(1, &[1, 2, 3]));
+        assert_ne!(mix_seed(1, &[1, 2, 3]), mix_seed(2, &[1, 2, 3]));
+        assert_ne!(mix_seed(1, &[1, 2, 3]), mix_seed(1, &[1, 2, 4]));
+
+        let mut bytes = [0u8; 32];
+        bytes[0] = 1;
+        bytes[8] = 2;
+        assert_eq!(fold_seed(&bytes), 3);
+        assert_eq!(fold_seed(&[0xff]), 0xff);
     }
 }
\ No newline at end of file